@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+/// a function in a `Bdd`. `FALSE` and `TRUE` are the two leaves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BddNode(usize);
+impl BddNode {
+    pub const FALSE: Self = Self(0);
+    pub const TRUE: Self = Self(1);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum BddOperation {
+    And,
+    Or,
+    Xor,
+}
+
+/// a reduced ordered binary decision diagram, where variables are tested in
+/// order of their number. nodes are shared between every function built in
+/// the same `Bdd`, so two functions are equal exactly when their nodes are.
+/// every operation gives `None` once there would be more than `max_nodes` nodes
+#[derive(Debug, Clone)]
+pub struct Bdd {
+    /// the variable, low child and high child of every node.
+    /// the leaves have a variable of `usize::MAX` so they sort after every real one
+    nodes: Vec<(usize, BddNode, BddNode)>,
+    unique: HashMap<(usize, BddNode, BddNode), BddNode>,
+    cache: HashMap<(BddOperation, BddNode, BddNode), BddNode>,
+    max_nodes: usize,
+}
+impl Bdd {
+    pub fn new(max_nodes: usize) -> Self {
+        Self {
+            nodes: vec![
+                (usize::MAX, BddNode::FALSE, BddNode::FALSE),
+                (usize::MAX, BddNode::TRUE, BddNode::TRUE),
+            ],
+            unique: HashMap::new(),
+            cache: HashMap::new(),
+            max_nodes,
+        }
+    }
+
+    pub fn constant(value: bool) -> BddNode {
+        if value { BddNode::TRUE } else { BddNode::FALSE }
+    }
+
+    pub fn variable(&mut self, variable: usize) -> Option<BddNode> {
+        self.node(variable, BddNode::FALSE, BddNode::TRUE)
+    }
+
+    fn node(&mut self, variable: usize, low: BddNode, high: BddNode) -> Option<BddNode> {
+        if low == high {
+            return Some(low);
+        }
+        if let Some(node) = self.unique.get(&(variable, low, high)) {
+            return Some(*node);
+        }
+        if self.nodes.len() >= self.max_nodes {
+            return None;
+        }
+        let node = BddNode(self.nodes.len());
+        self.nodes.push((variable, low, high));
+        self.unique.insert((variable, low, high), node);
+        Some(node)
+    }
+
+    pub fn not(&mut self, a: BddNode) -> Option<BddNode> {
+        self.xor(a, BddNode::TRUE)
+    }
+
+    pub fn and(&mut self, a: BddNode, b: BddNode) -> Option<BddNode> {
+        self.apply(BddOperation::And, a, b)
+    }
+
+    pub fn or(&mut self, a: BddNode, b: BddNode) -> Option<BddNode> {
+        self.apply(BddOperation::Or, a, b)
+    }
+
+    pub fn xor(&mut self, a: BddNode, b: BddNode) -> Option<BddNode> {
+        self.apply(BddOperation::Xor, a, b)
+    }
+
+    /// `then` where `condition` is true, and `otherwise` everywhere else
+    pub fn select(
+        &mut self,
+        condition: BddNode,
+        then: BddNode,
+        otherwise: BddNode,
+    ) -> Option<BddNode> {
+        let then = self.and(condition, then)?;
+        let not_condition = self.not(condition)?;
+        let otherwise = self.and(not_condition, otherwise)?;
+        self.or(then, otherwise)
+    }
+
+    fn apply(&mut self, operation: BddOperation, a: BddNode, b: BddNode) -> Option<BddNode> {
+        let (a, b) = (a.min(b), a.max(b));
+        let (f, t) = (BddNode::FALSE, BddNode::TRUE);
+        match operation {
+            BddOperation::And if a == f => return Some(f),
+            BddOperation::And if a == t || a == b => return Some(b),
+            BddOperation::Or if a == t => return Some(t),
+            BddOperation::Or if a == f || a == b => return Some(b),
+            BddOperation::Xor if a == b => return Some(f),
+            BddOperation::Xor if a == f => return Some(b),
+            _ => {}
+        }
+        if let Some(node) = self.cache.get(&(operation, a, b)) {
+            return Some(*node);
+        }
+        let (a_variable, a_low, a_high) = self.nodes[a.0];
+        let (b_variable, b_low, b_high) = self.nodes[b.0];
+        let variable = a_variable.min(b_variable);
+        // a function which doesn't test the variable is the same either way
+        let (a_low, a_high) = if a_variable == variable {
+            (a_low, a_high)
+        } else {
+            (a, a)
+        };
+        let (b_low, b_high) = if b_variable == variable {
+            (b_low, b_high)
+        } else {
+            (b, b)
+        };
+        let low = self.apply(operation, a_low, b_low)?;
+        let high = self.apply(operation, a_high, b_high)?;
+        let node = self.node(variable, low, high)?;
+        self.cache.insert((operation, a, b), node);
+        Some(node)
+    }
+
+    /// values for some of the variables which make `node` true, with every
+    /// variable that isn't given able to be anything. `None` if it's never true
+    pub fn satisfy(&self, mut node: BddNode) -> Option<Vec<(usize, bool)>> {
+        if node == BddNode::FALSE {
+            return None;
+        }
+        let mut assignment = vec![];
+        while node != BddNode::TRUE {
+            let (variable, low, high) = self.nodes[node.0];
+            let value = low == BddNode::FALSE;
+            assignment.push((variable, value));
+            node = if value { high } else { low };
+        }
+        Some(assignment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_functions_share_a_node() {
+        let mut bdd = Bdd::new(1000);
+        let a = bdd.variable(0).unwrap();
+        let b = bdd.variable(1).unwrap();
+        assert_eq!(bdd.and(a, b), bdd.and(b, a));
+        let not_a = bdd.not(a).unwrap();
+        assert_eq!(bdd.and(a, not_a), Some(BddNode::FALSE));
+        assert_eq!(bdd.or(a, not_a), Some(BddNode::TRUE));
+        assert_eq!(bdd.xor(a, a), Some(BddNode::FALSE));
+        assert_eq!(bdd.not(not_a), Some(a));
+
+        // !(a & b) == !a | !b
+        let a_and_b = bdd.and(a, b).unwrap();
+        let nand = bdd.not(a_and_b).unwrap();
+        let not_b = bdd.not(b).unwrap();
+        assert_eq!(bdd.or(not_a, not_b), Some(nand));
+        assert_eq!(bdd.select(a, b, BddNode::FALSE), Some(a_and_b));
+        assert_eq!(bdd.select(a, BddNode::TRUE, BddNode::FALSE), Some(a));
+    }
+
+    #[test]
+    fn satisfy_finds_values_which_make_a_function_true() {
+        let mut bdd = Bdd::new(1000);
+        let a = bdd.variable(0).unwrap();
+        let b = bdd.variable(1).unwrap();
+        let c = bdd.variable(2).unwrap();
+        let not_b = bdd.not(b).unwrap();
+        let a_and_not_b = bdd.and(a, not_b).unwrap();
+        let function = bdd.and(a_and_not_b, c).unwrap();
+        let mut assignment = bdd.satisfy(function).unwrap();
+        assignment.sort();
+        assert_eq!(assignment, [(0, true), (1, false), (2, true)]);
+
+        // variables which don't matter are left out
+        let function = bdd.or(a, c).unwrap();
+        let assignment = bdd.satisfy(function).unwrap();
+        assert!(assignment.contains(&(0, true)) || assignment.contains(&(2, true)));
+        assert!(!assignment.iter().any(|(variable, _)| *variable == 1));
+
+        assert_eq!(bdd.satisfy(BddNode::FALSE), None);
+        assert_eq!(bdd.satisfy(BddNode::TRUE), Some(vec![]));
+    }
+
+    #[test]
+    fn gives_up_after_max_nodes() {
+        // the two leaves count towards the limit
+        let mut bdd = Bdd::new(3);
+        let a = bdd.variable(0).unwrap();
+        assert_eq!(bdd.variable(0), Some(a));
+        assert_eq!(bdd.variable(1), None);
+    }
+}
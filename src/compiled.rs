@@ -0,0 +1,1362 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::{
+    id::Id,
+    logic::Logic,
+    logic_gate::{Clock, Connection, ConnectionPoint, LogicGate, Memory, PrimitiveKind},
+    logic_gate_map::LogicGateMap,
+    path::PathError,
+    waveform::WaveformRecorder,
+};
+
+/// where a compiled signal lives in the hierarchical map it was built from.
+/// `path` is the list of custom gate ids to walk through from the top-level map,
+/// and `point` is the signal inside the innermost map
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SignalOrigin {
+    pub path: Vec<Id>,
+    pub point: ConnectionPoint,
+}
+
+impl Display for SignalOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for gate in &self.path {
+            write!(f, "{gate}/")?;
+        }
+        match self.point {
+            ConnectionPoint::Input(id) => write!(f, "input {id}"),
+            ConnectionPoint::Output(id) => write!(f, "output {id}"),
+            ConnectionPoint::MiddleSignal(id) => write!(f, "signal {id}"),
+            ConnectionPoint::GateInput { gate, input } => write!(f, "{gate} in {input}"),
+            ConnectionPoint::GateOutput { gate, output } => write!(f, "{gate} out {output}"),
+        }
+    }
+}
+
+/// the result of `settle` on a circuit which never stopped changing
+#[derive(Debug, Clone)]
+pub struct Oscillation {
+    /// how many steps it takes to get back to the same state,
+    /// or `None` if it didn't happen within `max_steps`
+    pub cycle_length: Option<usize>,
+    pub toggling: Vec<SignalOrigin>,
+}
+impl Oscillation {
+    /// names the toggling signals by their paths in `map`, which should be
+    /// the map the oscillating `CompiledMap` was built from
+    pub fn describe(&self, map: &LogicGateMap) -> String {
+        let mut description = match self.cycle_length {
+            Some(length) => format!("oscillating with a cycle of {length} steps:"),
+            None => "still changing with no repeating cycle found:".to_string(),
+        };
+        for origin in &self.toggling {
+            description.push(' ');
+            description.push_str(&map.origin_name(origin));
+        }
+        description
+    }
+}
+
+/// a bus contention found by `contentions`
+#[derive(Debug, Clone)]
+pub struct Contention {
+    pub point: SignalOrigin,
+    /// every driver which is driving the point, with the value it's driving
+    pub drivers: Vec<(SignalOrigin, Logic)>,
+}
+impl Contention {
+    /// names the point and its drivers by their paths in `map`, like `Oscillation::describe`
+    pub fn describe(&self, map: &LogicGateMap) -> String {
+        let mut description = format!("contention on {}:", map.origin_name(&self.point));
+        for (driver, value) in &self.drivers {
+            description.push_str(&format!(" {} = {value}", map.origin_name(driver)));
+        }
+        description
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Nand,
+    /// the sources are the input and then the enable
+    TriState,
+    /// combines every connection driving a point
+    Resolve,
+    Primitive(PrimitiveKind),
+    /// has no sources, and goes off of the clock tick instead
+    Clock(Clock),
+    /// one bit of the word a memory is outputting, with the address as the sources
+    MemoryRead {
+        memory: usize,
+        bit: usize,
+    },
+    /// has no sources, and holds a signal set with `LogicGateMap::force`
+    Constant(Logic),
+}
+
+#[derive(Debug, Clone)]
+struct Driver {
+    operation: Operation,
+    sources: Vec<usize>,
+    delay: u32,
+}
+
+/// a ROM or RAM, whose outputs are each driven by a `MemoryRead` op.
+/// the pins in `memory` aren't kept up to date, as its inputs are in `inputs`
+#[derive(Debug, Clone)]
+struct CompiledMemory {
+    path: Vec<Id>,
+    gate: Id,
+    writable: bool,
+    memory: Memory,
+    inputs: Vec<usize>,
+    read_ops: Vec<usize>,
+}
+
+/// the sources of an op are `sources[sources_start..sources_end]` in the `CompiledMap`
+#[derive(Debug, Clone, Copy)]
+struct CompiledOp {
+    operation: Operation,
+    sources_start: usize,
+    sources_end: usize,
+    output: usize,
+    delay: u32,
+}
+
+/// how `CompiledMap::step` decides which ops to evaluate.
+/// the first two produce exactly the same signal values every tick,
+/// and `Timed` does too as long as every delay is 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scheduler {
+    /// every op is evaluated every tick
+    #[default]
+    Synchronous,
+    /// only ops reading a signal which changed in the previous tick are evaluated
+    EventDriven,
+    /// like `EventDriven`, but each gate's new output only arrives
+    /// after that gate's delay (see `LogicGateMap::set_gate_delay`)
+    Timed,
+}
+
+/// a `LogicGateMap` with every nested custom gate flattened into one
+/// contiguous array of signals.
+/// every signal has at most one op driving it, and stepping computes every
+/// op from the previous tick's values, so it has the same synchronous semantics
+/// as `LogicGateMap::step` without cloning or allocating anything.
+/// the `LogicGateMap` stays the editable source of truth - use `load_inputs`
+/// and `store` to move values between the two
+#[derive(Debug, Clone)]
+pub struct CompiledMap {
+    signals: Vec<Logic>,
+    next_signals: Vec<Logic>,
+    ops: Vec<CompiledOp>,
+    sources: Vec<usize>,
+    origins: Vec<SignalOrigin>,
+    lookup: HashMap<SignalOrigin, usize>,
+
+    scheduler: Scheduler,
+    /// the ops reading signal `i` are `fanout[fanout_offsets[i]..fanout_offsets[i + 1]]`
+    fanout_offsets: Vec<usize>,
+    fanout: Vec<usize>,
+    driver_ops: Vec<Option<usize>>,
+    /// ops which might produce a different value from their output's current value
+    pending: Vec<usize>,
+    next_pending: Vec<usize>,
+    is_pending: Vec<bool>,
+    changes: Vec<(usize, Logic)>,
+
+    time: u64,
+    /// the values which will be written at time `t` are in `wheel[t % wheel.len()]`.
+    /// there is one slot for every tick up to the longest delay,
+    /// so a slot is always empty again before it's reused
+    wheel: Vec<Vec<(usize, Logic)>>,
+    scheduled: usize,
+
+    /// the same as `time`, apart from not counting steps taken while clocks are paused
+    clock_tick: u64,
+    clocks_paused: bool,
+    /// clock ops depend on the tick rather than any signal, so they're always pending
+    clock_ops: Vec<usize>,
+    memories: Vec<CompiledMemory>,
+    /// records some of the signals after every step
+    recorder: Option<WaveformRecorder>,
+}
+impl CompiledMap {
+    pub fn new(map: &LogicGateMap) -> Self {
+        let mut builder = CompiledMapBuilder::default();
+        builder.add_map(map, &mut vec![], 1);
+        // a forced signal is driven by a constant instead of whatever was driving it
+        for (origin, value) in map.forced() {
+            let index = builder.index(&origin.path, origin.point);
+            builder.values[index] = value;
+            builder.drivers[index] = Some(Driver {
+                operation: Operation::Constant(value),
+                sources: vec![],
+                delay: 1,
+            });
+        }
+        builder.build(map.clock_tick())
+    }
+
+    /// returns whether the circuit might still change, which for the
+    /// `Synchronous` and `EventDriven` schedulers is whether any signal changed
+    pub fn step(&mut self) -> bool {
+        self.time += 1;
+        if !self.clocks_paused {
+            self.clock_tick += 1;
+        }
+        if self.scheduler != Scheduler::Synchronous {
+            for i in 0..self.clock_ops.len() {
+                self.mark_pending(self.clock_ops[i]);
+            }
+        }
+        self.clock_memories();
+        let changed = match self.scheduler {
+            Scheduler::Synchronous => self.step_synchronous(),
+            Scheduler::EventDriven => self.step_event_driven(),
+            Scheduler::Timed => self.step_timed(),
+        };
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&self.signals);
+        }
+        changed
+    }
+
+    fn step_synchronous(&mut self) -> bool {
+        self.next_signals.copy_from_slice(&self.signals);
+        for op in &self.ops {
+            self.next_signals[op.output] = Self::evaluate(
+                &self.signals,
+                op.operation,
+                &self.sources[op.sources_start..op.sources_end],
+                self.clock_tick,
+                &self.memories,
+            );
+        }
+        std::mem::swap(&mut self.signals, &mut self.next_signals);
+        self.signals != self.next_signals
+    }
+
+    /// as every signal has a single driver, an op whose inputs didn't change
+    /// last tick would just write the value its output already has.
+    /// so only pending ops are evaluated, and the ops reading anything
+    /// that changed become pending for the next tick
+    fn step_event_driven(&mut self) -> bool {
+        self.changes.clear();
+        for op in &self.pending {
+            let CompiledOp {
+                operation,
+                sources_start,
+                sources_end,
+                output,
+                ..
+            } = self.ops[*op];
+            self.is_pending[*op] = false;
+            let value = Self::evaluate(
+                &self.signals,
+                operation,
+                &self.sources[sources_start..sources_end],
+                self.clock_tick,
+                &self.memories,
+            );
+            if value != self.signals[output] {
+                self.changes.push((output, value));
+            }
+        }
+        self.pending.clear();
+
+        for (index, value) in &self.changes {
+            self.signals[*index] = *value;
+            for op in &self.fanout[self.fanout_offsets[*index]..self.fanout_offsets[*index + 1]] {
+                if !self.is_pending[*op] {
+                    self.is_pending[*op] = true;
+                    self.next_pending.push(*op);
+                }
+            }
+        }
+        std::mem::swap(&mut self.pending, &mut self.next_pending);
+        !self.changes.is_empty()
+    }
+
+    /// pending ops are evaluated straight away, but their results are put in
+    /// the time wheel to be written once their delay has passed.
+    /// a delay of 1 means the result is written at the end of this step,
+    /// which is what every op does in the other schedulers
+    fn step_timed(&mut self) -> bool {
+        let slot_count = self.wheel.len() as u64;
+        for op in &self.pending {
+            let CompiledOp {
+                operation,
+                sources_start,
+                sources_end,
+                output,
+                delay,
+            } = self.ops[*op];
+            self.is_pending[*op] = false;
+            let value = Self::evaluate(
+                &self.signals,
+                operation,
+                &self.sources[sources_start..sources_end],
+                self.clock_tick,
+                &self.memories,
+            );
+            let slot = (self.time + delay as u64 - 1) % slot_count;
+            self.wheel[slot as usize].push((output, value));
+            self.scheduled += 1;
+        }
+        self.pending.clear();
+
+        self.changes.clear();
+        let slot = (self.time % slot_count) as usize;
+        self.scheduled -= self.wheel[slot].len();
+        for (index, value) in self.wheel[slot].drain(..) {
+            if value == self.signals[index] {
+                continue;
+            }
+            self.signals[index] = value;
+            self.changes.push((index, value));
+            for op in &self.fanout[self.fanout_offsets[index]..self.fanout_offsets[index + 1]] {
+                if !self.is_pending[*op] {
+                    self.is_pending[*op] = true;
+                    self.next_pending.push(*op);
+                }
+            }
+        }
+        std::mem::swap(&mut self.pending, &mut self.next_pending);
+        !self.changes.is_empty() || self.scheduled > 0
+    }
+
+    /// steps until nothing changes, returning how many steps changed something.
+    /// if the circuit is still changing after `max_steps`, it is stepped up to
+    /// `max_steps` more times to find which signals are toggling and
+    /// how long it takes to get back to the same state
+    pub fn settle(&mut self, max_steps: usize) -> Result<usize, Oscillation> {
+        for steps in 0..max_steps {
+            if !self.step() {
+                return Ok(steps);
+            }
+        }
+
+        let start = self.signals.clone();
+        let mut previous = self.signals.clone();
+        let mut toggling = vec![false; self.signals.len()];
+        let mut cycle_length = None;
+        for steps in 1..=max_steps {
+            if !self.step() {
+                return Ok(max_steps + steps - 1);
+            }
+            for (i, (old, new)) in previous.iter().zip(&self.signals).enumerate() {
+                toggling[i] |= old != new;
+            }
+            if self.signals == start {
+                cycle_length = Some(steps);
+                break;
+            }
+            previous.copy_from_slice(&self.signals);
+        }
+        // a running clock is meant to toggle, so it isn't reported itself
+        let clocks = self
+            .clock_ops
+            .iter()
+            .map(|op| self.ops[*op].output)
+            .collect::<HashSet<_>>();
+        Err(Oscillation {
+            cycle_length,
+            toggling: toggling
+                .into_iter()
+                .enumerate()
+                .filter(|(i, toggled)| *toggled && !clocks.contains(i))
+                .map(|(i, _)| self.origins[i].clone())
+                .collect(),
+        })
+    }
+
+    fn evaluate(
+        signals: &[Logic],
+        operation: Operation,
+        sources: &[usize],
+        clock_tick: u64,
+        memories: &[CompiledMemory],
+    ) -> Logic {
+        match operation {
+            Operation::Clock(clock) => clock.value(clock_tick),
+            Operation::Constant(value) => value,
+            Operation::Nand => signals[sources[0]].nand(signals[sources[1]]),
+            Operation::TriState => signals[sources[0]].tri_state(signals[sources[1]]),
+            Operation::Primitive(kind) => {
+                kind.evaluate(sources.iter().map(|source| signals[*source]))
+            }
+            Operation::Resolve => sources.iter().fold(Logic::HighImpedance, |value, source| {
+                value.resolve(signals[*source])
+            }),
+            Operation::MemoryRead { memory, bit } => memories[memory]
+                .memory
+                .read(sources.iter().map(|source| signals[*source]), bit),
+        }
+    }
+
+    /// every RAM is written from the previous tick's values before any op is
+    /// evaluated, like `LogicGateMap::step`, so a write can be read straight away
+    fn clock_memories(&mut self) {
+        for i in 0..self.memories.len() {
+            let CompiledMemory {
+                writable,
+                memory,
+                inputs,
+                ..
+            } = &mut self.memories[i];
+            let signals = &self.signals;
+            if *writable
+                && memory.clock(|pin| signals[inputs[pin]])
+                && self.scheduler != Scheduler::Synchronous
+            {
+                for j in 0..self.memories[i].read_ops.len() {
+                    self.mark_pending(self.memories[i].read_ops[j]);
+                }
+            }
+        }
+    }
+
+    fn mark_pending(&mut self, op: usize) {
+        if !self.is_pending[op] {
+            self.is_pending[op] = true;
+            self.pending.push(op);
+        }
+    }
+
+    fn mark_all_pending(&mut self) {
+        for op in 0..self.ops.len() {
+            self.mark_pending(op);
+        }
+    }
+
+    /// changing a signal from outside means both the ops reading it
+    /// and the op driving it (which may now disagree with it) have to be re-evaluated
+    fn mark_signal_changed(&mut self, index: usize) {
+        for i in self.fanout_offsets[index]..self.fanout_offsets[index + 1] {
+            self.mark_pending(self.fanout[i]);
+        }
+        if let Some(op) = self.driver_ops[index] {
+            self.mark_pending(op);
+        }
+    }
+}
+#[allow(unused)]
+impl CompiledMap {
+    pub fn signal_count(&self) -> usize {
+        self.signals.len()
+    }
+
+    pub fn value(&self, index: usize) -> Logic {
+        self.signals[index]
+    }
+
+    pub fn set_value(&mut self, index: usize, value: Logic) {
+        if self.signals[index] != value {
+            self.signals[index] = value;
+            self.mark_signal_changed(index);
+        }
+    }
+
+    pub fn scheduler(&self) -> Scheduler {
+        self.scheduler
+    }
+
+    /// how many steps have been taken since compiling
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn clocks_paused(&self) -> bool {
+        self.clocks_paused
+    }
+
+    /// while paused, every clock keeps its current value
+    pub fn set_clocks_paused(&mut self, paused: bool) {
+        self.clocks_paused = paused;
+    }
+
+    /// whether there's a clock which changes as the map is stepped,
+    /// in which case it never settles
+    pub fn has_running_clocks(&self) -> bool {
+        !self.clocks_paused && !self.clock_ops.is_empty()
+    }
+
+    /// records signals after every step from now on, see `WaveformRecorder::attach`
+    /// for what happens to any signals which aren't in `map`, which this was made from
+    pub fn attach_recorder(
+        &mut self,
+        map: &LogicGateMap,
+        mut recorder: WaveformRecorder,
+    ) -> Vec<String> {
+        let removed = recorder.attach(map, self);
+        self.recorder = Some(recorder);
+        removed
+    }
+
+    pub fn detach_recorder(&mut self) -> Option<WaveformRecorder> {
+        self.recorder.take()
+    }
+
+    pub fn recorder(&self) -> Option<&WaveformRecorder> {
+        self.recorder.as_ref()
+    }
+
+    pub fn recorder_mut(&mut self) -> Option<&mut WaveformRecorder> {
+        self.recorder.as_mut()
+    }
+
+    /// adds the signal at `path` in `map`, which this was made from,
+    /// to the attached recorder
+    pub fn record_signal(&mut self, map: &LogicGateMap, path: &str) -> Result<(), PathError> {
+        let origin = map.resolve_path(path)?;
+        let index = self
+            .index_of(&origin.path, origin.point)
+            .expect("should be able to find compiled signal!");
+        if let Some(recorder) = &mut self.recorder {
+            recorder.add_signal(path, index);
+        }
+        Ok(())
+    }
+
+    /// finds the signal for a connection point inside the map reached by `path`.
+    /// inputs and outputs of a custom gate can be found either from the outside
+    /// (as a `GateInput`/`GateOutput`) or from the inside (as an `Input`/`Output`)
+    pub fn index_of(&self, path: &[Id], point: ConnectionPoint) -> Option<usize> {
+        self.lookup
+            .get(&SignalOrigin {
+                path: path.to_vec(),
+                point,
+            })
+            .copied()
+    }
+
+    pub fn origin(&self, index: usize) -> &SignalOrigin {
+        &self.origins[index]
+    }
+
+    /// what computes signal `index` and which signals it reads,
+    /// or `None` if nothing drives it
+    pub fn driver(&self, index: usize) -> Option<(Operation, &[usize])> {
+        let op = &self.ops[self.driver_ops[index]?];
+        Some((
+            op.operation,
+            &self.sources[op.sources_start..op.sources_end],
+        ))
+    }
+
+    /// the memory read by `Operation::MemoryRead`, with its current contents
+    pub fn memory(&self, memory: usize) -> &Memory {
+        &self.memories[memory].memory
+    }
+}
+impl CompiledMap {
+    /// copies the values of the top-level inputs from `map`
+    pub fn load_inputs(&mut self, map: &LogicGateMap) {
+        for (id, value) in map.inputs() {
+            let index = self
+                .index_of(&[], ConnectionPoint::Input(id))
+                .expect("should be able to find compiled input!");
+            self.set_value(index, value);
+        }
+    }
+
+    /// the synchronous scheduler doesn't keep track of which ops are pending,
+    /// so every op is re-evaluated on the first event-driven step after switching.
+    /// anything waiting in the time wheel is thrown away
+    pub fn set_scheduler(&mut self, scheduler: Scheduler) {
+        self.scheduler = scheduler;
+        for slot in &mut self.wheel {
+            slot.clear();
+        }
+        self.scheduled = 0;
+        self.mark_all_pending();
+    }
+
+    /// see `LogicGateMap::set_unknown`
+    pub fn set_unknown(&mut self) {
+        for (origin, value) in self.origins.iter().zip(&mut self.signals) {
+            if !(origin.path.is_empty() && matches!(origin.point, ConnectionPoint::Input(_))) {
+                *value = Logic::Unknown;
+            }
+        }
+        for memory in &mut self.memories {
+            memory.memory.last_clock = Logic::Unknown;
+        }
+        self.mark_all_pending();
+    }
+
+    /// see `LogicGateMap::contentions`
+    pub fn contentions(&self) -> Vec<Contention> {
+        self.ops
+            .iter()
+            .filter(|op| op.operation == Operation::Resolve)
+            .filter_map(|op| {
+                let drivers = self.sources[op.sources_start..op.sources_end]
+                    .iter()
+                    .map(|source| (*source, self.signals[*source]))
+                    .filter(|(_, value)| *value != Logic::HighImpedance)
+                    .collect::<Vec<_>>();
+                drivers
+                    .iter()
+                    .any(|(_, value)| *value != drivers[0].1)
+                    .then(|| Contention {
+                        point: self.origins[op.output].clone(),
+                        drivers: drivers
+                            .into_iter()
+                            .map(|(source, value)| (self.origins[source].clone(), value))
+                            .collect(),
+                    })
+            })
+            .collect()
+    }
+
+    /// see `LogicGateMap::unknown_signals`
+    pub fn unknown_signals(&self) -> Vec<SignalOrigin> {
+        self.origins
+            .iter()
+            .zip(&self.signals)
+            .filter(|(_, value)| **value == Logic::Unknown)
+            .map(|(origin, _)| origin.clone())
+            .collect()
+    }
+
+    /// copies the contents of every ROM and RAM from `map`,
+    /// so that they can be edited while the simulation is running
+    pub fn load_memories(&mut self, map: &LogicGateMap) {
+        for i in 0..self.memories.len() {
+            let CompiledMemory {
+                path, gate, memory, ..
+            } = &mut self.memories[i];
+            let contents = &map
+                .inner_map(path)
+                .memory(*gate)
+                .expect("compiled memory should be a memory in the map!")
+                .contents;
+            if *contents != memory.contents {
+                memory.contents = contents.clone();
+                for j in 0..self.memories[i].read_ops.len() {
+                    self.mark_pending(self.memories[i].read_ops[j]);
+                }
+            }
+        }
+    }
+
+    /// writes every compiled signal and memory back into the hierarchical map
+    pub fn store(&self, map: &mut LogicGateMap) {
+        map.set_clock_tick(self.clock_tick);
+        for (origin, value) in self.origins.iter().zip(&self.signals) {
+            map.inner_map_mut(&origin.path)
+                .set_connection_point_value(&origin.point, *value);
+        }
+        for CompiledMemory {
+            path, gate, memory, ..
+        } in &self.memories
+        {
+            let stored = map
+                .inner_map_mut(path)
+                .memory_mut(*gate)
+                .expect("compiled memory should be a memory in the map!");
+            stored.contents = memory.contents.clone();
+            stored.last_clock = memory.last_clock;
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct CompiledMapBuilder {
+    values: Vec<Logic>,
+    drivers: Vec<Option<Driver>>,
+    origins: Vec<SignalOrigin>,
+    lookup: HashMap<SignalOrigin, usize>,
+    memories: Vec<CompiledMemory>,
+}
+impl CompiledMapBuilder {
+    fn add_signal(&mut self, path: &[Id], point: ConnectionPoint, value: Logic) -> usize {
+        let index = self.values.len();
+        let origin = SignalOrigin {
+            path: path.to_vec(),
+            point,
+        };
+        self.values.push(value);
+        self.drivers.push(None);
+        self.origins.push(origin.clone());
+        self.lookup.insert(origin, index);
+        index
+    }
+
+    fn index(&self, path: &[Id], point: ConnectionPoint) -> usize {
+        self.lookup[&SignalOrigin {
+            path: path.to_vec(),
+            point,
+        }]
+    }
+
+    /// a signal for the pin of a gate in `map`, starting with its value there
+    fn add_pin(&mut self, map: &LogicGateMap, path: &[Id], point: ConnectionPoint) -> usize {
+        let value = map.connection_point_value(&point);
+        self.add_signal(path, point, value)
+    }
+
+    fn add_alias(&mut self, path: &[Id], point: ConnectionPoint, index: usize) {
+        self.lookup.insert(
+            SignalOrigin {
+                path: path.to_vec(),
+                point,
+            },
+            index,
+        );
+    }
+
+    /// drivers added later override earlier ones, which mirrors `LogicGateMap::step`
+    /// where a map's connections are applied after its gates have been stepped.
+    /// `default_delay` is the delay for gates which don't have one set,
+    /// unless this map has a default of its own
+    fn add_map(&mut self, map: &LogicGateMap, path: &mut Vec<Id>, default_delay: u32) {
+        let default_delay = map.default_delay().unwrap_or(default_delay);
+        let mut inputs = map.inputs().collect::<Vec<_>>();
+        inputs.sort_by_key(|(id, _)| *id);
+        for (id, value) in inputs {
+            self.add_signal(path, ConnectionPoint::Input(id), value);
+        }
+        let mut outputs = map.outputs().collect::<Vec<_>>();
+        outputs.sort_by_key(|(id, _)| *id);
+        for (id, value) in outputs {
+            self.add_signal(path, ConnectionPoint::Output(id), value);
+        }
+        let mut middle_signals = map.middle_signals().collect::<Vec<_>>();
+        middle_signals.sort_by_key(|(id, _)| *id);
+        for (id, value) in middle_signals {
+            self.add_signal(path, ConnectionPoint::MiddleSignal(id), value);
+        }
+
+        let mut gates = map.gates().collect::<Vec<_>>();
+        gates.sort();
+        for gate in gates {
+            match map.gate_by_id(gate) {
+                LogicGate::Nand {
+                    inputs: [a, b],
+                    output,
+                } => {
+                    let a = self.add_pin(map, path, ConnectionPoint::GateInput { gate, input: *a });
+                    let b = self.add_pin(map, path, ConnectionPoint::GateInput { gate, input: *b });
+                    let output = self.add_pin(
+                        map,
+                        path,
+                        ConnectionPoint::GateOutput {
+                            gate,
+                            output: *output,
+                        },
+                    );
+                    self.drivers[output] = Some(Driver {
+                        operation: Operation::Nand,
+                        sources: vec![a, b],
+                        delay: map.gate_delay(gate).unwrap_or(default_delay),
+                    });
+                }
+                LogicGate::TriState {
+                    input,
+                    enable,
+                    output,
+                } => {
+                    let input = self.add_pin(
+                        map,
+                        path,
+                        ConnectionPoint::GateInput {
+                            gate,
+                            input: *input,
+                        },
+                    );
+                    let enable = self.add_pin(
+                        map,
+                        path,
+                        ConnectionPoint::GateInput {
+                            gate,
+                            input: *enable,
+                        },
+                    );
+                    let output = self.add_pin(
+                        map,
+                        path,
+                        ConnectionPoint::GateOutput {
+                            gate,
+                            output: *output,
+                        },
+                    );
+                    self.drivers[output] = Some(Driver {
+                        operation: Operation::TriState,
+                        sources: vec![input, enable],
+                        delay: map.gate_delay(gate).unwrap_or(default_delay),
+                    });
+                }
+                LogicGate::Primitive {
+                    kind,
+                    inputs,
+                    output,
+                } => {
+                    let sources = inputs
+                        .iter()
+                        .map(|input| {
+                            self.add_pin(
+                                map,
+                                path,
+                                ConnectionPoint::GateInput {
+                                    gate,
+                                    input: *input,
+                                },
+                            )
+                        })
+                        .collect();
+                    let output = self.add_pin(
+                        map,
+                        path,
+                        ConnectionPoint::GateOutput {
+                            gate,
+                            output: *output,
+                        },
+                    );
+                    self.drivers[output] = Some(Driver {
+                        operation: Operation::Primitive(*kind),
+                        sources,
+                        delay: map.gate_delay(gate).unwrap_or(default_delay),
+                    });
+                }
+                LogicGate::Clock { clock, output } => {
+                    let output = self.add_pin(
+                        map,
+                        path,
+                        ConnectionPoint::GateOutput {
+                            gate,
+                            output: *output,
+                        },
+                    );
+                    self.drivers[output] = Some(Driver {
+                        operation: Operation::Clock(*clock),
+                        sources: vec![],
+                        delay: 1,
+                    });
+                }
+                LogicGate::Rom { inputs, outputs } | LogicGate::Ram { inputs, outputs } => {
+                    let memory = map
+                        .memory(gate)
+                        .expect("should have the contents of every memory!");
+                    let inputs = inputs
+                        .iter()
+                        .map(|input| {
+                            self.add_pin(
+                                map,
+                                path,
+                                ConnectionPoint::GateInput {
+                                    gate,
+                                    input: *input,
+                                },
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    for (bit, output) in outputs.iter().enumerate() {
+                        let output = self.add_pin(
+                            map,
+                            path,
+                            ConnectionPoint::GateOutput {
+                                gate,
+                                output: *output,
+                            },
+                        );
+                        self.drivers[output] = Some(Driver {
+                            operation: Operation::MemoryRead {
+                                memory: self.memories.len(),
+                                bit,
+                            },
+                            sources: inputs[..memory.address_width].to_vec(),
+                            delay: map.gate_delay(gate).unwrap_or(default_delay),
+                        });
+                    }
+                    self.memories.push(CompiledMemory {
+                        path: path.clone(),
+                        gate,
+                        writable: matches!(map.gate_by_id(gate), LogicGate::Ram { .. }),
+                        memory: memory.clone(),
+                        inputs,
+                        read_ops: vec![],
+                    });
+                }
+                LogicGate::Custom { inputs, outputs } => {
+                    let inner = map
+                        .custom_gate(gate)
+                        .expect("should have an instance of every custom gate!");
+                    path.push(gate);
+                    self.add_map(inner, path, default_delay);
+                    path.pop();
+
+                    let mut inner_path = path.clone();
+                    inner_path.push(gate);
+                    for input in inputs {
+                        let index = self.index(&inner_path, ConnectionPoint::Input(*input));
+                        self.add_alias(
+                            path,
+                            ConnectionPoint::GateInput {
+                                gate,
+                                input: *input,
+                            },
+                            index,
+                        );
+                    }
+                    for output in outputs {
+                        let index = self.index(&inner_path, ConnectionPoint::Output(*output));
+                        self.add_alias(
+                            path,
+                            ConnectionPoint::GateOutput {
+                                gate,
+                                output: *output,
+                            },
+                            index,
+                        );
+                    }
+                }
+            }
+        }
+
+        let mut connections = map.connections().collect::<Vec<_>>();
+        connections.sort_by_key(|(id, _)| *id);
+        let mut resolved: Vec<(usize, Vec<usize>)> = vec![];
+        for (_, Connection { start, end }) in connections {
+            let from = self.index(path, start);
+            let to = self.index(path, end);
+            match resolved.iter_mut().find(|(end, _)| *end == to) {
+                Some((_, sources)) => sources.push(from),
+                None => resolved.push((to, vec![from])),
+            }
+        }
+        for (to, sources) in resolved {
+            self.drivers[to] = Some(Driver {
+                operation: Operation::Resolve,
+                sources,
+                delay: 1,
+            });
+        }
+    }
+
+    fn build(self, clock_tick: u64) -> CompiledMap {
+        let mut ops = vec![];
+        let mut sources = vec![];
+        let mut driver_ops = vec![None; self.values.len()];
+        let mut readers = vec![vec![]; self.values.len()];
+        for (output, driver) in self.drivers.into_iter().enumerate() {
+            let Some(driver) = driver else {
+                continue;
+            };
+            driver_ops[output] = Some(ops.len());
+            for source in &driver.sources {
+                if readers[*source].last() != Some(&ops.len()) {
+                    readers[*source].push(ops.len());
+                }
+            }
+            debug_assert!(driver.delay >= 1, "delays should be at least 1 tick!");
+            ops.push(CompiledOp {
+                operation: driver.operation,
+                sources_start: sources.len(),
+                sources_end: sources.len() + driver.sources.len(),
+                output,
+                delay: driver.delay,
+            });
+            sources.extend(driver.sources);
+        }
+        let mut memories = self.memories;
+        for (i, op) in ops.iter().enumerate() {
+            if let Operation::MemoryRead { memory, .. } = op.operation {
+                memories[memory].read_ops.push(i);
+            }
+        }
+        let mut fanout_offsets = vec![0];
+        let mut fanout = vec![];
+        for ops in readers {
+            fanout.extend(ops);
+            fanout_offsets.push(fanout.len());
+        }
+
+        let mut result = CompiledMap {
+            next_signals: self.values.clone(),
+            signals: self.values,
+            origins: self.origins,
+            lookup: self.lookup,
+            scheduler: Scheduler::default(),
+            fanout_offsets,
+            fanout,
+            driver_ops,
+            pending: Vec::with_capacity(ops.len()),
+            next_pending: Vec::with_capacity(ops.len()),
+            is_pending: vec![false; ops.len()],
+            changes: Vec::with_capacity(ops.len()),
+            time: 0,
+            wheel: vec![vec![]; ops.iter().map(|op| op.delay).max().unwrap_or(1) as usize],
+            scheduled: 0,
+            clock_tick,
+            clocks_paused: false,
+            clock_ops: ops
+                .iter()
+                .enumerate()
+                .filter(|(_, op)| matches!(op.operation, Operation::Clock(_)))
+                .map(|(i, _)| i)
+                .collect(),
+            memories,
+            ops,
+            sources,
+            recorder: None,
+        };
+        result.mark_all_pending();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    const RING_OSCILLATOR: &str = "version 0
+define_gate ring
+outputs out
+nots n1 n2 n3
+connections n1 out 0 => n2 in 0, n2 out 0 => n3 in 0, n3 out 0 => n1 in 0
+connections n3 out 0 => out
+";
+
+    fn index(map: &LogicGateMap, compiled: &CompiledMap, path: &str) -> usize {
+        let origin = map
+            .resolve_path(path)
+            .expect("should be able to find path!");
+        compiled
+            .index_of(&origin.path, origin.point)
+            .expect("should be able to find compiled signal!")
+    }
+
+    fn toggling(compiled: &CompiledMap, oscillation: &Oscillation) -> HashSet<usize> {
+        oscillation
+            .toggling
+            .iter()
+            .map(|origin| {
+                compiled
+                    .index_of(&origin.path, origin.point)
+                    .expect("should be able to find compiled signal!")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn settles_combinational_gates() {
+        let map = parse_gate(include_str!("../gates.dat"), "and");
+        let mut compiled = map.compile();
+        for (a, b) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            compiled.set_value(index(&map, &compiled, "a"), (a == 1).into());
+            compiled.set_value(index(&map, &compiled, "b"), (b == 1).into());
+            assert!(compiled.settle(100).is_ok());
+            assert_eq!(
+                compiled.value(index(&map, &compiled, "out")),
+                (a & b == 1).into()
+            );
+            assert_eq!(compiled.settle(100).ok(), Some(0));
+        }
+    }
+
+    #[test]
+    fn settled_latch_holds_its_value() {
+        let map = parse_gate(include_str!("../gates.dat"), "sr_latch");
+        let mut compiled = map.compile();
+        compiled.set_value(index(&map, &compiled, "set"), Logic::One);
+        assert!(compiled.settle(100).is_ok());
+        compiled.set_value(index(&map, &compiled, "set"), Logic::Zero);
+        assert!(compiled.settle(100).is_ok());
+        assert_eq!(compiled.value(index(&map, &compiled, "out")), Logic::One);
+        assert_eq!(
+            compiled.value(index(&map, &compiled, "not_out")),
+            Logic::Zero
+        );
+    }
+
+    #[test]
+    fn ring_oscillator_oscillates() {
+        let map = parse_gate(RING_OSCILLATOR, "ring");
+        let mut compiled = map.compile();
+        let oscillation = compiled.settle(100).expect_err("should never settle!");
+        assert!(oscillation.cycle_length.is_some());
+        let toggling = toggling(&compiled, &oscillation);
+        for path in ["n1.out0", "n2.out0", "n3.out0", "out"] {
+            assert!(toggling.contains(&index(&map, &compiled, path)));
+        }
+    }
+
+    #[test]
+    fn oscillations_are_described_by_path() {
+        let text = format!(
+            "{RING_OSCILLATOR}define_gate wrapped
+outputs out
+custom_gates inner = ring
+connections inner out 0 => out
+"
+        );
+        let map = parse_gate(&text, "wrapped");
+        let oscillation = map.compile().settle(100).expect_err("should never settle!");
+        let description = oscillation.describe(&map);
+        assert!(description.starts_with("oscillating with a cycle of"));
+        for path in ["inner/n1.out0", "inner/n3.out0", "inner/out", " out"] {
+            assert!(
+                description.contains(path),
+                "{description} should name {path}"
+            );
+        }
+    }
+
+    const SHARED_BUS: &str = "version 0
+define_gate shared
+inputs a b enable_a enable_b
+outputs out
+tri_states ta tb
+connections a => ta in 0, enable_a => ta in 1, b => tb in 0, enable_b => tb in 1
+connections ta out 0 => out, tb out 0 => out
+";
+
+    #[test]
+    fn tri_states_resolve_onto_a_shared_signal() {
+        let map = parse_gate(SHARED_BUS, "shared");
+        let mut compiled = map.compile();
+        let mut drive = |a, b, enable_a, enable_b| {
+            for (path, value) in [
+                ("a", a),
+                ("b", b),
+                ("enable_a", enable_a),
+                ("enable_b", enable_b),
+            ] {
+                compiled.set_value(index(&map, &compiled, path), value);
+            }
+            assert!(compiled.settle(100).is_ok());
+            (
+                compiled.value(index(&map, &compiled, "out")),
+                compiled.contentions().len(),
+            )
+        };
+        use Logic::{HighImpedance, One, Zero};
+        assert_eq!(drive(One, Zero, Zero, Zero), (HighImpedance, 0));
+        assert_eq!(drive(One, Zero, One, Zero), (One, 0));
+        assert_eq!(drive(One, Zero, Zero, One), (Zero, 0));
+        assert_eq!(drive(One, One, One, One), (One, 0));
+        assert_eq!(drive(One, Zero, One, One), (Logic::Unknown, 1));
+    }
+
+    #[test]
+    fn contentions_name_every_driver() {
+        let text = format!(
+            "{SHARED_BUS}define_gate wrapped
+inputs a b
+outputs out
+custom_gates inner = shared
+connections a => inner in 0, a => inner in 2, b => inner in 1, a => inner in 3
+connections inner out 0 => out
+"
+        );
+        let map = parse_gate(&text, "wrapped");
+        let mut compiled = map.compile();
+        compiled.set_value(index(&map, &compiled, "a"), Logic::One);
+        assert!(compiled.settle(100).is_ok());
+        let contentions = compiled.contentions();
+        assert_eq!(contentions.len(), 1);
+        let contention = &contentions[0];
+        assert_eq!(contention.point, map.resolve_path("inner/out").unwrap());
+        let drivers = contention
+            .drivers
+            .iter()
+            .map(|(driver, value)| (map.origin_name(driver), *value))
+            .collect::<HashSet<_>>();
+        assert_eq!(
+            drivers,
+            HashSet::from([
+                ("inner/ta.out0".to_string(), Logic::One),
+                ("inner/tb.out0".to_string(), Logic::Zero),
+            ])
+        );
+        let description = contention.describe(&map);
+        assert!(description.starts_with("contention on inner/out:"));
+        for driver in ["inner/ta.out0 = 1", "inner/tb.out0 = 0"] {
+            assert!(
+                description.contains(driver),
+                "{description} should name {driver}"
+            );
+        }
+    }
+
+    /// settles `map` with each input in `inputs` set, giving the value of `out`
+    fn settled_output(map: &LogicGateMap, inputs: &[(&str, Logic)]) -> Logic {
+        let mut compiled = map.compile();
+        for (path, value) in inputs {
+            compiled.set_value(index(map, &compiled, path), *value);
+        }
+        assert!(compiled.settle(100).is_ok());
+        compiled.value(index(map, &compiled, "out"))
+    }
+
+    #[test]
+    fn unknowns_propagate_through_nand_and_custom_gates() {
+        use Logic::{One, Unknown, Zero};
+        // the and gate is a NAND gate followed by a custom not gate
+        let map = parse_gate(include_str!("../gates.dat"), "and");
+        assert_eq!(settled_output(&map, &[("a", Unknown), ("b", One)]), Unknown);
+        assert_eq!(settled_output(&map, &[("a", Unknown), ("b", Zero)]), Zero);
+        let map = parse_gate(include_str!("../gates.dat"), "not");
+        assert_eq!(settled_output(&map, &[("in", Unknown)]), Unknown);
+    }
+
+    #[test]
+    fn unknowns_propagate_through_primitives() {
+        use Logic::{One, Unknown, Zero};
+        let primitive = |kind: &str| {
+            let text = format!(
+                "version 0
+define_gate primitive
+inputs a b
+outputs out
+{kind} g:2
+connections a => g in 0, b => g in 1, g out 0 => out
+"
+            );
+            parse_gate(&text, "primitive")
+        };
+        for (kind, known, dominant) in [
+            ("ands", One, Some((Zero, Zero))),
+            ("ors", Zero, Some((One, One))),
+            ("xors", One, None),
+        ] {
+            let map = primitive(kind);
+            assert_eq!(
+                settled_output(&map, &[("a", Unknown), ("b", known)]),
+                Unknown
+            );
+            // an input which decides the output on its own hides the unknown one
+            if let Some((input, output)) = dominant {
+                assert_eq!(
+                    settled_output(&map, &[("a", Unknown), ("b", input)]),
+                    output
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn no_unknowns_remain_after_a_reset() {
+        let mut map = parse_gate(include_str!("../gates.dat"), "sr_latch");
+        map.set_unknown();
+        // nothing has reset the latch yet, so it still doesn't know what it holds
+        assert!(map.settle(100).is_ok());
+        let unknown = map.unknown_signals();
+        assert!(unknown.contains(&map.resolve_path("out").unwrap()));
+        assert!(
+            unknown.iter().all(|origin| !origin.path.is_empty()
+                || !matches!(origin.point, ConnectionPoint::Input(_)))
+        );
+
+        let reset = map.resolve_path("reset").unwrap().point;
+        map.set_connection_point_value(&reset, Logic::One);
+        assert!(map.settle(100).is_ok());
+        map.set_connection_point_value(&reset, Logic::Zero);
+        assert!(map.settle(100).is_ok());
+        assert_eq!(map.unknown_signals(), vec![]);
+        assert_eq!(map.probe("out"), Ok(Logic::Zero));
+    }
+
+    /// how many steps it takes for `n` to change after `in` is turned on
+    fn steps_to_respond(text: &str, scheduler: Scheduler) -> usize {
+        let map = parse_gate(text, "slow");
+        let mut compiled = map.compile();
+        compiled.set_scheduler(scheduler);
+        assert!(compiled.settle(100).is_ok());
+        compiled.set_value(index(&map, &compiled, "in"), Logic::One);
+        let output = index(&map, &compiled, "n.out0");
+        (1..100)
+            .find(|_| {
+                compiled.step();
+                compiled.value(output) == Logic::Zero
+            })
+            .expect("should respond eventually!")
+    }
+
+    #[test]
+    fn timed_scheduler_waits_for_delays() {
+        let slow = |delay: &str| {
+            format!(
+                "version 0
+define_gate slow
+inputs in
+outputs out
+nots n
+{delay}
+connections in => n in 0, n out 0 => out
+"
+            )
+        };
+        let synchronous = steps_to_respond(&slow(""), Scheduler::Synchronous);
+        assert_eq!(steps_to_respond(&slow(""), Scheduler::Timed), synchronous);
+        assert_eq!(
+            steps_to_respond(&slow("delay 3 n"), Scheduler::Timed),
+            synchronous + 2
+        );
+        assert_eq!(
+            steps_to_respond(&slow("default_delay 5"), Scheduler::Timed),
+            synchronous + 4
+        );
+        // only the timed scheduler uses delays
+        assert_eq!(
+            steps_to_respond(&slow("delay 3 n"), Scheduler::EventDriven),
+            synchronous
+        );
+    }
+
+    #[test]
+    fn schedulers_agree_without_delays() {
+        let map = parse_gate(RING_OSCILLATOR, "ring");
+        let mut compiled = [
+            Scheduler::Synchronous,
+            Scheduler::EventDriven,
+            Scheduler::Timed,
+        ]
+        .map(|scheduler| {
+            let mut compiled = map.compile();
+            compiled.set_scheduler(scheduler);
+            compiled
+        });
+        for _ in 0..20 {
+            for compiled in &mut compiled {
+                compiled.step();
+            }
+            for other in &compiled[1..] {
+                assert_eq!(other.signals, compiled[0].signals);
+            }
+        }
+    }
+
+    #[test]
+    fn running_clocks_are_not_reported_as_oscillating() {
+        let map = parse_gate(
+            "version 0
+define_gate clocked
+outputs out
+clocks c:4
+buffers b
+connections c out 0 => b in 0, b out 0 => out
+",
+            "clocked",
+        );
+        let mut compiled = map.compile();
+        assert!(compiled.has_running_clocks());
+        let oscillation = compiled
+            .settle(100)
+            .expect_err("shouldn't settle with a running clock!");
+        let toggling = toggling(&compiled, &oscillation);
+        assert!(!toggling.contains(&index(&map, &compiled, "c.out0")));
+        assert!(toggling.contains(&index(&map, &compiled, "b.out0")));
+
+        compiled.set_clocks_paused(true);
+        assert!(!compiled.has_running_clocks());
+        assert!(compiled.settle(100).is_ok());
+    }
+}
@@ -0,0 +1,401 @@
+use std::fmt::Display;
+
+use crate::{
+    bdd::{Bdd, BddNode},
+    compiled::{CompiledMap, Operation, Oscillation},
+    logic::Logic,
+    logic_gate::{ConnectionPoint, PrimitiveKind},
+    logic_gate_map::LogicGateMap,
+    truth_table::{self, MAX_INPUTS, evaluate, ports},
+};
+
+/// maps with up to this many inputs are compared by trying every combination,
+/// and wider ones are compared with a `Bdd`
+pub const MAX_EXHAUSTIVE_INPUTS: usize = 10;
+/// how big the `Bdd` for both maps can get before giving up
+const MAX_BDD_NODES: usize = 1 << 20;
+
+#[derive(Debug, Clone)]
+pub enum Equivalence {
+    Equivalent,
+    Different(Counterexample),
+}
+
+/// inputs which give different outputs, and what each map gives.
+/// an output of `Err` means that map never settled
+#[derive(Debug, Clone)]
+pub struct Counterexample {
+    pub inputs: Vec<Logic>,
+    pub left: Result<Vec<Logic>, Oscillation>,
+    pub right: Result<Vec<Logic>, Oscillation>,
+}
+impl Display for Counterexample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let values = |values: &[Logic]| values.iter().map(|value| value.to_string()).collect();
+        let outputs = |outputs: &Result<Vec<Logic>, Oscillation>| match outputs {
+            Ok(outputs) => values(outputs),
+            Err(_) => "oscillating".to_string(),
+        };
+        write!(
+            f,
+            "inputs {} give {} and {}",
+            values(&self.inputs).as_str(),
+            outputs(&self.left),
+            outputs(&self.right)
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EquivalenceError {
+    InputCount(usize, usize),
+    OutputCount(usize, usize),
+    /// too many inputs to try every combination,
+    /// and the reason the maps couldn't be compared with a `Bdd`
+    TooWide(usize, String),
+}
+impl Display for EquivalenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EquivalenceError::InputCount(left, right) => {
+                write!(f, "one map has {left} inputs and the other has {right}")
+            }
+            EquivalenceError::OutputCount(left, right) => {
+                write!(f, "one map has {left} outputs and the other has {right}")
+            }
+            EquivalenceError::TooWide(inputs, reason) => write!(
+                f,
+                "{inputs} inputs is too many to try every combination, and {reason}"
+            ),
+        }
+    }
+}
+
+/// whether two maps give the same outputs for every combination of inputs,
+/// matching up inputs and outputs in the order they were declared.
+/// narrow maps are simulated like `truth_table::truth_table`, which also
+/// handles feedback and tri-states. wider ones are compared with a `Bdd`, which
+/// only works for maps where every signal is a function of the current inputs,
+/// so it falls back to simulating if it can and there is feedback.
+/// memories are read with their current contents and clocks keep their current value
+pub fn check_equivalence(
+    left: &LogicGateMap,
+    right: &LogicGateMap,
+) -> Result<Equivalence, EquivalenceError> {
+    let inputs = (ports(left.inputs()).len(), ports(right.inputs()).len());
+    if inputs.0 != inputs.1 {
+        return Err(EquivalenceError::InputCount(inputs.0, inputs.1));
+    }
+    let outputs = (ports(left.outputs()).len(), ports(right.outputs()).len());
+    if outputs.0 != outputs.1 {
+        return Err(EquivalenceError::OutputCount(outputs.0, outputs.1));
+    }
+    if inputs.0 <= MAX_EXHAUSTIVE_INPUTS {
+        return Ok(exhaustive(left, right));
+    }
+    match symbolic(left, right, inputs.0) {
+        Ok(equivalence) => Ok(equivalence),
+        Err(_) if inputs.0 <= MAX_INPUTS => Ok(exhaustive(left, right)),
+        Err(reason) => Err(EquivalenceError::TooWide(inputs.0, reason)),
+    }
+}
+
+fn exhaustive(left: &LogicGateMap, right: &LogicGateMap) -> Equivalence {
+    let tables = [left, right].map(|map| {
+        truth_table::truth_table(map).expect("should be narrow enough for a truth table!")
+    });
+    for (left, right) in tables[0].rows.iter().zip(&tables[1].rows) {
+        let same = match (&left.outputs, &right.outputs) {
+            (Ok(left), Ok(right)) => left == right,
+            (Err(_), Err(_)) => true,
+            _ => false,
+        };
+        if !same {
+            return Equivalence::Different(Counterexample {
+                inputs: left.inputs.clone(),
+                left: left.outputs.clone(),
+                right: right.outputs.clone(),
+            });
+        }
+    }
+    Equivalence::Equivalent
+}
+
+fn symbolic(
+    left: &LogicGateMap,
+    right: &LogicGateMap,
+    input_count: usize,
+) -> Result<Equivalence, String> {
+    let mut bdd = Bdd::new(MAX_BDD_NODES);
+    let left_outputs = outputs(&mut bdd, left)?;
+    let right_outputs = outputs(&mut bdd, right)?;
+    for (a, b) in left_outputs.into_iter().zip(right_outputs) {
+        let difference = bdd.xor(a, b).ok_or(too_large())?;
+        let Some(assignment) = bdd.satisfy(difference) else {
+            continue;
+        };
+        let mut inputs = vec![Logic::Zero; input_count];
+        for (variable, value) in assignment {
+            inputs[variable] = value.into();
+        }
+        return Ok(Equivalence::Different(Counterexample {
+            left: evaluate(left, &inputs),
+            right: evaluate(right, &inputs),
+            inputs,
+        }));
+    }
+    Ok(Equivalence::Equivalent)
+}
+
+fn too_large() -> String {
+    format!("the BDD needed more than {MAX_BDD_NODES} nodes")
+}
+
+/// a `Bdd` for each of the map's outputs, with its inputs as the variables
+fn outputs(bdd: &mut Bdd, map: &LogicGateMap) -> Result<Vec<BddNode>, String> {
+    let compiled = map.compile();
+    let index = |point| {
+        compiled
+            .index_of(&[], point)
+            .expect("should be able to find compiled input or output!")
+    };
+    let mut values = vec![None; compiled.signal_count()];
+    for (variable, id) in ports(map.inputs()).into_iter().enumerate() {
+        values[index(ConnectionPoint::Input(id))] =
+            Some(bdd.variable(variable).ok_or(too_large())?);
+    }
+    let mut visiting = vec![false; compiled.signal_count()];
+    ports(map.outputs())
+        .into_iter()
+        .map(|id| {
+            signal(
+                bdd,
+                &compiled,
+                &mut values,
+                &mut visiting,
+                index(ConnectionPoint::Output(id)),
+            )
+        })
+        .collect()
+}
+
+fn constant(compiled: &CompiledMap, index: usize) -> Result<BddNode, String> {
+    compiled
+        .value(index)
+        .to_bool()
+        .map(Bdd::constant)
+        .ok_or(format!(
+            "{} is {} rather than 0 or 1",
+            compiled.origin(index),
+            compiled.value(index)
+        ))
+}
+
+/// works through the signals `index` depends on without recursing,
+/// as a chain of gates can be much deeper than the stack
+fn signal(
+    bdd: &mut Bdd,
+    compiled: &CompiledMap,
+    values: &mut [Option<BddNode>],
+    visiting: &mut [bool],
+    index: usize,
+) -> Result<BddNode, String> {
+    let mut stack = vec![(index, false)];
+    while let Some((index, ready)) = stack.pop() {
+        if values[index].is_some() {
+            continue;
+        }
+        let Some((operation, sources)) = compiled.driver(index) else {
+            values[index] = Some(constant(compiled, index)?);
+            continue;
+        };
+        if !ready {
+            // a signal is only reached again before it has a value if it depends on itself
+            if visiting[index] {
+                return Err(format!(
+                    "{} is part of a feedback loop",
+                    compiled.origin(index)
+                ));
+            }
+            visiting[index] = true;
+            stack.push((index, true));
+            stack.extend(sources.iter().map(|source| (*source, false)));
+            continue;
+        }
+        let inputs = sources
+            .iter()
+            .map(|source| values[*source].expect("should have worked out sources first!"))
+            .collect::<Vec<_>>();
+        values[index] =
+            Some(operation_value(bdd, compiled, index, operation, &inputs).ok_or(too_large())??);
+    }
+    Ok(values[index].expect("should have worked out signal!"))
+}
+
+/// the outer `None` is from the `Bdd` getting too large
+fn operation_value(
+    bdd: &mut Bdd,
+    compiled: &CompiledMap,
+    index: usize,
+    operation: Operation,
+    inputs: &[BddNode],
+) -> Option<Result<BddNode, String>> {
+    let unsupported = |what: &str| Some(Err(format!("{} is {what}", compiled.origin(index))));
+    let node = match operation {
+        Operation::Nand => {
+            let and = bdd.and(inputs[0], inputs[1])?;
+            bdd.not(and)?
+        }
+        Operation::TriState => return unsupported("driven by a tri-state buffer"),
+        Operation::Resolve if inputs.len() > 1 => {
+            return unsupported("driven by more than one connection");
+        }
+        Operation::Resolve => inputs[0],
+        Operation::Primitive(kind) => {
+            let mut inputs = inputs.iter().copied();
+            let first = inputs.next()?;
+            let mut fold = |bdd: &mut Bdd, f: fn(&mut Bdd, BddNode, BddNode) -> Option<BddNode>| {
+                inputs.try_fold(first, |a, b| f(bdd, a, b))
+            };
+            match kind {
+                PrimitiveKind::And => fold(bdd, Bdd::and)?,
+                PrimitiveKind::Or => fold(bdd, Bdd::or)?,
+                PrimitiveKind::Xor => fold(bdd, Bdd::xor)?,
+                PrimitiveKind::Nor => {
+                    let or = fold(bdd, Bdd::or)?;
+                    bdd.not(or)?
+                }
+                PrimitiveKind::Xnor => {
+                    let xor = fold(bdd, Bdd::xor)?;
+                    bdd.not(xor)?
+                }
+                PrimitiveKind::Not => bdd.not(first)?,
+                PrimitiveKind::Buffer => first,
+            }
+        }
+        Operation::Clock(_) | Operation::Constant(_) => {
+            return Some(constant(compiled, index));
+        }
+        Operation::MemoryRead { memory, bit } => {
+            let memory = compiled.memory(memory);
+            memory_bit(bdd, &memory.contents, bit, inputs, memory.address_width, 0)?
+        }
+    };
+    Some(Ok(node))
+}
+
+/// one bit of the words from `base` with `level` address bits left to choose,
+/// where `address` is least significant bit first
+fn memory_bit(
+    bdd: &mut Bdd,
+    contents: &[u64],
+    bit: usize,
+    address: &[BddNode],
+    level: usize,
+    base: usize,
+) -> Option<BddNode> {
+    if level == 0 {
+        return Some(Bdd::constant(contents[base] & (1 << bit) != 0));
+    }
+    let low = memory_bit(bdd, contents, bit, address, level - 1, base)?;
+    let high = memory_bit(
+        bdd,
+        contents,
+        bit,
+        address,
+        level - 1,
+        base | 1 << (level - 1),
+    )?;
+    bdd.select(address[level - 1], high, low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    /// an and of `count` inputs, made from a chain of two input gates,
+    /// with the last one being `last` instead
+    fn chain(count: usize, last: &str) -> LogicGateMap {
+        let inputs = (0..count).map(|i| format!("i{i}")).collect::<Vec<_>>();
+        let mut text = format!(
+            "version 0\ndefine_gate chain\ninputs {}\noutputs out\nands",
+            inputs.join(" ")
+        );
+        for gate in 1..count - 1 {
+            text += &format!(" g{gate}");
+        }
+        text += &format!("\n{last}s g{}\n", count - 1);
+        text += &format!("connections i0 => g1 in 0, g{} out 0 => out\n", count - 1);
+        for gate in 1..count {
+            text += &format!("connections i{gate} => g{gate} in 1\n");
+            if gate > 1 {
+                text += &format!("connections g{} out 0 => g{gate} in 0\n", gate - 1);
+            }
+        }
+        parse_gate(&text, "chain")
+    }
+
+    fn wide_and(count: usize) -> LogicGateMap {
+        let inputs = (0..count).map(|i| format!("i{i}")).collect::<Vec<_>>();
+        let mut text = format!(
+            "version 0\ndefine_gate wide\ninputs {}\noutputs out\nands g:{count}\n",
+            inputs.join(" ")
+        );
+        for (i, input) in inputs.iter().enumerate() {
+            text += &format!("connections {input} => g in {i}\n");
+        }
+        text += "connections g out 0 => out\n";
+        parse_gate(&text, "wide")
+    }
+
+    /// whether the maps really do give different outputs for the counterexample
+    fn check_counterexample(left: &LogicGateMap, right: &LogicGateMap, equivalence: Equivalence) {
+        let Equivalence::Different(counterexample) = equivalence else {
+            panic!("should be different!");
+        };
+        let left_outputs = evaluate(left, &counterexample.inputs).ok();
+        let right_outputs = evaluate(right, &counterexample.inputs).ok();
+        assert_eq!(left_outputs, counterexample.left.ok());
+        assert_eq!(right_outputs, counterexample.right.ok());
+        assert_ne!(left_outputs, right_outputs);
+    }
+
+    #[test]
+    fn narrow_maps_are_compared_exhaustively() {
+        let gates = include_str!("../gates.dat");
+        let and = parse_gate(gates, "and");
+        assert!(matches!(
+            check_equivalence(&and, &wide_and(2)),
+            Ok(Equivalence::Equivalent)
+        ));
+        let or = parse_gate(gates, "or");
+        check_counterexample(&and, &or, check_equivalence(&and, &or).unwrap());
+    }
+
+    #[test]
+    fn wide_maps_are_compared_symbolically() {
+        let count = MAX_EXHAUSTIVE_INPUTS + 6;
+        let (wide, chain_of_ands) = (wide_and(count), chain(count, "and"));
+        assert!(matches!(
+            check_equivalence(&wide, &chain_of_ands),
+            Ok(Equivalence::Equivalent)
+        ));
+        let chain_with_or = chain(count, "or");
+        check_counterexample(
+            &wide,
+            &chain_with_or,
+            check_equivalence(&wide, &chain_with_or).unwrap(),
+        );
+    }
+
+    #[test]
+    fn maps_need_the_same_ports() {
+        let gates = include_str!("../gates.dat");
+        let (not, and) = (parse_gate(gates, "not"), parse_gate(gates, "and"));
+        assert_eq!(
+            check_equivalence(&not, &and).err(),
+            Some(EquivalenceError::InputCount(1, 2))
+        );
+    }
+}
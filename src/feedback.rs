@@ -0,0 +1,467 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    id::Id,
+    logic_gate::{ConnectionPoint, LogicGate, PrimitiveKind},
+    logic_gate_map::LogicGateMap,
+    truth_table::truth_table,
+};
+
+/// gates whose outputs feed back into their own inputs, found by `feedback_loops`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedbackLoop {
+    /// every gate the loop goes through, in id order
+    pub gates: Vec<Id>,
+    /// whether the loop holds a value like a latch rather than possibly
+    /// oscillating. that is, every way around it inverts an even number of times,
+    /// and every gate in it always either inverts or doesn't
+    pub storage: bool,
+}
+impl FeedbackLoop {
+    /// names each gate by its name in `map`, which is the map the loop was found in,
+    /// or its id if it doesn't have one
+    pub fn describe(&self, map: &LogicGateMap) -> String {
+        let gates = self
+            .gates
+            .iter()
+            .map(|gate| {
+                map.gate_name(*gate)
+                    .map_or_else(|| gate.to_string(), |name| name.to_string())
+            })
+            .collect::<Vec<_>>();
+        let behaviour = if self.storage {
+            "stores a value"
+        } else {
+            "may oscillate"
+        };
+        format!(
+            "feedback loop through gates {} which {behaviour}",
+            gates.join(" ")
+        )
+    }
+}
+
+/// how an output changes when one input goes from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dependence {
+    Same,
+    Inverted,
+    /// it can go either way depending on the other inputs
+    Either,
+}
+impl Dependence {
+    fn combine(a: Option<Self>, b: Self) -> Self {
+        match a {
+            Some(a) if a != b => Dependence::Either,
+            _ => b,
+        }
+    }
+}
+
+/// the strongly connected components of the map's own level, going into each gate
+/// input and out of every output which depends on it. like `validate`, the maps
+/// inside custom gates aren't looked at apart from working out which of their
+/// outputs depend on which inputs, which is done with a truth table so a loop
+/// through a custom gate which doesn't really go through it isn't found
+pub fn feedback_loops(map: &LogicGateMap) -> Vec<FeedbackLoop> {
+    // custom gates are expensive to look inside, so only the ones which could
+    // be in a loop if every output depended on every input are
+    let candidates = Graph::new(map, &HashSet::new())
+        .loops()
+        .into_iter()
+        .flat_map(|feedback| feedback.gates)
+        .collect::<HashSet<_>>();
+    let mut loops = Graph::new(map, &candidates).loops();
+    loops.sort_by(|a, b| a.gates.cmp(&b.gates));
+    loops
+}
+
+/// every pin and signal on the map's level, with an edge along every connection
+/// and through every gate from each input to each output depending on it
+struct Graph {
+    points: Vec<ConnectionPoint>,
+    indices: HashMap<ConnectionPoint, usize>,
+    /// `None` for a connection, which never inverts
+    edges: Vec<Vec<(usize, Option<Dependence>)>>,
+}
+impl Graph {
+    /// custom gates not in `look_inside` have every output depend on every input
+    fn new(map: &LogicGateMap, look_inside: &HashSet<Id>) -> Self {
+        let mut graph = Self {
+            points: vec![],
+            indices: HashMap::new(),
+            edges: vec![],
+        };
+        let mut connections = map.connections().collect::<Vec<_>>();
+        connections.sort_by_key(|(id, _)| *id);
+        for (_, connection) in connections {
+            if [connection.start, connection.end]
+                .iter()
+                .all(|point| map.has_connection_point(point))
+            {
+                graph.add_edge(connection.start, connection.end, None);
+            }
+        }
+        let mut gates = map.gates().collect::<Vec<_>>();
+        gates.sort();
+        for gate in gates {
+            for (input, output, dependence) in dependences(map, gate, look_inside.contains(&gate)) {
+                graph.add_edge(
+                    ConnectionPoint::GateInput { gate, input },
+                    ConnectionPoint::GateOutput { gate, output },
+                    Some(dependence),
+                );
+            }
+        }
+        graph
+    }
+
+    fn index(&mut self, point: ConnectionPoint) -> usize {
+        *self.indices.entry(point).or_insert_with(|| {
+            self.points.push(point);
+            self.edges.push(vec![]);
+            self.points.len() - 1
+        })
+    }
+
+    fn add_edge(
+        &mut self,
+        start: ConnectionPoint,
+        end: ConnectionPoint,
+        dependence: Option<Dependence>,
+    ) {
+        let (start, end) = (self.index(start), self.index(end));
+        self.edges[start].push((end, dependence));
+    }
+
+    fn gate(&self, index: usize) -> Option<Id> {
+        match self.points[index] {
+            ConnectionPoint::GateInput { gate, .. } | ConnectionPoint::GateOutput { gate, .. } => {
+                Some(gate)
+            }
+            _ => None,
+        }
+    }
+
+    fn loops(&self) -> Vec<FeedbackLoop> {
+        self.components()
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1
+                    || self.edges[component[0]]
+                        .iter()
+                        .any(|(end, _)| *end == component[0])
+            })
+            .map(|component| {
+                let mut gates = component
+                    .iter()
+                    .filter_map(|index| self.gate(*index))
+                    .collect::<Vec<_>>();
+                gates.sort();
+                gates.dedup();
+                FeedbackLoop {
+                    storage: self.is_storage(&component),
+                    gates,
+                }
+            })
+            .filter(|feedback| !feedback.gates.is_empty())
+            .collect()
+    }
+
+    /// gives every point in the component whether it's inverted compared to the
+    /// first one, which only works out if no way around the loop inverts an odd
+    /// number of times
+    fn is_storage(&self, component: &[usize]) -> bool {
+        let inside = component.iter().copied().collect::<HashSet<_>>();
+        let mut inverted = HashMap::from([(component[0], false)]);
+        let mut stack = vec![component[0]];
+        while let Some(index) = stack.pop() {
+            for (end, dependence) in &self.edges[index] {
+                if !inside.contains(end) {
+                    continue;
+                }
+                let flip = match dependence {
+                    None | Some(Dependence::Same) => false,
+                    Some(Dependence::Inverted) => true,
+                    Some(Dependence::Either) => return false,
+                };
+                let value = inverted[&index] ^ flip;
+                match inverted.get(end) {
+                    Some(existing) if *existing != value => return false,
+                    Some(_) => {}
+                    None => {
+                        inverted.insert(*end, value);
+                        stack.push(*end);
+                    }
+                }
+            }
+        }
+        true
+    }
+
+    /// Tarjan's algorithm, without recursing as a chain of gates can be
+    /// much deeper than the stack
+    fn components(&self) -> Vec<Vec<usize>> {
+        let count = self.points.len();
+        let mut order = vec![None; count];
+        let mut lowest = vec![0; count];
+        let mut on_stack = vec![false; count];
+        let mut stack = vec![];
+        let mut components = vec![];
+        let mut next_order = 0;
+        for root in 0..count {
+            if order[root].is_some() {
+                continue;
+            }
+            let mut search = vec![(root, 0)];
+            order[root] = Some(next_order);
+            lowest[root] = next_order;
+            next_order += 1;
+            stack.push(root);
+            on_stack[root] = true;
+            while let Some((index, next)) = search.last_mut() {
+                let index = *index;
+                if let Some((end, _)) = self.edges[index].get(*next).copied() {
+                    *next += 1;
+                    match order[end] {
+                        None => {
+                            order[end] = Some(next_order);
+                            lowest[end] = next_order;
+                            next_order += 1;
+                            stack.push(end);
+                            on_stack[end] = true;
+                            search.push((end, 0));
+                        }
+                        Some(end_order) if on_stack[end] => {
+                            lowest[index] = lowest[index].min(end_order);
+                        }
+                        Some(_) => {}
+                    }
+                    continue;
+                }
+                search.pop();
+                if let Some((parent, _)) = search.last() {
+                    lowest[*parent] = lowest[*parent].min(lowest[index]);
+                }
+                if Some(lowest[index]) == order[index] {
+                    let mut component = vec![];
+                    while let Some(member) = stack.pop() {
+                        on_stack[member] = false;
+                        component.push(member);
+                        if member == index {
+                            break;
+                        }
+                    }
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+}
+
+/// which outputs of a gate depend on which inputs straight away. a memory's
+/// data, write enable and clock only change it on a clock edge, so only its
+/// address is counted
+fn dependences(map: &LogicGateMap, gate: Id, look_inside: bool) -> Vec<(Id, Id, Dependence)> {
+    let logic_gate = map.gate_by_id(gate);
+    let every = |inputs: &[Id], dependence| {
+        inputs
+            .iter()
+            .flat_map(|input| {
+                logic_gate
+                    .outputs()
+                    .into_iter()
+                    .map(move |output| (*input, output, dependence))
+            })
+            .collect()
+    };
+    match logic_gate {
+        LogicGate::Nand { .. } => every(&logic_gate.inputs(), Dependence::Inverted),
+        LogicGate::Primitive { kind, inputs, .. } => {
+            let dependence = match kind {
+                PrimitiveKind::And | PrimitiveKind::Or | PrimitiveKind::Buffer => Dependence::Same,
+                PrimitiveKind::Nor | PrimitiveKind::Not => Dependence::Inverted,
+                PrimitiveKind::Xor | PrimitiveKind::Xnor => Dependence::Either,
+            };
+            every(inputs, dependence)
+        }
+        LogicGate::TriState { input, enable, .. } => [
+            every(&[*input], Dependence::Same),
+            every(&[*enable], Dependence::Either),
+        ]
+        .concat(),
+        LogicGate::Clock { .. } => vec![],
+        LogicGate::Rom { inputs, .. } | LogicGate::Ram { inputs, .. } => {
+            let address_width = map
+                .memory(gate)
+                .expect("should have the contents of every memory!")
+                .address_width;
+            every(&inputs[..address_width], Dependence::Either)
+        }
+        LogicGate::Custom { .. } if look_inside => custom_dependences(
+            map.custom_gate(gate)
+                .expect("should have an instance of every custom gate!"),
+        ),
+        LogicGate::Custom { inputs, .. } => every(inputs, Dependence::Either),
+    }
+}
+
+/// compares each pair of rows in the truth table which only differ in one input.
+/// a custom gate that's too wide for a truth table, or that doesn't settle,
+/// has every output depend on every input
+fn custom_dependences(inner: &LogicGateMap) -> Vec<(Id, Id, Dependence)> {
+    let mut inputs = inner.inputs().map(|(id, _)| id).collect::<Vec<_>>();
+    inputs.sort();
+    let mut outputs = inner.outputs().map(|(id, _)| id).collect::<Vec<_>>();
+    outputs.sort();
+    let Ok(table) = truth_table(inner) else {
+        return inputs
+            .iter()
+            .flat_map(|input| {
+                outputs
+                    .iter()
+                    .map(|output| (*input, *output, Dependence::Either))
+            })
+            .collect();
+    };
+    let mut result = vec![];
+    for (i, input) in inputs.iter().enumerate() {
+        let bit = 1 << (inputs.len() - 1 - i);
+        let mut found = vec![None; outputs.len()];
+        for row in 0..1usize << inputs.len() {
+            if row & bit != 0 {
+                continue;
+            }
+            let values = (&table.rows[row].outputs, &table.rows[row | bit].outputs);
+            for (j, found) in found.iter_mut().enumerate() {
+                let change = match values {
+                    (Ok(low), Ok(high)) => match (low[j].to_bool(), high[j].to_bool()) {
+                        (Some(low), Some(high)) if low == high => continue,
+                        (Some(false), Some(true)) => Dependence::Same,
+                        (Some(true), Some(false)) => Dependence::Inverted,
+                        _ if low[j] == high[j] => continue,
+                        _ => Dependence::Either,
+                    },
+                    _ => Dependence::Either,
+                };
+                *found = Some(Dependence::combine(*found, change));
+            }
+        }
+        for (output, found) in outputs.iter().zip(found) {
+            if let Some(dependence) = found {
+                result.push((*input, *output, dependence));
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    fn describe(text: &str, name: &str) -> Vec<String> {
+        let map = parse_gate(text, name);
+        feedback_loops(&map)
+            .iter()
+            .map(|feedback| feedback.describe(&map))
+            .collect()
+    }
+
+    #[test]
+    fn latches_store_a_value() {
+        assert_eq!(
+            describe(include_str!("../gates.dat"), "sr_latch"),
+            ["feedback loop through gates nor_a nor_b which stores a value"]
+        );
+        assert_eq!(
+            describe(
+                "version 0
+define_gate pair
+outputs out
+nots n1 n2
+connections n1 out 0 => n2 in 0, n2 out 0 => n1 in 0, n2 out 0 => out
+",
+                "pair"
+            ),
+            ["feedback loop through gates n1 n2 which stores a value"]
+        );
+    }
+
+    #[test]
+    fn odd_loops_may_oscillate() {
+        assert_eq!(
+            describe(
+                "version 0
+define_gate ring
+outputs out
+nots n1 n2 n3
+connections n1 out 0 => n2 in 0, n2 out 0 => n3 in 0, n3 out 0 => n1 in 0
+connections n3 out 0 => out
+",
+                "ring"
+            ),
+            ["feedback loop through gates n1 n2 n3 which may oscillate"]
+        );
+        // an xor sometimes inverts and sometimes doesn't
+        assert_eq!(
+            describe(
+                "version 0
+define_gate toggle
+inputs a
+outputs out
+xors x
+connections a => x in 0, x out 0 => x in 1, x out 0 => out
+",
+                "toggle"
+            ),
+            ["feedback loop through gates x which may oscillate"]
+        );
+    }
+
+    #[test]
+    fn loops_only_go_through_outputs_which_depend_on_inputs() {
+        let text = "version 0
+define_gate first
+inputs a b
+outputs out
+buffers p
+connections a => p in 0, p out 0 => out
+define_gate looped
+inputs a
+outputs out
+custom_gates g = first
+connections a => g in 0, g out 0 => g in 1, g out 0 => out
+";
+        assert!(describe(text, "looped").is_empty());
+        assert!(describe(include_str!("../gates.dat"), "nor").is_empty());
+    }
+
+    #[test]
+    fn unnamed_gates_are_given_by_id() {
+        let mut map = LogicGateMap::empty();
+        let gates = [map.create_nand_gate(), map.create_nand_gate()];
+        for (from, to) in [(0, 1), (1, 0)] {
+            map.create_connection((
+                gates[from].output_connection(0),
+                gates[to].input_connection(0),
+            ));
+            map.create_connection((
+                gates[from].output_connection(0),
+                gates[to].input_connection(1),
+            ));
+        }
+        let loops = feedback_loops(&map);
+        assert_eq!(loops.len(), 1);
+        let ids = gates.map(|gate| gate.gate_id());
+        assert_eq!(
+            loops[0].describe(&map),
+            format!(
+                "feedback loop through gates {} {} which stores a value",
+                ids[0], ids[1]
+            )
+        );
+    }
+}
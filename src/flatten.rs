@@ -0,0 +1,460 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
+
+use crate::{
+    compiled::SignalOrigin,
+    id::Id,
+    logic_gate::{Bus, ConnectionPoint, LogicGate, PrimitiveKind},
+    logic_gate_map::LogicGateMap,
+};
+
+/// a gate somewhere inside a hierarchical map, found by walking
+/// through the custom gates in `path` like a `SignalOrigin`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GateOrigin {
+    pub path: Vec<Id>,
+    pub gate: Id,
+}
+impl GateOrigin {
+    /// the names of the gates leading to this one, like `sr_latch/nor_a/not_a/n`,
+    /// with the id used for any gate without a name
+    pub fn name(&self, map: &LogicGateMap) -> String {
+        let mut names = vec![];
+        let mut current = map;
+        for gate in self.path.iter().chain([&self.gate]) {
+            names.push(
+                current
+                    .gate_name(*gate)
+                    .map_or_else(|| gate.to_string(), |name| name.to_string()),
+            );
+            if let Some(inner) = current.custom_gate(*gate) {
+                current = inner;
+            }
+        }
+        names.join("/")
+    }
+}
+
+/// a map with only NAND gates and the same top-level inputs and outputs,
+/// see `flatten`
+#[derive(Debug, Clone)]
+pub struct Flattened {
+    pub map: LogicGateMap,
+    /// the gate in the original map that each NAND gate came from.
+    /// a primitive gate turns into several NAND gates with the same origin
+    pub origins: HashMap<Id, GateOrigin>,
+}
+
+/// a gate which can't be made out of NAND gates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlattenError {
+    pub gate: String,
+    pub kind: &'static str,
+}
+impl Display for FlattenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} is a {}, which can't be made from NANDs",
+            self.gate, self.kind
+        )
+    }
+}
+
+/// replaces every custom gate with what's inside it and every primitive gate
+/// with NAND gates. middle signals and the inputs and outputs of custom gates
+/// are wired straight through, so a signal takes fewer steps to get through
+/// the flattened map, but it settles to the same values. tri-states, clocks
+/// and memories can't be flattened. the names of the top-level inputs and
+/// outputs are kept, and signal values are copied where there's a NAND gate
+/// or top-level signal they obviously belong to
+pub fn flatten(map: &LogicGateMap) -> Result<Flattened, FlattenError> {
+    let mut flattener = Flattener {
+        flat: LogicGateMap::empty(),
+        origins: HashMap::new(),
+        sinks: vec![],
+        sources: HashMap::new(),
+        drivers: HashMap::new(),
+    };
+    let mut top_level = HashMap::new();
+    let mut inputs = map.inputs().collect::<Vec<_>>();
+    inputs.sort_by_key(|(id, _)| *id);
+    for (id, value) in inputs {
+        let new_id = flattener.flat.create_input();
+        flattener.flat.set_input(new_id, value);
+        let (point, new_point) = (ConnectionPoint::Input(id), ConnectionPoint::Input(new_id));
+        flattener.sources.insert(origin(&[], point), new_point);
+        top_level.insert(point, new_point);
+    }
+    let mut outputs = map.outputs().collect::<Vec<_>>();
+    outputs.sort_by_key(|(id, _)| *id);
+    for (id, value) in outputs {
+        let new_id = flattener.flat.create_output();
+        let (point, new_point) = (ConnectionPoint::Output(id), ConnectionPoint::Output(new_id));
+        flattener.flat.set_connection_point_value(&new_point, value);
+        flattener.sinks.push((origin(&[], point), vec![new_point]));
+        flattener.sources.insert(origin(&[], point), new_point);
+        top_level.insert(point, new_point);
+    }
+    for (name, bus) in map.signal_names() {
+        let bits = bus
+            .bits()
+            .iter()
+            .map(|bit| top_level.get(bit).copied())
+            .collect::<Option<Vec<_>>>();
+        if let Some(bits) = bits {
+            flattener
+                .flat
+                .set_signal_name(name.to_string(), Bus::new(bits));
+        }
+    }
+
+    flattener.add_map(map, map, &mut vec![])?;
+
+    let Flattener {
+        mut flat,
+        origins,
+        sinks,
+        sources,
+        drivers,
+    } = flattener;
+    for (sink, points) in sinks {
+        // follows the drivers back through anything which isn't a real gate
+        let mut found = vec![];
+        let mut visited = HashSet::new();
+        let mut stack = vec![sink];
+        while let Some(signal) = stack.pop() {
+            for driver in drivers.get(&signal).into_iter().flatten() {
+                match sources.get(driver) {
+                    Some(source) if !found.contains(source) => found.push(*source),
+                    Some(_) => {}
+                    None if visited.insert(driver.clone()) => stack.push(driver.clone()),
+                    None => {}
+                }
+            }
+        }
+        for source in found {
+            for point in &points {
+                flat.create_connection((source, *point));
+            }
+        }
+    }
+    Ok(Flattened { map: flat, origins })
+}
+
+fn origin(path: &[Id], point: ConnectionPoint) -> SignalOrigin {
+    SignalOrigin {
+        path: path.to_vec(),
+        point,
+    }
+}
+
+struct Flattener {
+    flat: LogicGateMap,
+    origins: HashMap<Id, GateOrigin>,
+    /// the points in `flat` that each gate input or top-level output goes to,
+    /// in the order they were added so connections are made in a fixed order
+    sinks: Vec<(SignalOrigin, Vec<ConnectionPoint>)>,
+    /// the point in `flat` for each gate output and top-level input or output
+    sources: HashMap<SignalOrigin, ConnectionPoint>,
+    /// every connection in the original map, from its end to its starts.
+    /// the inputs and outputs of a custom gate are always the ones inside it
+    drivers: HashMap<SignalOrigin, Vec<SignalOrigin>>,
+}
+impl Flattener {
+    fn add_map(
+        &mut self,
+        top: &LogicGateMap,
+        map: &LogicGateMap,
+        path: &mut Vec<Id>,
+    ) -> Result<(), FlattenError> {
+        let mut gates = map.gates().collect::<Vec<_>>();
+        gates.sort();
+        for gate in gates {
+            let unsupported = |kind| FlattenError {
+                gate: GateOrigin {
+                    path: path.clone(),
+                    gate,
+                }
+                .name(top),
+                kind,
+            };
+            match map.gate_by_id(gate) {
+                LogicGate::Nand { .. } | LogicGate::Primitive { .. } => {
+                    self.add_gate(map, path, gate);
+                }
+                LogicGate::Custom { .. } => {
+                    let inner = map
+                        .custom_gate(gate)
+                        .expect("should have an instance of every custom gate!");
+                    path.push(gate);
+                    self.add_map(top, inner, path)?;
+                    path.pop();
+                }
+                LogicGate::TriState { .. } => return Err(unsupported("tri-state buffer")),
+                LogicGate::Clock { .. } => return Err(unsupported("clock")),
+                LogicGate::Rom { .. } => return Err(unsupported("ROM")),
+                LogicGate::Ram { .. } => return Err(unsupported("RAM")),
+            }
+        }
+
+        let inner = |point| match point {
+            ConnectionPoint::GateInput { gate, input }
+                if matches!(map.gate_by_id(gate), LogicGate::Custom { .. }) =>
+            {
+                origin(
+                    &[path.as_slice(), &[gate]].concat(),
+                    ConnectionPoint::Input(input),
+                )
+            }
+            ConnectionPoint::GateOutput { gate, output }
+                if matches!(map.gate_by_id(gate), LogicGate::Custom { .. }) =>
+            {
+                origin(
+                    &[path.as_slice(), &[gate]].concat(),
+                    ConnectionPoint::Output(output),
+                )
+            }
+            point => origin(path, point),
+        };
+        let mut connections = map.connections().collect::<Vec<_>>();
+        connections.sort_by_key(|(id, _)| *id);
+        for (_, connection) in connections {
+            // a dangling connection has nothing to carry, see `Diagnostic::Dangling`
+            if ![connection.start, connection.end]
+                .iter()
+                .all(|point| map.has_connection_point(point))
+            {
+                continue;
+            }
+            self.drivers
+                .entry(inner(connection.end))
+                .or_default()
+                .push(inner(connection.start));
+        }
+        Ok(())
+    }
+
+    /// a NAND gate is copied, and a primitive gate is built out of NAND gates
+    fn add_gate(&mut self, map: &LogicGateMap, path: &[Id], gate: Id) {
+        let logic_gate = map.gate_by_id(gate);
+        let inputs = logic_gate.inputs();
+        let output = logic_gate.outputs()[0];
+        let output_value =
+            map.connection_point_value(&ConnectionPoint::GateOutput { gate, output });
+        let mut builder = NandBuilder {
+            flat: &mut self.flat,
+            inputs: vec![vec![]; inputs.len()],
+            gates: vec![],
+        };
+        let wires = (0..inputs.len()).map(Wire::Input).collect::<Vec<_>>();
+        let result = match logic_gate {
+            LogicGate::Primitive { kind, .. } => builder.primitive(*kind, &wires),
+            _ => builder.nand(wires[0], wires[1]),
+        };
+        let Wire::Nand(result) = result else {
+            panic!("a gate should be made of at least one NAND!");
+        };
+        let NandBuilder {
+            inputs: sinks,
+            gates,
+            ..
+        } = builder;
+
+        for (input, points) in inputs.into_iter().zip(sinks) {
+            let value = map.connection_point_value(&ConnectionPoint::GateInput { gate, input });
+            for point in &points {
+                self.flat.set_connection_point_value(point, value);
+            }
+            self.sinks.push((
+                origin(path, ConnectionPoint::GateInput { gate, input }),
+                points,
+            ));
+        }
+        self.flat.set_connection_point_value(&result, output_value);
+        self.sources.insert(
+            origin(path, ConnectionPoint::GateOutput { gate, output }),
+            result,
+        );
+        for nand in gates {
+            self.origins.insert(
+                nand,
+                GateOrigin {
+                    path: path.to_vec(),
+                    gate,
+                },
+            );
+        }
+    }
+}
+
+/// something that can be fed into a NAND gate while building a primitive gate:
+/// either one of the primitive's inputs, or the output of a NAND gate
+#[derive(Debug, Clone, Copy)]
+enum Wire {
+    Input(usize),
+    Nand(ConnectionPoint),
+}
+
+struct NandBuilder<'a> {
+    flat: &'a mut LogicGateMap,
+    /// the NAND inputs which each of the primitive's inputs goes to
+    inputs: Vec<Vec<ConnectionPoint>>,
+    gates: Vec<Id>,
+}
+impl NandBuilder<'_> {
+    fn nand(&mut self, a: Wire, b: Wire) -> Wire {
+        let info = self.flat.create_nand_gate();
+        self.gates.push(info.gate_id());
+        for (i, wire) in [a, b].into_iter().enumerate() {
+            match wire {
+                Wire::Input(input) => self.inputs[input].push(info.input_connection(i)),
+                Wire::Nand(point) => {
+                    self.flat
+                        .create_connection((point, info.input_connection(i)));
+                }
+            }
+        }
+        Wire::Nand(info.output_connection(0))
+    }
+
+    fn not(&mut self, a: Wire) -> Wire {
+        self.nand(a, a)
+    }
+
+    fn and(&mut self, a: Wire, b: Wire) -> Wire {
+        let nand = self.nand(a, b);
+        self.not(nand)
+    }
+
+    fn or(&mut self, a: Wire, b: Wire) -> Wire {
+        let (a, b) = (self.not(a), self.not(b));
+        self.nand(a, b)
+    }
+
+    fn xor(&mut self, a: Wire, b: Wire) -> Wire {
+        let nand = self.nand(a, b);
+        let (a, b) = (self.nand(a, nand), self.nand(b, nand));
+        self.nand(a, b)
+    }
+
+    fn primitive(&mut self, kind: PrimitiveKind, inputs: &[Wire]) -> Wire {
+        let fold = |builder: &mut Self, f: fn(&mut Self, Wire, Wire) -> Wire| {
+            inputs[1..].iter().fold(inputs[0], |a, b| f(builder, a, *b))
+        };
+        match kind {
+            PrimitiveKind::And => fold(self, Self::and),
+            PrimitiveKind::Or => fold(self, Self::or),
+            PrimitiveKind::Xor => fold(self, Self::xor),
+            PrimitiveKind::Nor => {
+                let or = fold(self, Self::or);
+                self.not(or)
+            }
+            PrimitiveKind::Xnor => {
+                let xor = fold(self, Self::xor);
+                self.not(xor)
+            }
+            PrimitiveKind::Not => self.not(inputs[0]),
+            PrimitiveKind::Buffer => {
+                let not = self.not(inputs[0]);
+                self.not(not)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{logic_gate::Clock, parse::tests::parse_gate, truth_table::TruthTable};
+
+    pub const PRIMITIVES: &str = "version 0
+define_gate primitives
+inputs a b c
+outputs and or not xor xnor nor buffer
+ands and_gate:3
+ors or_gate
+nots not_gate
+xors xor_gate
+xnors xnor_gate
+nors nor_gate
+buffers buffer_gate
+connections a => and_gate in 0, b => and_gate in 1, c => and_gate in 2
+connections a => or_gate in 0, b => or_gate in 1, c => not_gate in 0
+connections a => xor_gate in 0, c => xor_gate in 1, b => xnor_gate in 0, c => xnor_gate in 1
+connections a => nor_gate in 0, c => nor_gate in 1, b => buffer_gate in 0
+connections and_gate out 0 => and, or_gate out 0 => or, not_gate out 0 => not
+connections xor_gate out 0 => xor, xnor_gate out 0 => xnor, nor_gate out 0 => nor
+connections buffer_gate out 0 => buffer
+";
+
+    pub fn assert_same_truth_table(left: &LogicGateMap, right: &LogicGateMap) {
+        let tables = [left, right].map(|map| {
+            map.truth_table()
+                .expect("should be narrow enough for a truth table!")
+        });
+        assert_eq!(tables[0].inputs, tables[1].inputs);
+        assert_eq!(tables[0].outputs, tables[1].outputs);
+        let outputs = |table: &TruthTable| {
+            table
+                .rows
+                .iter()
+                .map(|row| row.outputs.clone().ok())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(outputs(&tables[0]), outputs(&tables[1]));
+        assert!(outputs(&tables[0]).iter().all(|row| row.is_some()));
+    }
+
+    fn only_nands(map: &LogicGateMap) -> bool {
+        map.gates()
+            .all(|gate| matches!(map.gate_by_id(gate), LogicGate::Nand { .. }))
+    }
+
+    #[test]
+    fn flattening_keeps_the_truth_table() {
+        let gates = include_str!("../gates.dat");
+        let maps = ["not", "and", "or", "nor"]
+            .map(|name| parse_gate(gates, name))
+            .into_iter()
+            .chain([parse_gate(PRIMITIVES, "primitives")]);
+        for map in maps {
+            let flattened = map.flatten().expect("should be able to flatten!");
+            assert!(only_nands(&flattened.map));
+            assert_same_truth_table(&map, &flattened.map);
+        }
+    }
+
+    #[test]
+    fn flattened_gates_know_where_they_came_from() {
+        let nor = parse_gate(include_str!("../gates.dat"), "nor");
+        let flattened = nor.flatten().expect("should be able to flatten!");
+        let mut names = flattened
+            .map
+            .gates()
+            .map(|gate| flattened.origins[&gate].name(&nor))
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["and/nand", "and/not/n", "not_a/n", "not_b/n"]);
+    }
+
+    #[test]
+    fn clocks_cant_be_flattened() {
+        let mut map = LogicGateMap::empty();
+        let clock = map.create_clock(Clock {
+            period: 2,
+            duty: 1,
+            phase: 0,
+        });
+        map.set_gate_name(clock.gate_id(), "c".to_string());
+        assert_eq!(
+            map.flatten().err(),
+            Some(FlattenError {
+                gate: "c".to_string(),
+                kind: "clock",
+            })
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use std::{fmt::Display, str::FromStr};
+
+/// the value of a single signal.
+/// a circuit built with the `create_*` functions starts with every signal
+/// at `Zero` or `One` and stays two-valued, but `LogicGateMap::set_unknown`
+/// puts it into a power-on state where every internal signal is `Unknown`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Logic {
+    #[default]
+    Zero,
+    One,
+    /// could be either `Zero` or `One`
+    Unknown,
+    /// not being driven at all
+    HighImpedance,
+}
+impl Logic {
+    /// a `Zero` on either input is enough to know the output,
+    /// and anything else that isn't two `One`s gives `Unknown`
+    pub fn nand(self, other: Self) -> Self {
+        match (self, other) {
+            (Logic::Zero, _) | (_, Logic::Zero) => Logic::One,
+            (Logic::One, Logic::One) => Logic::Zero,
+            _ => Logic::Unknown,
+        }
+    }
+
+    pub fn not(self) -> Self {
+        match self {
+            Logic::Zero => Logic::One,
+            Logic::One => Logic::Zero,
+            Logic::Unknown | Logic::HighImpedance => Logic::Unknown,
+        }
+    }
+
+    /// the output of a tri-state buffer
+    pub fn tri_state(self, enable: Self) -> Self {
+        match (enable, self) {
+            (Logic::Zero, _) => Logic::HighImpedance,
+            (Logic::One, Logic::Zero | Logic::One) => self,
+            _ => Logic::Unknown,
+        }
+    }
+
+    /// the value of a wire driven by both `self` and `other`.
+    /// anything not driving it is ignored, and drivers which disagree give `Unknown`
+    pub fn resolve(self, other: Self) -> Self {
+        match (self, other) {
+            (Logic::HighImpedance, value) | (value, Logic::HighImpedance) => value,
+            (a, b) if a == b => a,
+            _ => Logic::Unknown,
+        }
+    }
+
+    pub fn to_bool(self) -> Option<bool> {
+        match self {
+            Logic::Zero => Some(false),
+            Logic::One => Some(true),
+            Logic::Unknown | Logic::HighImpedance => None,
+        }
+    }
+}
+impl From<bool> for Logic {
+    fn from(value: bool) -> Self {
+        if value { Logic::One } else { Logic::Zero }
+    }
+}
+impl Display for Logic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Logic::Zero => write!(f, "0"),
+            Logic::One => write!(f, "1"),
+            Logic::Unknown => write!(f, "X"),
+            Logic::HighImpedance => write!(f, "Z"),
+        }
+    }
+}
+impl FromStr for Logic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(Logic::Zero),
+            "1" => Ok(Logic::One),
+            "X" | "x" => Ok(Logic::Unknown),
+            "Z" | "z" => Ok(Logic::HighImpedance),
+            _ => Err(format!("{s} isn't 0, 1, X or Z")),
+        }
+    }
+}
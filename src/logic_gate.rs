@@ -1,4 +1,6 @@
-use crate::{id::*, logic_gate_map::LogicGateMap};
+use std::sync::Arc;
+
+use crate::{id::*, logic::Logic};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Connection {
@@ -11,7 +13,56 @@ impl From<(ConnectionPoint, ConnectionPoint)> for Connection {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// a group of single-bit connection points treated as one multi-bit value,
+/// least significant bit first.
+/// slicing and concatenating buses only rearranges which signals are used,
+/// so it doesn't cost any gates or simulation time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bus(Vec<ConnectionPoint>);
+#[allow(unused)]
+impl Bus {
+    pub fn new(bits: Vec<ConnectionPoint>) -> Self {
+        Self(bits)
+    }
+
+    pub fn width(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn bit(&self, index: usize) -> ConnectionPoint {
+        self.0[index]
+    }
+
+    pub fn bits(&self) -> &[ConnectionPoint] {
+        &self.0
+    }
+
+    pub fn slice(&self, range: std::ops::Range<usize>) -> Self {
+        Self(self.0[range].to_vec())
+    }
+
+    /// `self` ends up in the low bits and `other` in the high bits
+    pub fn concat(mut self, other: &Bus) -> Self {
+        self.0.extend_from_slice(&other.0);
+        self
+    }
+}
+impl From<ConnectionPoint> for Bus {
+    fn from(value: ConnectionPoint) -> Self {
+        Self(vec![value])
+    }
+}
+
+/// a connection between two buses of the same width, made of one
+/// `Connection` per bit so that the simulation only ever deals with single bits
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusConnection {
+    pub start: Bus,
+    pub end: Bus,
+    pub connections: Vec<Id>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[allow(unused)]
 pub enum ConnectionPoint {
     Input(Id),
@@ -21,159 +72,259 @@ pub enum ConnectionPoint {
     GateOutput { gate: Id, output: Id },
 }
 
+/// the shape of a gate: what it does and the ids of its pins. this is part of
+/// the definition of the map it's in, so it's shared between every copy of that map,
+/// and each copy keeps its own values for the pins, see `LogicGateMap::connection_point_value`
 #[derive(Debug, Clone)]
 pub enum LogicGate {
     Nand {
-        inputs: [(Id, bool); 2],
-        output: (Id, bool),
+        inputs: [Id; 2],
+        output: Id,
+    },
+    /// passes `input` through while `enable` is on,
+    /// and stops driving its output at all while it's off
+    TriState {
+        input: Id,
+        enable: Id,
+        output: Id,
+    },
+    /// a built-in gate other than NAND, which saves building it out of
+    /// NANDs as a custom gate. `Not` and `Buffer` have a single input,
+    /// and the rest have any number of inputs
+    Primitive {
+        kind: PrimitiveKind,
+        inputs: Vec<Id>,
+        output: Id,
+    },
+    /// a source with no inputs which turns on and off by itself, see `Clock`
+    Clock {
+        clock: Clock,
+        output: Id,
+    },
+    /// a read-only memory, whose outputs are the word at the address on its inputs.
+    /// the contents are kept by each copy of the map, see `LogicGateMap::memory`
+    Rom {
+        inputs: Vec<Id>,
+        outputs: Vec<Id>,
+    },
+    /// a memory which can also be written on the rising edge of its clock
+    Ram {
+        inputs: Vec<Id>,
+        outputs: Vec<Id>,
+    },
+    /// a copy of another map, which each copy of this map has its own instance of,
+    /// see `LogicGateMap::custom_gate`. the pins are that map's inputs and outputs in id order
+    Custom {
+        inputs: Vec<Id>,
+        outputs: Vec<Id>,
     },
-    Custom(LogicGateMap),
 }
 impl LogicGate {
-    pub fn step(&self) -> Self {
-        match self {
-            LogicGate::Nand {
-                inputs: [(id1, v1), (id2, v2)],
-                output: (idq, _),
-            } => Self::Nand {
-                inputs: [(*id1, *v1), (*id2, *v2)],
-                output: (*idq, !(*v1 && *v2)),
-            },
-            LogicGate::Custom(map) => LogicGate::Custom(map.step()),
-        }
+    pub fn get_input_index(&self, id: Id) -> usize {
+        self.inputs()
+            .iter()
+            .position(|input| *input == id)
+            .expect("should be able to find input with that ID!")
     }
 
-    pub fn get_input(&self, id: Id) -> Option<bool> {
+    pub fn input_count(&self) -> usize {
         match self {
-            LogicGate::Nand {
-                inputs: [(id1, v1), (id2, v2)],
-                ..
-            } => {
-                if id == *id1 {
-                    Some(*v1)
-                } else if id == *id2 {
-                    Some(*v2)
-                } else {
-                    None
-                }
-            }
-            LogicGate::Custom(logic_gate_map) => Some(logic_gate_map.input_by_id(id)),
+            LogicGate::Nand { .. } | LogicGate::TriState { .. } => 2,
+            LogicGate::Primitive { inputs, .. }
+            | LogicGate::Rom { inputs, .. }
+            | LogicGate::Ram { inputs, .. }
+            | LogicGate::Custom { inputs, .. } => inputs.len(),
+            LogicGate::Clock { .. } => 0,
         }
     }
 
-    pub fn get_output(&self, id: Id) -> Option<bool> {
-        match self {
-            LogicGate::Nand {
-                output: (idq, q), ..
-            } => (id == *idq).then_some(*q),
-            LogicGate::Custom(logic_gate_map) => Some(logic_gate_map.output_by_id(id)),
-        }
+    pub fn get_output_index(&self, id: Id) -> usize {
+        self.outputs()
+            .iter()
+            .position(|output| *output == id)
+            .expect("should be able to find output with that ID!")
     }
 
-    pub fn set_input(&mut self, id: Id, new_value: bool) {
+    pub fn output_count(&self) -> usize {
         match self {
-            LogicGate::Nand {
-                inputs: [(id1, v1), (id2, v2)],
-                ..
-            } => {
-                if id == *id1 {
-                    *v1 = new_value;
-                } else if id == *id2 {
-                    *v2 = new_value;
-                }
-            }
-            LogicGate::Custom(logic_gate_map) => {
-                logic_gate_map.set_input(id, new_value);
-            }
+            LogicGate::Nand { .. }
+            | LogicGate::TriState { .. }
+            | LogicGate::Primitive { .. }
+            | LogicGate::Clock { .. } => 1,
+            LogicGate::Rom { outputs, .. }
+            | LogicGate::Ram { outputs, .. }
+            | LogicGate::Custom { outputs, .. } => outputs.len(),
         }
     }
 
-    pub fn set_output(&mut self, id: Id, new_value: bool) {
-        match self {
-            LogicGate::Nand {
-                output: (idq, q), ..
-            } => {
-                if id == *idq {
-                    *q = new_value
-                }
-            }
-            LogicGate::Custom(logic_gate_map) => {
-                logic_gate_map.set_output(id, new_value);
-            }
-        }
+    /// in id order, which is the order they were created in
+    pub fn inputs(&self) -> Vec<Id> {
+        let mut inputs = match self {
+            LogicGate::Nand { inputs, .. } => inputs.to_vec(),
+            LogicGate::TriState { input, enable, .. } => vec![*input, *enable],
+            LogicGate::Primitive { inputs, .. }
+            | LogicGate::Rom { inputs, .. }
+            | LogicGate::Ram { inputs, .. }
+            | LogicGate::Custom { inputs, .. } => inputs.clone(),
+            LogicGate::Clock { .. } => vec![],
+        };
+        inputs.sort();
+        inputs
     }
 
-    pub fn get_input_index(&self, id: Id) -> usize {
-        match self {
-            LogicGate::Nand {
-                inputs: [(id1, _), (_, _)],
-                ..
-            } => {
-                if id == *id1 {
-                    0
-                } else {
-                    1
-                }
-            }
-            LogicGate::Custom(logic_gate_map) => {
-                let mut ids = logic_gate_map.inputs().map(|x| x.0).collect::<Vec<_>>();
-                ids.sort();
-                ids.iter()
-                    .enumerate()
-                    .find(|(_, x)| **x == id)
-                    .map(|(i, _)| i)
-                    .expect("should be able to find input with that ID!")
-            }
+    pub fn outputs(&self) -> Vec<Id> {
+        let mut outputs = match self {
+            LogicGate::Nand { output, .. }
+            | LogicGate::TriState { output, .. }
+            | LogicGate::Primitive { output, .. }
+            | LogicGate::Clock { output, .. } => vec![*output],
+            LogicGate::Rom { outputs, .. }
+            | LogicGate::Ram { outputs, .. }
+            | LogicGate::Custom { outputs, .. } => outputs.clone(),
+        };
+        outputs.sort();
+        outputs
+    }
+}
+
+/// the settings for a `LogicGate::Clock`.
+/// the clock repeats every `period` ticks, and is on for the first `duty`
+/// ticks of each period. `phase` shifts it forwards by that many ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Clock {
+    pub period: u32,
+    pub duty: u32,
+    pub phase: u32,
+}
+impl Clock {
+    pub fn value(&self, tick: u64) -> Logic {
+        ((tick + self.phase as u64) % (self.period as u64) < self.duty as u64).into()
+    }
+
+    /// how many times it turns on per tick
+    pub fn frequency(&self) -> f32 {
+        1.0 / self.period as f32
+    }
+}
+
+/// the contents of a `LogicGate::Rom` or `LogicGate::Ram` in one copy of a map.
+/// the inputs are the address, least significant bit first, and a RAM then
+/// has the data to write, the write enable and the clock.
+/// the outputs are the word at the address, and there is a word in `contents`
+/// for every address. the contents are shared between copies of the memory
+/// until one of them is written to
+#[derive(Debug, Clone)]
+pub struct Memory {
+    pub address_width: usize,
+    data_width: usize,
+    pub contents: Arc<Vec<u64>>,
+    /// the clock's value when the memory was last stepped, to find rising edges
+    pub last_clock: Logic,
+}
+impl Memory {
+    /// `contents` is padded with zeros up to one word for every address
+    pub fn new(address_width: usize, data_width: usize, mut contents: Vec<u64>) -> Self {
+        let mask = u64::MAX >> (64 - data_width);
+        contents.resize(1 << address_width, 0);
+        for word in &mut contents {
+            *word &= mask;
+        }
+        Self {
+            address_width,
+            data_width,
+            contents: Arc::new(contents),
+            last_clock: Logic::Zero,
         }
     }
 
-    pub fn input_count(&self) -> usize {
-        match self {
-            LogicGate::Nand { .. } => 2,
-            LogicGate::Custom(map) => map.inputs().count(),
+    pub fn data_width(&self) -> usize {
+        self.data_width
+    }
+
+    /// the largest value a word can hold
+    pub fn word_mask(&self) -> u64 {
+        u64::MAX >> (64 - self.data_width())
+    }
+
+    /// the bit at `bit` of the word at `address`, which is every input
+    /// up to `address_width`. reading from an address which isn't known gives `Unknown`
+    pub fn read(&self, address: impl Iterator<Item = Logic>, bit: usize) -> Logic {
+        let address = address
+            .take(self.address_width)
+            .enumerate()
+            .try_fold(0, |value, (i, bit)| {
+                Some(value | (bit.to_bool()? as usize) << i)
+            });
+        match address {
+            Some(address) => (self.contents[address] & (1 << bit) != 0).into(),
+            None => Logic::Unknown,
         }
     }
-    pub fn get_output_index(&self, id: Id) -> usize {
-        match self {
-            LogicGate::Nand { .. } => 0,
-            LogicGate::Custom(logic_gate_map) => {
-                let mut ids = logic_gate_map.outputs().map(|x| x.0).collect::<Vec<_>>();
-                ids.sort();
-                ids.iter()
-                    .enumerate()
-                    .find(|(_, x)| **x == id)
-                    .map(|(i, _)| i)
-                    .expect("should be able to find input with that ID!")
-            }
+
+    /// writes the data inputs to the address on a rising edge of the clock
+    /// while write enable is on. `input` gives the value of each input.
+    /// a write with an address or data that isn't known is ignored, as there's
+    /// nowhere to keep an `Unknown` bit. returns whether the contents changed
+    pub fn clock(&mut self, input: impl Fn(usize) -> Logic) -> bool {
+        let data_width = self.data_width();
+        let clock = input(self.address_width + data_width + 1);
+        let rising = self.last_clock == Logic::Zero && clock == Logic::One;
+        self.last_clock = clock;
+        if !rising || input(self.address_width + data_width) != Logic::One {
+            return false;
         }
+        let value = |range: std::ops::Range<usize>| {
+            range.enumerate().try_fold(0, |value, (i, pin)| {
+                Some(value | (input(pin).to_bool()? as u64) << i)
+            })
+        };
+        let address = value(0..self.address_width);
+        let data = value(self.address_width..self.address_width + data_width);
+        let (Some(address), Some(data)) = (address, data) else {
+            return false;
+        };
+        if self.contents[address as usize] == data {
+            return false;
+        }
+        Arc::make_mut(&mut self.contents)[address as usize] = data;
+        true
     }
+}
 
-    pub fn output_count(&self) -> usize {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrimitiveKind {
+    And,
+    Or,
+    Not,
+    Xor,
+    Xnor,
+    Nor,
+    Buffer,
+}
+impl PrimitiveKind {
+    /// whether a gate of this kind can have `count` inputs
+    pub fn allows_input_count(self, count: usize) -> bool {
         match self {
-            LogicGate::Nand { .. } => 1,
-            LogicGate::Custom(map) => map.outputs().count(),
+            PrimitiveKind::Not | PrimitiveKind::Buffer => count == 1,
+            _ => count >= 2,
         }
     }
 
-    pub fn inputs(&self) -> Vec<(Id, bool)> {
-        let mut inputs = match self {
-            LogicGate::Nand { inputs, .. } => {
-                inputs.iter().map(|(a, b)| (*a, *b)).collect::<Vec<_>>()
-            }
-            LogicGate::Custom(logic_gate_map) => logic_gate_map.inputs().collect::<Vec<_>>(),
-        };
-        inputs.sort_by_key(|(a, _)| *a);
-        inputs
-    }
-    pub fn outputs(&self) -> Vec<(Id, bool)> {
-        let mut outputs = match self {
-            LogicGate::Nand { output, .. } => {
-                vec![*output]
-            }
-            LogicGate::Custom(logic_gate_map) => logic_gate_map.outputs().collect::<Vec<_>>(),
-        };
-        outputs.sort_by_key(|(a, _)| *a);
-        outputs
+    /// like `Logic::nand`, a single input can be enough to know the output,
+    /// and otherwise anything which isn't `Zero` or `One` gives `Unknown`
+    pub fn evaluate(self, inputs: impl Iterator<Item = Logic>) -> Logic {
+        match self {
+            PrimitiveKind::And => inputs.fold(Logic::One, |a, b| a.nand(b).not()),
+            PrimitiveKind::Or => inputs.fold(Logic::Zero, |a, b| a.not().nand(b.not())),
+            PrimitiveKind::Nor => PrimitiveKind::Or.evaluate(inputs).not(),
+            PrimitiveKind::Xor => inputs.fold(Logic::Zero, |a, b| match (a, b) {
+                (Logic::Zero | Logic::One, Logic::Zero | Logic::One) => (a != b).into(),
+                _ => Logic::Unknown,
+            }),
+            PrimitiveKind::Xnor => PrimitiveKind::Xor.evaluate(inputs).not(),
+            PrimitiveKind::Not => inputs.fold(Logic::Unknown, |_, b| b.not()),
+            PrimitiveKind::Buffer => inputs.fold(Logic::Unknown, |_, b| b.not().not()),
+        }
     }
 }
 
@@ -219,6 +370,14 @@ impl GateCreationInfo {
     pub fn output_count(&self) -> usize {
         self.outputs.len()
     }
+
+    pub fn input_bus(&self, range: std::ops::Range<usize>) -> Bus {
+        Bus::new(range.map(|i| self.input_connection(i)).collect())
+    }
+
+    pub fn output_bus(&self, range: std::ops::Range<usize>) -> Bus {
+        Bus::new(range.map(|i| self.output_connection(i)).collect())
+    }
 }
 
 #[macro_export]
@@ -283,3 +442,117 @@ macro_rules! gate {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{logic_gate_map::LogicGateMap, parse::tests::parse_gate};
+
+    const RAM: &str = "version 0
+define_gate memory
+inputs address:2 data:4 write clock
+outputs q:4
+rams m:2:4
+connections address => m in 0..2, data => m in 2..6, write => m in 6, clock => m in 7
+connections m out 0..4 => q
+";
+
+    fn bits(value: u64, width: usize) -> impl Iterator<Item = Logic> {
+        (0..width).map(move |i| Logic::from(value & (1 << i) != 0))
+    }
+
+    #[test]
+    fn memories_read_the_word_at_their_address() {
+        let mut map = LogicGateMap::empty();
+        let rom = map.create_rom(2, 4, vec![0x1, 0x2, 0xf]);
+        let memory = map
+            .memory(rom.gate_id())
+            .expect("should have made a memory!");
+        // the contents are padded out to every address
+        assert_eq!(*memory.contents, vec![0x1, 0x2, 0xf, 0x0]);
+        for (address, word) in memory.contents.iter().enumerate() {
+            for bit in 0..4 {
+                assert_eq!(
+                    memory.read(bits(address as u64, 2), bit),
+                    (word & (1 << bit) != 0).into()
+                );
+            }
+        }
+        let unknown = [Logic::One, Logic::Unknown].into_iter();
+        assert_eq!(memory.read(unknown, 0), Logic::Unknown);
+    }
+
+    #[test]
+    fn roms_are_read_through_the_map() {
+        let mut map = LogicGateMap::empty();
+        let address = map.create_input_bus(2);
+        let address = map.bus_by_id(address).clone();
+        let data = map.create_output_bus(4);
+        let data = map.bus_by_id(data).clone();
+        let rom = map.create_rom(2, 4, vec![0x3, 0x5, 0x9, 0xc]);
+        map.create_bus_connection(address.clone(), rom.input_bus(0..2));
+        map.create_bus_connection(rom.output_bus(0..4), data.clone());
+        for (value, word) in [0x3, 0x5, 0x9, 0xc].into_iter().enumerate() {
+            map.set_bus_value(&address, value as u64);
+            assert!(map.settle(10).is_ok());
+            assert_eq!(map.bus_value(&data), Some(word));
+        }
+    }
+
+    #[test]
+    fn rams_write_on_a_rising_clock_while_enabled() {
+        let mut map = parse_gate(RAM, "memory");
+        let bus = |map: &LogicGateMap, name: &str| {
+            map.signal_by_name(name)
+                .expect("should have the signal!")
+                .clone()
+        };
+        let (address, data, write, clock, q) = (
+            bus(&map, "address"),
+            bus(&map, "data"),
+            bus(&map, "write"),
+            bus(&map, "clock"),
+            bus(&map, "q"),
+        );
+        let cycle = |map: &mut LogicGateMap, address_value, data_value, enabled| {
+            map.set_bus_value(&address, address_value);
+            map.set_bus_value(&data, data_value);
+            map.set_bus_value(&write, enabled);
+            for edge in [0, 1, 0] {
+                map.set_bus_value(&clock, edge);
+                assert!(map.settle(10).is_ok());
+            }
+            map.bus_value(&q)
+        };
+        assert_eq!(cycle(&mut map, 2, 0x9, 1), Some(0x9));
+        // nothing is written without write enable
+        assert_eq!(cycle(&mut map, 2, 0x3, 0), Some(0x9));
+        assert_eq!(cycle(&mut map, 1, 0x3, 0), Some(0x0));
+        assert_eq!(cycle(&mut map, 1, 0x6, 1), Some(0x6));
+
+        let ram = map.gate_by_name("m").expect("should have a ram called m!");
+        let memory = map.memory(ram).expect("should be a memory!");
+        assert_eq!(*memory.contents, vec![0x0, 0x6, 0x9, 0x0]);
+    }
+
+    #[test]
+    fn writes_with_unknown_data_are_ignored() {
+        let mut map = LogicGateMap::empty();
+        let ram = map.create_ram(1, 2, vec![0x2, 0x1]);
+        let memory = map
+            .memory_mut(ram.gate_id())
+            .expect("should have made a memory!");
+        // address 1, data of one unknown bit, write enable and then the clock
+        let mut inputs = [
+            Logic::One,
+            Logic::Unknown,
+            Logic::One,
+            Logic::One,
+            Logic::Zero,
+        ];
+        assert!(!memory.clock(|i| inputs[i]));
+        inputs[4] = Logic::One;
+        assert!(!memory.clock(|i| inputs[i]));
+        assert_eq!(*memory.contents, vec![0x2, 0x1]);
+    }
+}
@@ -1,124 +1,607 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display, sync::Arc};
 
 use crate::{
-    create_connection, create_custom_gate, create_input, create_nand_gate, create_output, gate,
+    compiled::{CompiledMap, Contention, Oscillation, SignalOrigin},
+    create_connection, create_custom_gate, create_input, create_nand_gate, create_output,
+    equivalence::{Equivalence, EquivalenceError, check_equivalence},
+    feedback::{FeedbackLoop, feedback_loops},
+    flatten::{FlattenError, Flattened, flatten},
+    gate,
     id::{Id, IdGenerator},
-    logic_gate::{Connection, ConnectionPoint, GateCreationInfo, LogicGate},
+    logic::Logic,
+    logic_gate::{
+        Bus, BusConnection, Clock, Connection, ConnectionPoint, GateCreationInfo, LogicGate,
+        Memory, PrimitiveKind,
+    },
+    optimise::{OptimiseError, Optimised, optimise},
+    path::{PathError, name, resolve},
     point,
+    snapshot::{Snapshot, SnapshotError, restore, take},
+    statistics::{Statistics, statistics},
+    truth_table::{TooManyInputs, TruthTable, truth_table},
+    validate::{Diagnostic, validate},
 };
 
+/// the most address bits a ROM or RAM can have, which is 64K words
+pub const MAX_ADDRESS_WIDTH: usize = 16;
+/// the longest delay a gate can have, as the timed scheduler
+/// has a slot for every tick up to the longest one
+pub const MAX_DELAY: u32 = 1024;
+/// the widest a bus can be, so its value fits in a `u64`
+pub const MAX_BUS_WIDTH: usize = 64;
+
+/// a map holds the values of its own signals and gate pins, the contents of its
+/// memories and its own instance of every custom gate in it. the gates, connections,
+/// names and delays are its definition, a `MapStructure` shared between every copy
+/// of it, so using a definition as a custom gate many times, or stepping a map,
+/// only copies values, and a memory's contents are only copied once they're written
 #[derive(Debug, Clone)]
 pub struct LogicGateMap {
-    inputs: HashMap<Id, bool>,
-    outputs: HashMap<Id, bool>,
-    middle_signals: HashMap<Id, bool>,
+    inputs: HashMap<Id, Logic>,
+    outputs: HashMap<Id, Logic>,
+    middle_signals: HashMap<Id, Logic>,
+    /// the value on every pin of every gate which isn't a custom gate,
+    /// as the pins of a custom gate are the inputs and outputs of its instance
+    pins: HashMap<Id, Logic>,
+    /// the contents of every ROM and RAM
+    memories: HashMap<Id, Memory>,
+    /// this copy's instance of every custom gate
+    custom_gates: HashMap<Id, LogicGateMap>,
+    structure: Arc<MapStructure>,
+    /// how many times this map has been stepped, which is what clocks go off of
+    clock_tick: u64,
+    /// signals at any depth which are held at a value, see `force`
+    forced: HashMap<SignalOrigin, Logic>,
+}
+
+/// the part of a `LogicGateMap` which doesn't change while it's simulated.
+/// editing a map which shares its structure gives it its own copy first
+#[derive(Debug, Clone)]
+struct MapStructure {
     gates: HashMap<Id, LogicGate>,
     connections: HashMap<Id, Connection>,
+    buses: HashMap<Id, Bus>,
+    bus_connections: HashMap<Id, BusConnection>,
+    /// how many ticks each gate takes to update its output when using
+    /// `Scheduler::Timed`. gates without one use `default_delay`,
+    /// or the default of the map this one is inside of
+    delays: HashMap<Id, u32>,
+    default_delay: Option<u32>,
+    /// the name of the definition this map was made from, which
+    /// every custom gate made from it shares
+    name: Option<String>,
+    /// set by `expect_feedback` in a definition, so `validate` doesn't warn about
+    /// feedback loops which store a value
+    expects_feedback: bool,
+    /// set by `nand_only` in a definition, for teaching, so only nands,
+    /// clocks and custom gates can be used in it
+    nand_only: bool,
+    /// names given to gates and signals, for finding them with `resolve_path`.
+    /// a single signal is named as a bus of width 1
+    gate_names: HashMap<String, Id>,
+    signal_names: HashMap<String, Bus>,
     id_generator: IdGenerator,
 }
+
 impl LogicGateMap {
     pub fn empty() -> Self {
         Self {
             inputs: HashMap::new(),
             outputs: HashMap::new(),
             middle_signals: HashMap::new(),
-            gates: HashMap::new(),
-            connections: HashMap::new(),
-            id_generator: IdGenerator::new(),
+            pins: HashMap::new(),
+            memories: HashMap::new(),
+            custom_gates: HashMap::new(),
+            structure: Arc::new(MapStructure {
+                gates: HashMap::new(),
+                connections: HashMap::new(),
+                buses: HashMap::new(),
+                bus_connections: HashMap::new(),
+                delays: HashMap::new(),
+                default_delay: None,
+                name: None,
+                expects_feedback: false,
+                nand_only: false,
+                gate_names: HashMap::new(),
+                signal_names: HashMap::new(),
+                id_generator: IdGenerator::new(),
+            }),
+            clock_tick: 0,
+            forced: HashMap::new(),
         }
     }
 
+    fn structure_mut(&mut self) -> &mut MapStructure {
+        Arc::make_mut(&mut self.structure)
+    }
+
+    #[allow(unused)]
     pub fn step(&self) -> Self {
-        let mut new_map = self.clone();
-        for (id, gate) in &mut new_map.gates {
-            *gate = self.gates[id].step();
-        }
-        for Connection { start, end } in self.connections.values() {
-            let input_value = self.connection_point_value(start);
-            match *end {
-                ConnectionPoint::Input(id) => {
-                    new_map.inputs.insert(id, input_value);
+        let mut new_map = Self {
+            inputs: self.inputs.clone(),
+            outputs: self.outputs.clone(),
+            middle_signals: self.middle_signals.clone(),
+            pins: self.pins.clone(),
+            memories: self.memories.clone(),
+            custom_gates: self
+                .custom_gates
+                .iter()
+                .map(|(id, inner)| (*id, inner.step()))
+                .collect(),
+            structure: Arc::clone(&self.structure),
+            clock_tick: self.clock_tick + 1,
+            forced: self.forced.clone(),
+        };
+        let pin = |id: &Id| self.pins[id];
+        for (id, gate) in &self.structure.gates {
+            match gate {
+                LogicGate::Nand {
+                    inputs: [a, b],
+                    output,
+                } => {
+                    new_map.pins.insert(*output, pin(a).nand(pin(b)));
+                }
+                LogicGate::TriState {
+                    input,
+                    enable,
+                    output,
+                } => {
+                    new_map
+                        .pins
+                        .insert(*output, pin(input).tri_state(pin(enable)));
                 }
-                // NOTE: maybe this ^^^ should be some sort of error??
-                ConnectionPoint::Output(id) => {
-                    new_map.outputs.insert(id, input_value);
+                LogicGate::Primitive {
+                    kind,
+                    inputs,
+                    output,
+                } => {
+                    new_map
+                        .pins
+                        .insert(*output, kind.evaluate(inputs.iter().map(pin)));
                 }
-                ConnectionPoint::MiddleSignal(id) => {
-                    new_map.middle_signals.insert(id, input_value);
+                LogicGate::Clock { clock, output } => {
+                    new_map
+                        .pins
+                        .insert(*output, clock.value(new_map.clock_tick));
                 }
-                ConnectionPoint::GateInput { gate, input } => new_map
-                    .gates
-                    .get_mut(&gate)
-                    .expect("should be able to get gate by ID!")
-                    .set_input(input, input_value),
-                ConnectionPoint::GateOutput { gate, output } => new_map
-                    .gates
-                    .get_mut(&gate)
-                    .expect("should be able to get gate by ID")
-                    .set_output(output, input_value), // NOTE: maybe this ^^^ shuold be some sort of error??
+                LogicGate::Rom { inputs, outputs } | LogicGate::Ram { inputs, outputs } => {
+                    let memory = new_map
+                        .memories
+                        .get_mut(id)
+                        .expect("should have the contents of every memory!");
+                    if matches!(gate, LogicGate::Ram { .. }) {
+                        memory.clock(|i| pin(&inputs[i]));
+                    }
+                    // reading happens after writing, so a write shows up on the outputs straight away
+                    for (bit, output) in outputs.iter().enumerate() {
+                        new_map
+                            .pins
+                            .insert(*output, memory.read(inputs.iter().map(pin), bit));
+                    }
+                }
+                // already stepped along with the rest of the values
+                LogicGate::Custom { .. } => {}
             }
         }
+        // every connection driving the same point is resolved together,
+        // so a wire shared between tri-state buffers takes the enabled one's value
+        let mut resolved = HashMap::new();
+        for Connection { start, end } in self.structure.connections.values() {
+            let input_value = self.connection_point_value(start);
+            resolved
+                .entry(*end)
+                .and_modify(|value: &mut Logic| *value = value.resolve(input_value))
+                .or_insert(input_value);
+        }
+        for (end, input_value) in resolved {
+            new_map.set_connection_point_value(&end, input_value);
+        }
+        for (origin, value) in &self.forced {
+            new_map
+                .inner_map_mut(&origin.path)
+                .set_connection_point_value(&origin.point, *value);
+        }
         new_map
     }
 
-    pub fn connection_point_value(&self, connection_point: &ConnectionPoint) -> bool {
+    pub fn connection_point_value(&self, connection_point: &ConnectionPoint) -> Logic {
         match connection_point {
-            ConnectionPoint::GateInput { gate, input } => self.gates[gate]
-                .get_input(*input)
-                .expect("should be able to get input!"),
-            ConnectionPoint::GateOutput { gate, output } => self.gates[gate]
-                .get_output(*output)
-                .expect("should be able to get output!"),
+            ConnectionPoint::GateInput { gate, input } => match self.custom_gates.get(gate) {
+                Some(inner) => inner.input_by_id(*input),
+                None => self.pins[input],
+            },
+            ConnectionPoint::GateOutput { gate, output } => match self.custom_gates.get(gate) {
+                Some(inner) => inner.output_by_id(*output),
+                None => self.pins[output],
+            },
             ConnectionPoint::Input(id) => self.inputs[id],
             ConnectionPoint::Output(id) => self.outputs[id],
             ConnectionPoint::MiddleSignal(id) => self.middle_signals[id],
         }
     }
+
+    pub fn set_connection_point_value(&mut self, connection_point: &ConnectionPoint, value: Logic) {
+        match connection_point {
+            ConnectionPoint::GateInput { gate, input } => match self.custom_gates.get_mut(gate) {
+                Some(inner) => inner.set_input(*input, value),
+                None => {
+                    self.pins.insert(*input, value);
+                }
+            },
+            ConnectionPoint::GateOutput { gate, output } => match self.custom_gates.get_mut(gate) {
+                Some(inner) => inner.set_output(*output, value),
+                None => {
+                    self.pins.insert(*output, value);
+                }
+            },
+            ConnectionPoint::Input(id) => self.set_input(*id, value),
+            ConnectionPoint::Output(id) => self.set_output(*id, value),
+            ConnectionPoint::MiddleSignal(id) => {
+                self.middle_signals.insert(*id, value);
+            }
+        }
+    }
+
+    /// the value of a bus as an unsigned number, least significant bit first,
+    /// or `None` if any of its bits aren't `Zero` or `One`
+    pub fn bus_value(&self, bus: &Bus) -> Option<u64> {
+        bus.bits()
+            .iter()
+            .enumerate()
+            .try_fold(0, |value, (i, bit)| {
+                Some(value | (self.connection_point_value(bit).to_bool()? as u64) << i)
+            })
+    }
+
+    pub fn set_bus_value(&mut self, bus: &Bus, value: u64) {
+        for (i, bit) in bus.bits().iter().enumerate() {
+            self.set_connection_point_value(bit, (value & (1 << i) != 0).into());
+        }
+    }
+
+    /// puts every signal apart from the top-level inputs into the `Unknown` state,
+    /// like the circuit has just been powered on
+    #[allow(unused)]
+    pub fn set_unknown(&mut self) {
+        let mut compiled = self.compile();
+        compiled.set_unknown();
+        compiled.store(self);
+    }
+
+    /// every point at any depth being driven by more than one enabled driver
+    /// which disagree
+    #[allow(unused)]
+    pub fn contentions(&self) -> Vec<Contention> {
+        self.compile().contentions()
+    }
+
+    /// every signal at any depth which is `Unknown`, which should be empty
+    /// after a circuit has been reset from `set_unknown`
+    #[allow(unused)]
+    pub fn unknown_signals(&self) -> Vec<SignalOrigin> {
+        self.compile().unknown_signals()
+    }
+
+    /// steps until nothing changes, see `CompiledMap::settle`
+    #[allow(unused)]
+    pub fn settle(&mut self, max_steps: usize) -> Result<usize, Oscillation> {
+        let mut compiled = self.compile();
+        let result = compiled.settle(max_steps);
+        compiled.store(self);
+        result
+    }
+
+    /// finds anything wired up wrongly, see `validate::validate`
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        validate(self)
+    }
+
+    /// whether this map and `other` give the same outputs for the same inputs,
+    /// see `equivalence::check_equivalence`
+    pub fn check_equivalence(&self, other: &LogicGateMap) -> Result<Equivalence, EquivalenceError> {
+        check_equivalence(self, other)
+    }
+
+    /// the same circuit made only of NAND gates, see `flatten::flatten`
+    pub fn flatten(&self) -> Result<Flattened, FlattenError> {
+        flatten(self)
+    }
+
+    /// flattens the map and takes out any gates that aren't needed, see `optimise::optimise`
+    pub fn optimise(&self) -> Result<Optimised, OptimiseError> {
+        optimise(self)
+    }
+
+    /// settles the map for every combination of its inputs, see `truth_table::truth_table`
+    pub fn truth_table(&self) -> Result<TruthTable, TooManyInputs> {
+        truth_table(self)
+    }
+
+    /// finds a signal by the names of the gates leading to it, like
+    /// `d_latch/sr_latch/nor_a.out0`, see `path::resolve`
+    #[allow(unused)]
+    pub fn resolve_path(&self, path: &str) -> Result<SignalOrigin, PathError> {
+        resolve(self, path)
+    }
+
+    /// every loop of gates on this map's level, see `feedback::feedback_loops`
+    #[allow(unused)]
+    pub fn feedback_loops(&self) -> Vec<FeedbackLoop> {
+        feedback_loops(self)
+    }
+
+    /// gate counts, logic depth and the critical path, see `statistics::statistics`
+    pub fn statistics(&self) -> Statistics {
+        statistics(self)
+    }
+
+    /// every value at every depth, to go back to with `restore`
+    pub fn snapshot(&self) -> Snapshot {
+        take(self)
+    }
+
+    /// puts back the values from `snapshot`, see `snapshot::restore`
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        restore(self, snapshot)
+    }
+
+    /// a path which `resolve_path` turns back into `origin`, see `path::name`
+    pub fn origin_name(&self, origin: &SignalOrigin) -> String {
+        name(self, origin)
+    }
+
+    /// the current value of the signal at `path`
+    #[allow(unused)]
+    pub fn probe(&self, path: &str) -> Result<Logic, PathError> {
+        let origin = self.resolve_path(path)?;
+        Ok(self
+            .inner_map(&origin.path)
+            .connection_point_value(&origin.point))
+    }
+
+    /// holds the signal at `path` at `value`, whatever is driving it,
+    /// until it's released. this is also kept when compiling
+    #[allow(unused)]
+    pub fn force(&mut self, path: &str, value: Logic) -> Result<(), PathError> {
+        let origin = self.resolve_path(path)?;
+        self.inner_map_mut(&origin.path)
+            .set_connection_point_value(&origin.point, value);
+        self.forced.insert(origin, value);
+        Ok(())
+    }
+
+    /// stops forcing the signal at `path`, which then goes back to
+    /// whatever is driving it on the next step
+    #[allow(unused)]
+    pub fn release(&mut self, path: &str) -> Result<(), PathError> {
+        let origin = self.resolve_path(path)?;
+        self.forced.remove(&origin);
+        Ok(())
+    }
+
+    /// compiles this map into a flat netlist which can be stepped in place.
+    /// see `CompiledMap` for details
+    pub fn compile(&self) -> CompiledMap {
+        CompiledMap::new(self)
+    }
 }
 impl LogicGateMap {
-    pub fn inputs(&self) -> impl Iterator<Item = (Id, bool)> {
+    pub fn inputs(&self) -> impl Iterator<Item = (Id, Logic)> {
         self.inputs.iter().map(|(a, b)| (*a, *b))
     }
-    pub fn inputs_mut(&mut self) -> impl Iterator<Item = (Id, &mut bool)> {
+    #[allow(unused)]
+    pub fn inputs_mut(&mut self) -> impl Iterator<Item = (Id, &mut Logic)> {
         self.inputs.iter_mut().map(|(a, b)| (*a, b))
     }
 
-    pub fn input_by_id(&self, id: Id) -> bool {
+    pub fn input_by_id(&self, id: Id) -> Logic {
         self.inputs[&id]
     }
 
-    pub fn set_input(&mut self, id: Id, initial_value: bool) {
+    pub fn set_input(&mut self, id: Id, initial_value: Logic) {
         self.inputs.insert(id, initial_value);
     }
 
-    pub fn outputs(&self) -> impl Iterator<Item = (Id, bool)> {
+    pub fn outputs(&self) -> impl Iterator<Item = (Id, Logic)> {
         self.outputs.iter().map(|(a, b)| (*a, *b))
     }
 
-    pub fn output_by_id(&self, id: Id) -> bool {
+    pub fn output_by_id(&self, id: Id) -> Logic {
         self.outputs[&id]
     }
 
-    pub fn set_output(&mut self, id: Id, initial_value: bool) {
+    pub fn set_output(&mut self, id: Id, initial_value: Logic) {
         self.outputs.insert(id, initial_value);
     }
 
-    pub fn middle_signals(&self) -> impl Iterator<Item = (Id, bool)> {
+    pub fn middle_signals(&self) -> impl Iterator<Item = (Id, Logic)> {
         self.middle_signals.iter().map(|(a, b)| (*a, *b))
     }
 
     pub fn connections(&self) -> impl Iterator<Item = (Id, Connection)> {
-        self.connections
+        self.structure
+            .connections
             .iter()
             .map(|(id, connection)| (*id, *connection))
     }
 
     pub fn gate_by_id(&self, id: Id) -> &LogicGate {
-        &self.gates[&id]
+        &self.structure.gates[&id]
     }
 
     pub fn gates(&self) -> impl Iterator<Item = Id> {
-        self.gates.keys().copied()
+        self.structure.gates.keys().copied()
+    }
+
+    /// this copy's instance of the custom gate `id`, which shares its definition
+    /// with every other instance of the same map
+    pub fn custom_gate(&self, id: Id) -> Option<&LogicGateMap> {
+        self.custom_gates.get(&id)
+    }
+
+    pub fn custom_gate_mut(&mut self, id: Id) -> Option<&mut LogicGateMap> {
+        self.custom_gates.get_mut(&id)
+    }
+
+    /// the contents of the ROM or RAM `id` in this copy of the map
+    pub fn memory(&self, id: Id) -> Option<&Memory> {
+        self.memories.get(&id)
+    }
+
+    pub fn memory_mut(&mut self, id: Id) -> Option<&mut Memory> {
+        self.memories.get_mut(&id)
+    }
+
+    /// whether both maps are copies of the same definition, which neither
+    /// has been edited since, so they only differ in their values
+    #[allow(unused)]
+    pub fn shares_definition_with(&self, other: &LogicGateMap) -> bool {
+        Arc::ptr_eq(&self.structure, &other.structure)
+    }
+
+    /// the map inside the custom gates reached by following `path`
+    pub fn inner_map(&self, path: &[Id]) -> &LogicGateMap {
+        path.iter().fold(self, |map, gate| {
+            map.custom_gate(*gate)
+                .expect("path should only go through custom gates!")
+        })
+    }
+
+    pub fn inner_map_mut(&mut self, path: &[Id]) -> &mut LogicGateMap {
+        path.iter().fold(self, |map, gate| {
+            map.custom_gate_mut(*gate)
+                .expect("path should only go through custom gates!")
+        })
+    }
+
+    pub fn forced(&self) -> impl Iterator<Item = (&SignalOrigin, Logic)> {
+        self.forced.iter().map(|(origin, value)| (origin, *value))
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.structure.name.as_deref()
+    }
+
+    pub fn set_name(&mut self, name: String) {
+        self.structure_mut().name = Some(name);
+    }
+
+    pub fn expects_feedback(&self) -> bool {
+        self.structure.expects_feedback
+    }
+
+    pub fn set_expects_feedback(&mut self, expects_feedback: bool) {
+        self.structure_mut().expects_feedback = expects_feedback;
+    }
+
+    pub fn nand_only(&self) -> bool {
+        self.structure.nand_only
+    }
+
+    pub fn set_nand_only(&mut self, nand_only: bool) {
+        self.structure_mut().nand_only = nand_only;
+    }
+
+    /// whether every gate is a NAND gate or a clock, including every gate
+    /// inside the custom gates, which is what `nand_only` asks for
+    pub fn only_has_nand_gates(&self) -> bool {
+        self.structure.gates.iter().all(|(id, gate)| match gate {
+            LogicGate::Nand { .. } | LogicGate::Clock { .. } => true,
+            LogicGate::Custom { .. } => self.custom_gates[id].only_has_nand_gates(),
+            _ => false,
+        })
+    }
+
+    pub fn gate_by_name(&self, name: &str) -> Option<Id> {
+        self.structure.gate_names.get(name).copied()
+    }
+
+    pub fn gate_name(&self, gate: Id) -> Option<&str> {
+        self.structure
+            .gate_names
+            .iter()
+            .find(|(_, id)| **id == gate)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// replaces any name the gate already had
+    pub fn set_gate_name(&mut self, gate: Id, name: String) {
+        let structure = self.structure_mut();
+        structure.gate_names.retain(|_, id| *id != gate);
+        structure.gate_names.insert(name, gate);
+    }
+
+    pub fn signal_by_name(&self, name: &str) -> Option<&Bus> {
+        self.structure.signal_names.get(name)
+    }
+
+    pub fn signal_names(&self) -> impl Iterator<Item = (&str, &Bus)> {
+        self.structure
+            .signal_names
+            .iter()
+            .map(|(name, bus)| (name.as_str(), bus))
+    }
+
+    /// the name of the signal `point` is part of, with the bit index
+    /// if it's in a bus, like `sum[2]`
+    pub fn point_name(&self, point: &ConnectionPoint) -> Option<String> {
+        self.structure.signal_names.iter().find_map(|(name, bus)| {
+            let index = bus.bits().iter().position(|bit| bit == point)?;
+            Some(match bus.width() {
+                1 => name.clone(),
+                _ => format!("{name}[{index}]"),
+            })
+        })
+    }
+
+    pub fn set_signal_name(&mut self, name: String, signal: Bus) {
+        self.structure_mut().signal_names.insert(name, signal);
+    }
+
+    pub fn clock_tick(&self) -> u64 {
+        self.clock_tick
+    }
+
+    /// sets the clock tick of this map and every map inside it
+    pub fn set_clock_tick(&mut self, clock_tick: u64) {
+        self.clock_tick = clock_tick;
+        for inner in self.custom_gates.values_mut() {
+            inner.set_clock_tick(clock_tick);
+        }
+    }
+
+    pub fn gate_delay(&self, gate: Id) -> Option<u32> {
+        self.structure.delays.get(&gate).copied()
+    }
+
+    /// a custom gate doesn't have a delay of its own, so setting it
+    /// sets the default delay of the gates inside that instance instead
+    pub fn set_gate_delay(&mut self, gate: Id, delay: u32) {
+        match self.custom_gates.get_mut(&gate) {
+            Some(inner) => inner.set_default_delay(delay),
+            None => {
+                self.structure_mut().delays.insert(gate, delay);
+            }
+        }
+    }
+
+    pub fn default_delay(&self) -> Option<u32> {
+        self.structure.default_delay
+    }
+
+    pub fn set_default_delay(&mut self, delay: u32) {
+        self.structure_mut().default_delay = Some(delay);
+    }
+
+    pub fn bus_by_id(&self, id: Id) -> &Bus {
+        &self.structure.buses[&id]
+    }
+
+    pub fn bus_connections(&self) -> impl Iterator<Item = (Id, &BusConnection)> {
+        self.structure
+            .bus_connections
+            .iter()
+            .map(|(id, connection)| (*id, connection))
     }
 }
 #[allow(unused)]
@@ -224,45 +707,785 @@ impl LogicGateMap {
     }
 
     pub fn create_input(&mut self) -> Id {
-        let id = self.id_generator.generate();
-        self.inputs.insert(id, false);
+        let id = self.structure_mut().id_generator.generate();
+        self.inputs.insert(id, Logic::Zero);
         id
     }
 
     pub fn create_output(&mut self) -> Id {
-        let id = self.id_generator.generate();
-        self.outputs.insert(id, false);
+        let id = self.structure_mut().id_generator.generate();
+        self.outputs.insert(id, Logic::Zero);
         id
     }
 
     pub fn create_nand_gate(&mut self) -> GateCreationInfo {
-        let id = self.id_generator.generate();
+        let id = self.structure_mut().id_generator.generate();
         let (i1, i2, q) = (
-            self.id_generator.generate(),
-            self.id_generator.generate(),
-            self.id_generator.generate(),
+            self.structure_mut().id_generator.generate(),
+            self.structure_mut().id_generator.generate(),
+            self.structure_mut().id_generator.generate(),
         );
-        self.gates.insert(
+        self.structure_mut().gates.insert(
             id,
             LogicGate::Nand {
-                inputs: [(i1, false), (i2, false)],
-                output: (q, true),
+                inputs: [i1, i2],
+                output: q,
             },
         );
+        self.pins
+            .extend([(i1, Logic::Zero), (i2, Logic::Zero), (q, Logic::One)]);
         GateCreationInfo::new(id, vec![i1, i2], vec![q])
     }
 
+    pub fn create_primitive_gate(
+        &mut self,
+        kind: PrimitiveKind,
+        input_count: usize,
+    ) -> GateCreationInfo {
+        assert!(
+            kind.allows_input_count(input_count),
+            "should be able to create a {kind:?} gate with {input_count} inputs!"
+        );
+        let id = self.structure_mut().id_generator.generate();
+        let inputs = (0..input_count)
+            .map(|_| self.structure_mut().id_generator.generate())
+            .collect::<Vec<_>>();
+        let output = self.structure_mut().id_generator.generate();
+        self.structure_mut().gates.insert(
+            id,
+            LogicGate::Primitive {
+                kind,
+                inputs: inputs.clone(),
+                output,
+            },
+        );
+        self.pins
+            .extend(inputs.iter().map(|input| (*input, Logic::Zero)));
+        self.pins
+            .insert(output, kind.evaluate(inputs.iter().map(|_| Logic::Zero)));
+        GateCreationInfo::new(id, inputs, vec![output])
+    }
+
+    pub fn create_clock(&mut self, clock: Clock) -> GateCreationInfo {
+        assert!(
+            clock.period > 0 && clock.duty <= clock.period,
+            "should be able to create a clock with {clock:?}!"
+        );
+        let id = self.structure_mut().id_generator.generate();
+        let output = self.structure_mut().id_generator.generate();
+        self.structure_mut()
+            .gates
+            .insert(id, LogicGate::Clock { clock, output });
+        self.pins.insert(output, clock.value(self.clock_tick));
+        GateCreationInfo::new(id, vec![], vec![output])
+    }
+
+    /// see `LogicGate::TriState`. the inputs are the data input then the enable
+    pub fn create_tri_state_buffer(&mut self) -> GateCreationInfo {
+        let id = self.structure_mut().id_generator.generate();
+        let (input, enable, output) = (
+            self.structure_mut().id_generator.generate(),
+            self.structure_mut().id_generator.generate(),
+            self.structure_mut().id_generator.generate(),
+        );
+        self.structure_mut().gates.insert(
+            id,
+            LogicGate::TriState {
+                input,
+                enable,
+                output,
+            },
+        );
+        self.pins.extend([
+            (input, Logic::Zero),
+            (enable, Logic::Zero),
+            (output, Logic::HighImpedance),
+        ]);
+        GateCreationInfo::new(id, vec![input, enable], vec![output])
+    }
+
+    /// see `Memory`. `contents` is padded with zeros up to one word for every address
+    pub fn create_rom(
+        &mut self,
+        address_width: usize,
+        data_width: usize,
+        contents: Vec<u64>,
+    ) -> GateCreationInfo {
+        let (id, inputs, outputs) = self.create_memory(address_width, 0, data_width, contents);
+        let info = GateCreationInfo::new(id, inputs.clone(), outputs.clone());
+        self.structure_mut()
+            .gates
+            .insert(id, LogicGate::Rom { inputs, outputs });
+        info
+    }
+
+    /// see `Memory`. the inputs are the address, the data to write,
+    /// the write enable and then the clock
+    pub fn create_ram(
+        &mut self,
+        address_width: usize,
+        data_width: usize,
+        contents: Vec<u64>,
+    ) -> GateCreationInfo {
+        let (id, inputs, outputs) =
+            self.create_memory(address_width, data_width + 2, data_width, contents);
+        let info = GateCreationInfo::new(id, inputs.clone(), outputs.clone());
+        self.structure_mut()
+            .gates
+            .insert(id, LogicGate::Ram { inputs, outputs });
+        info
+    }
+
+    /// the contents and the values of the pins, giving back the id of the gate
+    /// and its inputs and outputs for the gate to be added with
+    fn create_memory(
+        &mut self,
+        address_width: usize,
+        extra_inputs: usize,
+        data_width: usize,
+        contents: Vec<u64>,
+    ) -> (Id, Vec<Id>, Vec<Id>) {
+        assert!(
+            address_width <= MAX_ADDRESS_WIDTH && (1..=64).contains(&data_width),
+            "should be able to create a memory with {address_width} address bits and {data_width} data bits!"
+        );
+        assert!(
+            contents.len() <= 1 << address_width,
+            "should be able to fit the contents into the memory!"
+        );
+        let id = self.structure_mut().id_generator.generate();
+        let inputs = (0..address_width + extra_inputs)
+            .map(|_| self.structure_mut().id_generator.generate())
+            .collect::<Vec<_>>();
+        let outputs = (0..data_width)
+            .map(|_| self.structure_mut().id_generator.generate())
+            .collect::<Vec<_>>();
+        let memory = Memory::new(address_width, data_width, contents);
+        self.pins
+            .extend(inputs.iter().map(|input| (*input, Logic::Zero)));
+        for (bit, output) in outputs.iter().enumerate() {
+            self.pins
+                .insert(*output, memory.read(std::iter::repeat(Logic::Zero), bit));
+        }
+        self.memories.insert(id, memory);
+        (id, inputs, outputs)
+    }
+
+    /// `gate` becomes this map's instance of the custom gate, sharing its
+    /// definition with every other custom gate made from a copy of it
     pub fn create_custom_gate(&mut self, gate: LogicGateMap) -> GateCreationInfo {
-        let id = self.id_generator.generate();
-        let inputs = gate.inputs().map(|(id, _)| id).collect();
-        let outputs = gate.outputs().map(|(id, _)| id).collect();
-        self.gates.insert(id, LogicGate::Custom(gate));
+        let id = self.structure_mut().id_generator.generate();
+        let mut inputs = gate.inputs().map(|(id, _)| id).collect::<Vec<_>>();
+        let mut outputs = gate.outputs().map(|(id, _)| id).collect::<Vec<_>>();
+        inputs.sort();
+        outputs.sort();
+        self.structure_mut().gates.insert(
+            id,
+            LogicGate::Custom {
+                inputs: inputs.clone(),
+                outputs: outputs.clone(),
+            },
+        );
+        self.custom_gates.insert(id, gate);
         GateCreationInfo::new(id, inputs, outputs)
     }
 
     pub fn create_connection(&mut self, connection: impl Into<Connection>) -> Id {
-        let id = self.id_generator.generate();
-        self.connections.insert(id, connection.into());
+        let id = self.structure_mut().id_generator.generate();
+        self.structure_mut()
+            .connections
+            .insert(id, connection.into());
+        id
+    }
+
+    pub fn create_input_bus(&mut self, width: usize) -> Id {
+        let bits = (0..width)
+            .map(|_| ConnectionPoint::Input(self.create_input()))
+            .collect();
+        self.create_bus(bits)
+    }
+
+    pub fn create_output_bus(&mut self, width: usize) -> Id {
+        let bits = (0..width)
+            .map(|_| ConnectionPoint::Output(self.create_output()))
+            .collect();
+        self.create_bus(bits)
+    }
+
+    /// a signal inside the map which isn't an input or output, for tapping
+    /// a wire or joining several wires together
+    pub fn create_middle_signal(&mut self) -> Id {
+        let id = self.structure_mut().id_generator.generate();
+        self.middle_signals.insert(id, Logic::Zero);
+        id
+    }
+
+    pub fn create_middle_signal_bus(&mut self, width: usize) -> Id {
+        let bits = (0..width)
+            .map(|_| ConnectionPoint::MiddleSignal(self.create_middle_signal()))
+            .collect();
+        self.create_bus(bits)
+    }
+
+    fn create_bus(&mut self, bits: Vec<ConnectionPoint>) -> Id {
+        assert!(
+            bits.len() <= MAX_BUS_WIDTH,
+            "should only create buses up to {MAX_BUS_WIDTH} bits wide!"
+        );
+        let id = self.structure_mut().id_generator.generate();
+        self.structure_mut().buses.insert(id, Bus::new(bits));
+        id
+    }
+
+    /// connects each bit of `start` to the same bit of `end`
+    pub fn create_bus_connection(&mut self, start: Bus, end: Bus) -> Id {
+        assert_eq!(
+            start.width(),
+            end.width(),
+            "should only connect buses of the same width!"
+        );
+        let connections = start
+            .bits()
+            .iter()
+            .zip(end.bits())
+            .map(|(start, end)| self.create_connection((*start, *end)))
+            .collect();
+        let id = self.structure_mut().id_generator.generate();
+        self.structure_mut().bus_connections.insert(
+            id,
+            BusConnection {
+                start,
+                end,
+                connections,
+            },
+        );
         id
     }
 }
+
+/// an edit which referred to something that isn't in the map,
+/// usually because it has already been removed. each variant is the missing thing,
+/// apart from `NameTaken`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditError {
+    Input(Id),
+    Output(Id),
+    MiddleSignal(Id),
+    Gate(Id),
+    Connection(Id),
+    ConnectionPoint(ConnectionPoint),
+    SignalName(String),
+    /// renaming something to a name another gate or signal already has
+    NameTaken(String),
+}
+impl Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditError::Input(id) => write!(f, "there is no input {id}"),
+            EditError::Output(id) => write!(f, "there is no output {id}"),
+            EditError::MiddleSignal(id) => write!(f, "there is no signal {id}"),
+            EditError::Gate(id) => write!(f, "there is no gate {id}"),
+            EditError::Connection(id) => write!(f, "there is no connection {id}"),
+            EditError::ConnectionPoint(point) => {
+                write!(f, "there is no connection point {point:?}")
+            }
+            EditError::SignalName(name) => write!(f, "there is no signal named {name}"),
+            EditError::NameTaken(name) => write!(f, "{name} is already used"),
+        }
+    }
+}
+
+/// removing anything also removes everything which referred to it,
+/// so a map never has connections, buses or delays for things that don't exist.
+/// a bus connection which loses any of its bits is split back up into
+/// its remaining single-bit connections
+#[allow(unused)]
+impl LogicGateMap {
+    pub fn has_connection_point(&self, point: &ConnectionPoint) -> bool {
+        match point {
+            ConnectionPoint::Input(id) => self.inputs.contains_key(id),
+            ConnectionPoint::Output(id) => self.outputs.contains_key(id),
+            ConnectionPoint::MiddleSignal(id) => self.middle_signals.contains_key(id),
+            ConnectionPoint::GateInput { gate, input } => self
+                .structure
+                .gates
+                .get(gate)
+                .is_some_and(|gate| gate.inputs().contains(input)),
+            ConnectionPoint::GateOutput { gate, output } => self
+                .structure
+                .gates
+                .get(gate)
+                .is_some_and(|gate| gate.outputs().contains(output)),
+        }
+    }
+
+    /// like `create_connection`, but checks both ends exist first
+    pub fn try_create_connection(
+        &mut self,
+        connection: impl Into<Connection>,
+    ) -> Result<Id, EditError> {
+        let connection = connection.into();
+        self.check_connection(&connection)?;
+        Ok(self.create_connection(connection))
+    }
+
+    /// removing an input, output, middle signal or gate from a map which is
+    /// being drawn has to go through `MapRenderSavedState`, so it doesn't try
+    /// to draw something which isn't there any more
+    pub(crate) fn remove_input(&mut self, id: Id) -> Result<(), EditError> {
+        self.inputs.remove(&id).ok_or(EditError::Input(id))?;
+        self.remove_references(|point| *point == ConnectionPoint::Input(id));
+        Ok(())
+    }
+
+    pub(crate) fn remove_output(&mut self, id: Id) -> Result<(), EditError> {
+        self.outputs.remove(&id).ok_or(EditError::Output(id))?;
+        self.remove_references(|point| *point == ConnectionPoint::Output(id));
+        Ok(())
+    }
+
+    pub(crate) fn remove_middle_signal(&mut self, id: Id) -> Result<(), EditError> {
+        self.middle_signals
+            .remove(&id)
+            .ok_or(EditError::MiddleSignal(id))?;
+        self.remove_references(|point| *point == ConnectionPoint::MiddleSignal(id));
+        Ok(())
+    }
+
+    /// returns the removed gate
+    pub(crate) fn remove_gate(&mut self, id: Id) -> Result<LogicGate, EditError> {
+        if !self.structure.gates.contains_key(&id) {
+            return Err(EditError::Gate(id));
+        }
+        let structure = self.structure_mut();
+        let gate = structure
+            .gates
+            .remove(&id)
+            .expect("should have checked the gate exists!");
+        structure.delays.remove(&id);
+        structure.gate_names.retain(|_, gate| *gate != id);
+        if self.custom_gates.remove(&id).is_none() {
+            for pin in gate.inputs().iter().chain(&gate.outputs()) {
+                self.pins.remove(pin);
+            }
+        }
+        self.memories.remove(&id);
+        self.forced
+            .retain(|origin, _| origin.path.first() != Some(&id));
+        self.remove_references(|point| match point {
+            ConnectionPoint::GateInput { gate, .. } | ConnectionPoint::GateOutput { gate, .. } => {
+                *gate == id
+            }
+            _ => false,
+        });
+        Ok(gate)
+    }
+
+    /// the name a gate is found by in paths, like the ones `resolve_path` takes
+    pub fn rename_gate(&mut self, id: Id, name: String) -> Result<(), EditError> {
+        if !self.structure.gates.contains_key(&id) {
+            return Err(EditError::Gate(id));
+        }
+        if self.gate_by_name(&name).is_some_and(|other| other != id) {
+            return Err(EditError::NameTaken(name));
+        }
+        self.set_gate_name(id, name);
+        Ok(())
+    }
+
+    /// renames a named input, output or middle signal, or a bus of them
+    pub fn rename_signal(&mut self, old: &str, new: String) -> Result<(), EditError> {
+        if !self.structure.signal_names.contains_key(old) {
+            return Err(EditError::SignalName(old.to_string()));
+        }
+        if new != old && self.structure.signal_names.contains_key(&new) {
+            return Err(EditError::NameTaken(new));
+        }
+        let structure = self.structure_mut();
+        let bus = structure
+            .signal_names
+            .remove(old)
+            .expect("should have checked the signal exists!");
+        structure.signal_names.insert(new, bus);
+        Ok(())
+    }
+
+    /// returns the removed connection
+    pub fn remove_connection(&mut self, id: Id) -> Result<Connection, EditError> {
+        let connection = self
+            .structure_mut()
+            .connections
+            .remove(&id)
+            .ok_or(EditError::Connection(id))?;
+        self.split_bus_connections(|connection| connection == id);
+        Ok(connection)
+    }
+
+    /// moves both ends of an existing connection, keeping its id
+    pub fn rewire_connection(
+        &mut self,
+        id: Id,
+        connection: impl Into<Connection>,
+    ) -> Result<(), EditError> {
+        let connection = connection.into();
+        if !self.structure.connections.contains_key(&id) {
+            return Err(EditError::Connection(id));
+        }
+        self.check_connection(&connection)?;
+        self.structure_mut().connections.insert(id, connection);
+        self.split_bus_connections(|connection| connection == id);
+        Ok(())
+    }
+
+    fn check_connection(&self, connection: &Connection) -> Result<(), EditError> {
+        for point in [connection.start, connection.end] {
+            if !self.has_connection_point(&point) {
+                return Err(EditError::ConnectionPoint(point));
+            }
+        }
+        Ok(())
+    }
+
+    /// removes every connection and bus with a bit where `removed` is true
+    fn remove_references(&mut self, removed: impl Fn(&ConnectionPoint) -> bool) {
+        let structure = self.structure_mut();
+        let dangling = structure
+            .connections
+            .iter()
+            .filter(|(_, connection)| removed(&connection.start) || removed(&connection.end))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        for id in &dangling {
+            structure.connections.remove(id);
+        }
+        structure
+            .buses
+            .retain(|_, bus| !bus.bits().iter().any(&removed));
+        structure
+            .signal_names
+            .retain(|_, bus| !bus.bits().iter().any(&removed));
+        self.forced
+            .retain(|origin, _| !(origin.path.is_empty() && removed(&origin.point)));
+        self.split_bus_connections(|connection| dangling.contains(&connection));
+    }
+
+    /// forgets about every bus connection which has a bit where `changed` is true,
+    /// leaving the rest of its bits as ordinary connections
+    fn split_bus_connections(&mut self, changed: impl Fn(Id) -> bool) {
+        if self
+            .structure
+            .bus_connections
+            .values()
+            .any(|bus_connection| bus_connection.connections.iter().any(|id| changed(*id)))
+        {
+            self.structure_mut()
+                .bus_connections
+                .retain(|_, bus_connection| {
+                    !bus_connection.connections.iter().any(|id| changed(*id))
+                });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    #[test]
+    fn bus_values_round_trip() {
+        let mut map = LogicGateMap::empty();
+        for width in [1, 8, MAX_BUS_WIDTH] {
+            let id = map.create_input_bus(width);
+            let bus = map.bus_by_id(id).clone();
+            let value = u64::MAX >> (64 - width) & 0xa5a5_a5a5_a5a5_a5a5;
+            map.set_bus_value(&bus, value);
+            assert_eq!(map.bus_value(&bus), Some(value));
+        }
+    }
+
+    #[test]
+    fn unknown_bits_have_no_bus_value() {
+        let mut map = LogicGateMap::empty();
+        let id = map.create_input_bus(4);
+        let bus = map.bus_by_id(id).clone();
+        map.set_bus_value(&bus, 0b0110);
+        map.set_connection_point_value(&bus.bit(2), Logic::Unknown);
+        assert_eq!(map.bus_value(&bus), None);
+    }
+
+    #[test]
+    fn bus_connections_carry_every_bit() {
+        let mut map = parse_gate(
+            "version 0
+define_gate pass
+inputs a:4
+outputs q:4
+connections a[0..2] + a[2..4] => q
+",
+            "pass",
+        );
+        let (a, q) = (
+            map.signal_by_name("a")
+                .expect("should have a bus a!")
+                .clone(),
+            map.signal_by_name("q")
+                .expect("should have a bus q!")
+                .clone(),
+        );
+        map.set_bus_value(&a, 0b1001);
+        assert!(map.settle(10).is_ok());
+        assert_eq!(map.bus_value(&q), Some(0b1001));
+    }
+
+    #[test]
+    #[should_panic]
+    fn buses_are_at_most_64_bits() {
+        LogicGateMap::empty().create_input_bus(MAX_BUS_WIDTH + 1);
+    }
+
+    #[test]
+    fn renaming_updates_paths() {
+        let mut map = parse_gate(include_str!("../gates.dat"), "and");
+        let nand = map
+            .gate_by_name("nand")
+            .expect("should have a gate called nand!");
+        assert_eq!(
+            map.rename_gate(nand, "not".to_string()),
+            Err(EditError::NameTaken("not".to_string()))
+        );
+        assert_eq!(map.rename_gate(nand, "first".to_string()), Ok(()));
+        assert_eq!(map.gate_name(nand), Some("first"));
+        assert!(map.resolve_path("first.out0").is_ok());
+        assert!(map.resolve_path("nand.out0").is_err());
+
+        assert_eq!(
+            map.rename_signal("a", "b".to_string()),
+            Err(EditError::NameTaken("b".to_string()))
+        );
+        assert_eq!(
+            map.rename_signal("c", "d".to_string()),
+            Err(EditError::SignalName("c".to_string()))
+        );
+        let a = map
+            .resolve_path("a")
+            .expect("should have a signal called a!");
+        assert_eq!(map.rename_signal("a", "left".to_string()), Ok(()));
+        assert_eq!(map.resolve_path("left"), Ok(a));
+        assert!(map.resolve_path("a").is_err());
+    }
+
+    /// `map` with `a` and `b` both on, so its NAND gate is driving `Zero`
+    fn and_gate() -> LogicGateMap {
+        let mut map = parse_gate(include_str!("../gates.dat"), "and");
+        for input in ["a", "b"] {
+            let point = map.resolve_path(input).unwrap().point;
+            map.set_connection_point_value(&point, Logic::One);
+        }
+        map
+    }
+
+    #[test]
+    fn custom_gates_share_their_definition() {
+        let mut map = parse_gate(
+            "version 0
+define_gate not
+inputs a
+outputs q
+nands n
+connections a => n in 0, a => n in 1, n out 0 => q
+define_gate four
+custom_gates n1 = not, n2 = not, n3 = not, n4 = not
+",
+            "four",
+        );
+        let gates = ["n1", "n2", "n3", "n4"].map(|name| {
+            map.gate_by_name(name)
+                .expect("should have the custom gate!")
+        });
+        let instance = |map: &LogicGateMap, gate| {
+            map.custom_gate(gate)
+                .expect("should be a custom gate!")
+                .clone()
+        };
+        let first = instance(&map, gates[0]);
+        for gate in gates {
+            assert!(instance(&map, gate).shares_definition_with(&first));
+            assert!(instance(&map.step(), gate).shares_definition_with(&first));
+        }
+
+        // but each instance has its own values
+        let (a, q) = (
+            first.signal_by_name("a").expect("should have a").bit(0),
+            first.signal_by_name("q").expect("should have q").bit(0),
+        );
+        map.custom_gate_mut(gates[0])
+            .expect("should be a custom gate!")
+            .set_connection_point_value(&a, Logic::One);
+        for _ in 0..5 {
+            map = map.step();
+        }
+        assert_eq!(
+            instance(&map, gates[0]).connection_point_value(&q),
+            Logic::Zero
+        );
+        for gate in &gates[1..] {
+            assert_eq!(instance(&map, *gate).connection_point_value(&q), Logic::One);
+        }
+    }
+
+    #[test]
+    fn forced_signals_override_their_drivers_when_stepped() {
+        let mut map = and_gate();
+        map.force("nand.out0", Logic::One).unwrap();
+        for _ in 0..10 {
+            map = map.step();
+            assert_eq!(map.probe("nand.out0"), Ok(Logic::One));
+        }
+        assert_eq!(map.probe("out"), Ok(Logic::Zero));
+
+        map.release("nand.out0").unwrap();
+        for _ in 0..10 {
+            map = map.step();
+        }
+        assert_eq!(map.probe("nand.out0"), Ok(Logic::Zero));
+        assert_eq!(map.probe("out"), Ok(Logic::One));
+        assert_eq!(map.forced().count(), 0);
+    }
+
+    #[test]
+    fn forced_signals_override_their_drivers_when_settled() {
+        let mut map = and_gate();
+        // inside the custom not gate, to check the path is kept
+        map.force("not/n.out0", Logic::Zero).unwrap();
+        assert!(map.settle(100).is_ok());
+        assert_eq!(map.probe("nand.out0"), Ok(Logic::Zero));
+        assert_eq!(map.probe("not/n.out0"), Ok(Logic::Zero));
+        assert_eq!(map.probe("out"), Ok(Logic::Zero));
+
+        map.release("not/n.out0").unwrap();
+        assert!(map.settle(100).is_ok());
+        assert_eq!(map.probe("not/n.out0"), Ok(Logic::One));
+        assert_eq!(map.probe("out"), Ok(Logic::One));
+    }
+
+    const EDITED: &str = "version 0
+define_gate edited
+inputs a b:2
+outputs q r:2
+nands n
+delay 3 n
+connections a => n in 0, a => n in 1, n out 0 => q
+connections b => r
+";
+
+    fn point(map: &LogicGateMap, path: &str) -> ConnectionPoint {
+        map.resolve_path(path)
+            .expect("should be able to find path!")
+            .point
+    }
+
+    fn connection(map: &LogicGateMap, start: &str, end: &str) -> Option<Id> {
+        let (start, end) = (point(map, start), point(map, end));
+        map.connections()
+            .find(|(_, connection)| connection.start == start && connection.end == end)
+            .map(|(id, _)| id)
+    }
+
+    #[test]
+    fn removing_a_gate_removes_what_refers_to_it() {
+        let mut map = parse_gate(EDITED, "edited");
+        let n = map.gate_by_name("n").expect("should have a gate called n!");
+        let output = point(&map, "n.out0");
+        assert_eq!(map.connections().count(), 5);
+        assert!(matches!(map.remove_gate(n), Ok(LogicGate::Nand { .. })));
+        assert_eq!(map.connections().count(), 2);
+        assert!(map.connections().all(|(_, connection)| {
+            [connection.start, connection.end].iter().all(|point| {
+                !matches!(point, ConnectionPoint::GateInput { gate, .. }
+                    | ConnectionPoint::GateOutput { gate, .. } if *gate == n)
+            })
+        }));
+        assert_eq!(map.gate_delay(n), None);
+        assert_eq!(map.gate_by_name("n"), None);
+        assert_eq!(map.bus_connections().count(), 1);
+
+        // anything referring to the removed gate is now stale
+        assert!(matches!(map.remove_gate(n), Err(EditError::Gate(id)) if id == n));
+        assert_eq!(map.rename_gate(n, "m".to_string()), Err(EditError::Gate(n)));
+        assert_eq!(
+            map.try_create_connection((output, point(&map, "q"))),
+            Err(EditError::ConnectionPoint(output))
+        );
+    }
+
+    #[test]
+    fn removing_a_signal_removes_its_connections() {
+        let mut map = parse_gate(EDITED, "edited");
+        let ConnectionPoint::Input(a) = point(&map, "a") else {
+            panic!("a should be an input!");
+        };
+        assert_eq!(map.remove_input(a), Ok(()));
+        assert!(connection(&map, "n.out0", "q").is_some());
+        assert_eq!(map.connections().count(), 3);
+        assert!(map.signal_by_name("a").is_none());
+        assert_eq!(map.remove_input(a), Err(EditError::Input(a)));
+        assert_eq!(
+            map.try_create_connection((ConnectionPoint::Input(a), point(&map, "q"))),
+            Err(EditError::ConnectionPoint(ConnectionPoint::Input(a)))
+        );
+    }
+
+    #[test]
+    fn losing_a_bit_splits_a_bus_connection() {
+        let mut map = parse_gate(EDITED, "edited");
+        let bits = map
+            .signal_by_name("b")
+            .expect("should have a bus called b!")
+            .bits()
+            .to_vec();
+        let ConnectionPoint::Input(first) = bits[0] else {
+            panic!("b should be a bus of inputs!");
+        };
+        let end = point(&map, "r[1]");
+        assert_eq!(map.bus_connections().count(), 1);
+        assert_eq!(map.remove_input(first), Ok(()));
+        assert_eq!(map.bus_connections().count(), 0);
+        assert!(map.signal_by_name("b").is_none());
+        // the bit which is left stays connected on its own
+        assert!(
+            map.connections()
+                .any(|(_, connection)| connection.start == bits[1] && connection.end == end)
+        );
+    }
+
+    #[test]
+    fn rewiring_keeps_the_connection() {
+        let mut map = parse_gate(EDITED, "edited");
+        let id = connection(&map, "n.out0", "q").expect("should have connected n to q!");
+        let (a, q) = (point(&map, "a"), point(&map, "q"));
+        assert_eq!(map.rewire_connection(id, (a, q)), Ok(()));
+        assert!(connection(&map, "n.out0", "q").is_none());
+        assert_eq!(connection(&map, "a", "q"), Some(id));
+
+        // rewiring one bit of a bus connection splits it up
+        let bit = connection(&map, "b[0]", "r[0]").expect("should have connected b to r!");
+        let r = point(&map, "r[0]");
+        assert_eq!(map.rewire_connection(bit, (a, r)), Ok(()));
+        assert_eq!(map.bus_connections().count(), 0);
+        assert!(connection(&map, "b[1]", "r[1]").is_some());
+
+        assert_eq!(
+            map.remove_connection(id).map(|connection| connection.end),
+            Ok(q)
+        );
+        assert!(matches!(
+            map.remove_connection(id),
+            Err(EditError::Connection(_))
+        ));
+        assert_eq!(
+            map.rewire_connection(id, (a, q)),
+            Err(EditError::Connection(id))
+        );
+    }
+}
@@ -1,57 +1,375 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
+mod bdd;
+mod compiled;
+mod equivalence;
+mod feedback;
+mod flatten;
 mod id;
+mod logic;
 mod logic_gate;
 mod logic_gate_map;
+mod optimise;
 mod parse;
+mod path;
 mod render;
+mod snapshot;
+mod statistics;
+mod testbench;
+mod truth_table;
+mod validate;
+mod waveform;
+mod waveform_view;
 
+use compiled::{CompiledMap, Contention, Oscillation, Scheduler, SignalOrigin};
+use equivalence::Equivalence;
+use logic_gate::ConnectionPoint;
 use logic_gate_map::LogicGateMap;
-use parse::parse_text;
-use render::MapRenderSavedState;
-use std::sync::{
-    Arc, RwLock,
-    atomic::{AtomicBool, Ordering},
+use parse::{ParsedGate, parse_text};
+use render::{MapRenderSavedState, memories};
+use snapshot::{Snapshot, format_snapshots, parse_snapshots};
+use statistics::Statistics;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
 };
+use testbench::{parse_tests, run_test};
+use truth_table::{TooManyInputs, TruthTable, TruthTableFormat, ports};
+use waveform::WaveformRecorder;
+use waveform_view::WaveformView;
 
 use eframe::{
     App,
-    egui::{self, PointerButton},
+    egui::{self, Color32, PointerButton},
 };
 
-fn main() -> eframe::Result {
+fn main() -> ExitCode {
     env_logger::init();
-    eframe::run_native(
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let command = match args.first().map(String::as_str) {
+        Some("truth-table") => Some(truth_table_command(&args[1..])),
+        Some("equivalent") => Some(equivalent_command(&args[1..])),
+        Some("optimise") => Some(optimise_command(&args[1..])),
+        Some("statistics") => Some(statistics_command(&args[1..])),
+        Some("test") => Some(test_command(&args[1..])),
+        _ => None,
+    };
+    if let Some(result) = command {
+        return result.unwrap_or_else(|error| {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        });
+    }
+    let result = eframe::run_native(
         "Logic Gate Simulator",
         eframe::NativeOptions {
             viewport: egui::ViewportBuilder::default().with_inner_size([600.0, 600.0]),
             ..Default::default()
         },
         Box::new(|_cc| Ok(Box::<LogicGateApp>::default())),
-    )
+    );
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn load_gates(filenames: &[String]) -> Result<Vec<ParsedGate>, String> {
+    let mut gates = vec![];
+    for filename in filenames {
+        let data =
+            std::fs::read_to_string(filename).map_err(|error| format!("{filename}: {error}"))?;
+        let directory = Path::new(filename).parent().unwrap_or(Path::new(""));
+        let new_gates = parse_text(data.as_str(), directory)
+            .map_err(|error| format!("{filename}: {error:?}"))?;
+        gates.extend(new_gates);
+    }
+    Ok(gates)
+}
+
+/// how many ticks of waveforms the GUI keeps
+const WAVEFORM_CAPACITY: usize = 10_000;
+/// how many times the GUI steps a map with running clocks each frame
+const CLOCK_TICKS_PER_FRAME: usize = 1;
+
+fn find_gate<'a>(gates: &'a [ParsedGate], name: &str) -> Result<&'a ParsedGate, String> {
+    gates
+        .iter()
+        .find(|gate| gate.name == name)
+        .ok_or(format!("there is no gate named {name}"))
+}
+
+/// truth-table [--format text|csv|markdown] <gate> <files...>
+fn truth_table_command(args: &[String]) -> Result<ExitCode, String> {
+    let usage = "usage: truth-table [--format text|csv|markdown] <gate> <files...>";
+    let (format, args) = match args {
+        [flag, format, rest @ ..] if flag == "--format" => (format.parse()?, rest),
+        _ => (TruthTableFormat::Text, args),
+    };
+    let [name, filenames @ ..] = args else {
+        return Err(usage.to_string());
+    };
+    if filenames.is_empty() {
+        return Err(usage.to_string());
+    }
+    let gates = load_gates(filenames)?;
+    let gate = find_gate(&gates, name)?;
+    let table = gate.map.truth_table().map_err(|error| error.to_string())?;
+    print!("{}", table.format(format));
+    Ok(ExitCode::SUCCESS)
+}
+
+/// optimise <gate> <files...>, which prints how many NAND gates each pass removed
+fn optimise_command(args: &[String]) -> Result<ExitCode, String> {
+    let [name, filenames @ ..] = args else {
+        return Err("usage: optimise <gate> <files...>".to_string());
+    };
+    if filenames.is_empty() {
+        return Err("usage: optimise <gate> <files...>".to_string());
+    }
+    let gates = load_gates(filenames)?;
+    let optimised = find_gate(&gates, name)?
+        .map
+        .optimise()
+        .map_err(|error| error.to_string())?;
+    println!("{optimised}");
+    Ok(ExitCode::SUCCESS)
+}
+
+/// statistics <gate> <files...>
+fn statistics_command(args: &[String]) -> Result<ExitCode, String> {
+    let [name, filenames @ ..] = args else {
+        return Err("usage: statistics <gate> <files...>".to_string());
+    };
+    if filenames.is_empty() {
+        return Err("usage: statistics <gate> <files...>".to_string());
+    }
+    let gates = load_gates(filenames)?;
+    print!("{}", find_gate(&gates, name)?.map.statistics());
+    Ok(ExitCode::SUCCESS)
+}
+
+/// test <test file> <files...>, which fails if any of the tests in the test file do
+fn test_command(args: &[String]) -> Result<ExitCode, String> {
+    let [test_filename, filenames @ ..] = args else {
+        return Err("usage: test <test file> <files...>".to_string());
+    };
+    if filenames.is_empty() {
+        return Err("usage: test <test file> <files...>".to_string());
+    }
+    let text = std::fs::read_to_string(test_filename)
+        .map_err(|error| format!("{test_filename}: {error}"))?;
+    let tests = parse_tests(&text).map_err(|error| format!("{test_filename}: {error}"))?;
+    let gates = load_gates(filenames)?;
+    let mut failed = 0;
+    for test in &tests {
+        let result = find_gate(&gates, &test.gate).and_then(|gate| {
+            run_test(&gate.map, test).map_err(|(line_number, failure)| {
+                format!("{test_filename}:{}: {failure}", line_number + 1)
+            })
+        });
+        match result {
+            Ok(()) => println!("passed {}", test.name),
+            Err(error) => {
+                failed += 1;
+                println!("FAILED {}\n  {error}", test.name);
+            }
+        }
+    }
+    println!("{} passed, {failed} failed", tests.len() - failed);
+    Ok(if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+/// equivalent <gate> <gate> <files...>, which fails if the gates are different
+fn equivalent_command(args: &[String]) -> Result<ExitCode, String> {
+    let [left, right, filenames @ ..] = args else {
+        return Err("usage: equivalent <gate> <gate> <files...>".to_string());
+    };
+    if filenames.is_empty() {
+        return Err("usage: equivalent <gate> <gate> <files...>".to_string());
+    }
+    let gates = load_gates(filenames)?;
+    let (left, right) = (find_gate(&gates, left)?, find_gate(&gates, right)?);
+    match left
+        .map
+        .check_equivalence(&right.map)
+        .map_err(|error| error.to_string())?
+    {
+        Equivalence::Equivalent => {
+            println!("{} and {} are equivalent", left.name, right.name);
+            Ok(ExitCode::SUCCESS)
+        }
+        Equivalence::Different(counterexample) => {
+            println!(
+                "{} and {} are different: {counterexample}",
+                left.name, right.name
+            );
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// compiles `map` again in place of `compiled`, keeping its scheduler and recorder,
+/// giving back the paths of any recorded signals `map` doesn't have
+fn recompile(compiled: &mut CompiledMap, map: &LogicGateMap) -> Vec<String> {
+    let scheduler = compiled.scheduler();
+    let recorder = compiled.detach_recorder();
+    *compiled = map.compile();
+    compiled.set_scheduler(scheduler);
+    recorder
+        .map(|recorder| compiled.attach_recorder(map, recorder))
+        .unwrap_or_default()
 }
 
 struct LogicGateApp {
     map: Arc<RwLock<LogicGateMap>>,
+    compiled: CompiledMap,
+    /// `None` while clocks are running, as the map is ticked instead of settled then
+    settled: Option<Result<usize, Oscillation>>,
+    contentions: Vec<Contention>,
+    /// every signal still `Unknown`, like after powering on until the circuit is reset
+    unknown_signals: Vec<SignalOrigin>,
+    /// what `LogicGateMap::validate` found in every definition that was loaded,
+    /// with whether each is an error and its description
+    diagnostics: Vec<(bool, String)>,
+    /// why the displayed map can't be simulated, which is when it has errors
+    /// or couldn't be loaded at all.
+    /// nothing is compiled or drawn then, and only the diagnostics are shown
+    invalid: Option<String>,
+    /// generated from the displayed map when asked for, as it can take a while
+    truth_table: Option<Result<TruthTable, TooManyInputs>>,
+    /// for the displayed map, whose critical path is highlighted while this is shown
+    statistics: Option<Statistics>,
+    /// the map and layout which aren't being shown, to swap back to.
+    /// this is the flattened map once it's been made, or the original one while
+    /// the flattened map is being shown
+    other_view: Option<(LogicGateMap, MapRenderSavedState)>,
+    flattened: bool,
+    flatten_error: Option<String>,
+    /// the state the map was loaded in, for resetting it
+    initial: Snapshot,
+    snapshots: Vec<(String, Snapshot)>,
+    /// the name to give the next snapshot
+    snapshot_name: String,
+    /// next to the last `.dat` file, with the extension `.snapshots`
+    snapshot_path: PathBuf,
+    /// what went wrong with loading, saving or restoring a snapshot
+    snapshot_error: Option<String>,
+    /// the name the editor renames a gate or signal to
+    edit_name: String,
+    /// why the last rename or removal didn't happen
+    edit_error: Option<String>,
+    /// the path of the next signal to record
+    waveform_path: String,
+    /// next to the snapshots, with the extension `.vcd`
+    vcd_path: PathBuf,
+    /// what went wrong with recording or exporting waveforms
+    waveform_error: Option<String>,
+    waveform_view: WaveformView,
+    show_waveform_view: bool,
+    /// while this is on, clicking a signal on the canvas records it
+    /// instead of changing it
+    picking_signals: bool,
     closed: Arc<AtomicBool>,
     render_data: MapRenderSavedState,
 }
 impl Default for LogicGateApp {
     fn default() -> Self {
-        let mut maps: Vec<(LogicGateMap, Option<MapRenderSavedState>)> = vec![];
-        for filename in std::env::args().skip(1) {
-            let data = std::fs::read_to_string(filename).unwrap();
-            let new_maps = parse_text(data.as_str()).unwrap();
-            maps.extend_from_slice(new_maps.as_slice());
-        }
-
-        let (map, render_data) = maps
+        let filenames = std::env::args().skip(1).collect::<Vec<_>>();
+        // anything going wrong with loading is shown in the window in place of the map,
+        // like the commands print it
+        let (gates, load_error) = match load_gates(&filenames) {
+            Ok(gates) => (gates, None),
+            Err(error) => (vec![], Some(error)),
+        };
+        let gate = gates.iter().rev().find(|gate| gate.render_data.is_some());
+        let invalid = match gate {
+            _ if load_error.is_some() => load_error,
+            None => Some("none of the gates that were loaded can be drawn".to_string()),
+            Some(gate) => {
+                let error_count = gate
+                    .diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.is_error())
+                    .count();
+                (error_count > 0).then(|| {
+                    format!(
+                        "{} can't be simulated as it has {error_count} errors, see the diagnostics",
+                        gate.name
+                    )
+                })
+            }
+        };
+        let (map, render_data) = match gate {
+            Some(gate) => (
+                gate.map.clone(),
+                gate.render_data
+                    .clone()
+                    .expect("should have found a renderable map!"),
+            ),
+            None => (LogicGateMap::empty(), MapRenderSavedState::new()),
+        };
+        let diagnostics = gates
             .iter()
-            .rev()
-            .find(|(_, b)| b.is_some())
-            .map(|(a, b)| (a.clone(), b.as_ref().unwrap().clone()))
-            .clone()
-            .expect("should be able to find a renderable map!");
+            .flat_map(|gate| {
+                gate.diagnostics.iter().map(|diagnostic| {
+                    let description = diagnostic.describe(&gate.map);
+                    (
+                        diagnostic.is_error(),
+                        format!("{}: {description}", gate.name),
+                    )
+                })
+            })
+            .collect();
 
+        let snapshot_path =
+            Path::new(filenames.last().map_or("gates", String::as_str)).with_extension("snapshots");
+        let (snapshots, snapshot_error) = match std::fs::read_to_string(&snapshot_path) {
+            Ok(text) => match parse_snapshots(&map, &text) {
+                Ok(snapshots) => (snapshots, None),
+                Err(error) => (
+                    vec![],
+                    Some(format!("{}: {error}", snapshot_path.display())),
+                ),
+            },
+            Err(_) => (vec![], None),
+        };
+
+        let vcd_path = snapshot_path.with_extension("vcd");
+
+        // a map with errors isn't compiled, as it may not make sense to
+        let mut compiled = match invalid {
+            Some(_) => LogicGateMap::empty().compile(),
+            None => map.compile(),
+        };
+        compiled.attach_recorder(&map, WaveformRecorder::new(WAVEFORM_CAPACITY));
+        let ports = ports(map.inputs())
+            .into_iter()
+            .map(ConnectionPoint::Input)
+            .chain(
+                ports(map.outputs())
+                    .into_iter()
+                    .map(ConnectionPoint::Output),
+            )
+            .filter_map(|point| map.point_name(&point))
+            .collect::<Vec<_>>();
+        for path in ports.into_iter().filter(|_| invalid.is_none()) {
+            compiled
+                .record_signal(&map, &path)
+                .expect("should be able to record a named signal!");
+        }
+        let initial = map.snapshot();
         let map = Arc::new(RwLock::new(map));
         let _update_map_clone = Arc::clone(&map);
         let closed = Arc::new(AtomicBool::new(false));
@@ -82,13 +400,328 @@ impl Default for LogicGateApp {
 
         Self {
             map,
+            compiled,
+            settled: None,
+            contentions: vec![],
+            unknown_signals: vec![],
+            diagnostics,
+            invalid,
+            truth_table: None,
+            statistics: None,
+            other_view: None,
+            flattened: false,
+            flatten_error: None,
+            initial,
+            snapshots,
+            snapshot_name: String::new(),
+            snapshot_path,
+            snapshot_error,
+            edit_name: String::new(),
+            edit_error: None,
+            waveform_path: String::new(),
+            vcd_path,
+            waveform_error: None,
+            waveform_view: WaveformView::new(),
+            show_waveform_view: true,
+            picking_signals: false,
             closed,
             render_data,
         }
     }
 }
+impl LogicGateApp {
+    /// switches between the original map and the flattened one, which is
+    /// only made the first time. the inputs are copied over in declaration order
+    fn toggle_flattened(&mut self) {
+        let mut writeable = self.map.write().expect("should be able to flatten map!");
+        let (mut other, mut other_render_data) = match self.other_view.take() {
+            Some(view) => view,
+            None => match writeable.flatten() {
+                Ok(flattened) => {
+                    let render_data = MapRenderSavedState::layered(&flattened.map, |gate| {
+                        flattened.origins[&gate].name(&writeable)
+                    });
+                    (flattened.map, render_data)
+                }
+                Err(error) => {
+                    self.flatten_error = Some(error.to_string());
+                    return;
+                }
+            },
+        };
+        let ports = |map: &LogicGateMap| {
+            let mut inputs = map.inputs().collect::<Vec<_>>();
+            inputs.sort_by_key(|(id, _)| *id);
+            inputs
+        };
+        let other_inputs = ports(&other).into_iter().map(|(id, _)| id);
+        for (id, (_, value)) in other_inputs.zip(ports(&writeable)).collect::<Vec<_>>() {
+            other.set_input(id, value);
+        }
+        self.render_data
+            .set_highlighted(HashSet::new(), HashSet::new());
+        std::mem::swap(&mut *writeable, &mut other);
+        std::mem::swap(&mut self.render_data, &mut other_render_data);
+        self.other_view = Some((other, other_render_data));
+        self.flattened = !self.flattened;
+        self.statistics = None;
+
+        let removed = recompile(&mut self.compiled, &writeable);
+        if !removed.is_empty() {
+            self.waveform_error = Some(format!(
+                "stopped recording {} as this map doesn't have them",
+                removed.join(", ")
+            ));
+        }
+    }
+
+    /// puts the map back how it was when `snapshot` was taken. the compiled map
+    /// is made again, as it has its own copy of every value
+    fn restore(&mut self, snapshot: &Snapshot) {
+        let mut writeable = self.map.write().expect("should be able to restore map!");
+        if let Err(error) = writeable.restore(snapshot) {
+            self.snapshot_error = Some(error.to_string());
+            return;
+        }
+        self.snapshot_error = None;
+        recompile(&mut self.compiled, &writeable);
+    }
+
+    fn show_waveforms(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.waveform_path);
+            let path = self.waveform_path.trim().to_string();
+            if ui
+                .add_enabled(!path.is_empty(), egui::Button::new("record"))
+                .clicked()
+            {
+                let readable = self.map.read().expect("should be able to read map!");
+                match self.compiled.record_signal(&readable, &path) {
+                    Ok(()) => {
+                        self.waveform_path.clear();
+                        self.waveform_error = None;
+                    }
+                    Err(error) => self.waveform_error = Some(error.to_string()),
+                }
+            }
+            if ui.button("clear").clicked()
+                && let Some(recorder) = self.compiled.recorder_mut()
+            {
+                recorder.clear();
+            }
+            if ui
+                .button(format!("export to {}", self.vcd_path.display()))
+                .clicked()
+                && let Some(recorder) = self.compiled.recorder()
+            {
+                let readable = self.map.read().expect("should be able to read map!");
+                let vcd = recorder.to_vcd(readable.name().unwrap_or("top"));
+                self.waveform_error = std::fs::write(&self.vcd_path, vcd)
+                    .err()
+                    .map(|error| format!("{}: {error}", self.vcd_path.display()));
+            }
+        });
+        if let Some(recorder) = self.compiled.recorder_mut() {
+            ui.label(format!("{} ticks recorded", recorder.samples().count()));
+            let mut remove = None;
+            for path in recorder.signals() {
+                ui.horizontal(|ui| {
+                    ui.label(path);
+                    if ui.button("stop").clicked() {
+                        remove = Some(path.to_string());
+                    }
+                });
+            }
+            if let Some(path) = remove {
+                recorder.remove_signal(&path);
+            }
+        }
+        if let Some(error) = &self.waveform_error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+
+    fn show_diagnostics(&self, ui: &mut egui::Ui) {
+        if self.diagnostics.is_empty() {
+            return;
+        }
+        egui::CollapsingHeader::new(format!("{} diagnostics", self.diagnostics.len()))
+            .default_open(self.invalid.is_some())
+            .show(ui, |ui| {
+                for (is_error, description) in &self.diagnostics {
+                    let colour = if *is_error {
+                        Color32::RED
+                    } else {
+                        Color32::YELLOW
+                    };
+                    ui.colored_label(colour, description);
+                }
+            });
+    }
+
+    /// renames and removes the gates and named signals of the displayed map.
+    /// the map as it is after an edit becomes what resetting goes back to,
+    /// as the state it was loaded in may refer to things which are gone
+    fn show_editor(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("new name");
+            ui.text_edit_singleline(&mut self.edit_name);
+        });
+        let new_name = self.edit_name.trim().to_string();
+        let mut writeable = self.map.write().expect("should be able to edit map!");
+        let mut gates = writeable
+            .gates()
+            .map(|gate| {
+                let name = writeable
+                    .gate_name(gate)
+                    .map_or_else(|| gate.to_string(), |name| name.to_string());
+                (gate, name)
+            })
+            .collect::<Vec<_>>();
+        gates.sort_by(|(_, a), (_, b)| a.cmp(b));
+        let mut signals = writeable
+            .signal_names()
+            .map(|(name, bus)| (name.to_string(), bus.clone()))
+            .collect::<Vec<_>>();
+        signals.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let (mut rename_gate, mut remove_gate) = (None, None);
+        let (mut rename_signal, mut remove_signal) = (None, None);
+        egui::Grid::new("editor").striped(true).show(ui, |ui| {
+            for (gate, name) in &gates {
+                ui.label(format!("gate {name}"));
+                if ui
+                    .add_enabled(!new_name.is_empty(), egui::Button::new("rename"))
+                    .clicked()
+                {
+                    rename_gate = Some(*gate);
+                }
+                if ui.button("remove").clicked() {
+                    remove_gate = Some(*gate);
+                }
+                ui.end_row();
+            }
+            for (name, bus) in &signals {
+                ui.label(format!("signal {name}"));
+                if ui
+                    .add_enabled(!new_name.is_empty(), egui::Button::new("rename"))
+                    .clicked()
+                {
+                    rename_signal = Some(name.clone());
+                }
+                if ui.button("remove").clicked() {
+                    remove_signal = Some(bus.clone());
+                }
+                ui.end_row();
+            }
+        });
+
+        let render_data = &mut self.render_data;
+        let result = if let Some(gate) = rename_gate {
+            render_data.rename_gate(&mut writeable, gate, new_name)
+        } else if let Some(gate) = remove_gate {
+            render_data.remove_gate(&mut writeable, gate)
+        } else if let Some(old) = rename_signal {
+            render_data.rename_signal(&mut writeable, &old, new_name)
+        } else if let Some(bus) = remove_signal {
+            bus.bits().iter().try_for_each(|bit| match *bit {
+                ConnectionPoint::Input(id) => render_data.remove_input(&mut writeable, id),
+                ConnectionPoint::Output(id) => render_data.remove_output(&mut writeable, id),
+                ConnectionPoint::MiddleSignal(id) => {
+                    render_data.remove_middle_signal(&mut writeable, id)
+                }
+                _ => Ok(()),
+            })
+        } else {
+            return;
+        };
+        if let Err(error) = result {
+            self.edit_error = Some(error.to_string());
+            return;
+        }
+        self.edit_error = None;
+        self.edit_name.clear();
+        // the flattened map, statistics and truth table were all of the map before the edit
+        self.other_view = None;
+        self.statistics = None;
+        self.truth_table = None;
+        self.render_data
+            .set_highlighted(HashSet::new(), HashSet::new());
+        self.initial = writeable.snapshot();
+        let removed = recompile(&mut self.compiled, &writeable);
+        if !removed.is_empty() {
+            self.waveform_error = Some(format!(
+                "stopped recording {} as they were removed",
+                removed.join(", ")
+            ));
+        }
+    }
+
+    fn show_snapshots(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.snapshot_name);
+            let name = self.snapshot_name.trim().to_string();
+            if ui
+                .add_enabled(!name.is_empty(), egui::Button::new("take snapshot"))
+                .clicked()
+            {
+                let snapshot = self
+                    .map
+                    .read()
+                    .expect("should be able to read map!")
+                    .snapshot();
+                self.snapshots.retain(|(existing, _)| *existing != name);
+                self.snapshots.push((name, snapshot));
+                self.snapshot_name.clear();
+            }
+            if ui
+                .button(format!("save to {}", self.snapshot_path.display()))
+                .clicked()
+            {
+                let text = format_snapshots(
+                    &self.map.read().expect("should be able to read map!"),
+                    &self.snapshots,
+                );
+                self.snapshot_error = std::fs::write(&self.snapshot_path, text)
+                    .err()
+                    .map(|error| format!("{}: {error}", self.snapshot_path.display()));
+            }
+        });
+        let mut restore = None;
+        let mut delete = None;
+        for (i, (name, _)) in self.snapshots.iter().enumerate() {
+            ui.horizontal(|ui| {
+                ui.label(name);
+                if ui.button("restore").clicked() {
+                    restore = Some(i);
+                }
+                if ui.button("delete").clicked() {
+                    delete = Some(i);
+                }
+            });
+        }
+        if let Some(i) = restore {
+            self.restore(&self.snapshots[i].1.clone());
+        }
+        if let Some(i) = delete {
+            self.snapshots.remove(i);
+        }
+        if let Some(error) = &self.snapshot_error {
+            ui.colored_label(Color32::RED, error);
+        }
+    }
+}
 impl App for LogicGateApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        if let Some(reason) = &self.invalid {
+            egui::TopBottomPanel::bottom("controls").show(ctx, |ui| {
+                self.show_diagnostics(ui);
+            });
+            egui::CentralPanel::default().show(ctx, |ui| {
+                ui.colored_label(Color32::RED, reason);
+            });
+            return;
+        }
         let click_position = ctx
             .input(|i| {
                 i.pointer
@@ -96,18 +729,212 @@ impl App for LogicGateApp {
                     .then_some(i.pointer.interact_pos())
             })
             .flatten();
+        egui::TopBottomPanel::bottom("controls").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let mut scheduler = self.compiled.scheduler();
+                ui.radio_value(&mut scheduler, Scheduler::Synchronous, "synchronous");
+                ui.radio_value(&mut scheduler, Scheduler::EventDriven, "event-driven");
+                ui.radio_value(&mut scheduler, Scheduler::Timed, "timed");
+                if scheduler != self.compiled.scheduler() {
+                    self.compiled.set_scheduler(scheduler);
+                }
+                let mut clocks_paused = self.compiled.clocks_paused();
+                if ui.checkbox(&mut clocks_paused, "pause clocks").changed() {
+                    self.compiled.set_clocks_paused(clocks_paused);
+                }
+                if ui.button("power on (unknown state)").clicked() {
+                    self.compiled.set_unknown();
+                }
+                if ui
+                    .add_enabled(!self.flattened, egui::Button::new("reset"))
+                    .clicked()
+                {
+                    self.restore(&self.initial.clone());
+                }
+                ui.checkbox(&mut self.show_waveform_view, "waveform viewer");
+                let mut flattened = self.flattened;
+                if ui.checkbox(&mut flattened, "flattened to NANDs").changed() {
+                    self.toggle_flattened();
+                }
+                if self.flattened {
+                    let readable = self.map.read().expect("should be able to read map!");
+                    ui.label(format!("{} NAND gates", readable.gates().count()));
+                }
+                match &self.settled {
+                    None => ui.label("clocks running"),
+                    Some(Ok(steps)) => ui.label(format!("stable after {steps} steps")),
+                    Some(Err(oscillation)) => {
+                        let readable = self.map.read().expect("should be able to read map!");
+                        ui.colored_label(Color32::ORANGE, oscillation.describe(&readable))
+                    }
+                };
+            });
+            if let Some(error) = &self.flatten_error {
+                ui.colored_label(Color32::RED, error);
+            }
+            if !self.contentions.is_empty() {
+                let readable = self.map.read().expect("should be able to read map!");
+                for contention in &self.contentions {
+                    ui.colored_label(Color32::RED, contention.describe(&readable));
+                }
+            }
+            if !self.unknown_signals.is_empty() {
+                let readable = self.map.read().expect("should be able to read map!");
+                ui.collapsing(
+                    format!("{} unknown signals", self.unknown_signals.len()),
+                    |ui| {
+                        for origin in &self.unknown_signals {
+                            ui.colored_label(Color32::ORANGE, readable.origin_name(origin));
+                        }
+                    },
+                );
+            }
+            ui.add_enabled_ui(!self.flattened, |ui| {
+                ui.collapsing(format!("{} snapshots", self.snapshots.len()), |ui| {
+                    self.show_snapshots(ui);
+                });
+                ui.collapsing("edit", |ui| {
+                    self.show_editor(ui);
+                });
+            });
+            ui.collapsing("waveforms", |ui| {
+                self.show_waveforms(ui);
+            });
+            self.show_diagnostics(ui);
+        });
+        // beneath the canvas, so added after the controls which go below it
+        let mut click_position = click_position;
+        if self.show_waveform_view {
+            let viewer = egui::TopBottomPanel::bottom("waveform viewer")
+                .resizable(true)
+                .default_height(200.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.strong("waveforms");
+                        ui.checkbox(&mut self.picking_signals, "pick signals by clicking them");
+                        if let Some(error) = &self.waveform_error {
+                            ui.colored_label(Color32::RED, error);
+                        }
+                    });
+                    match self.compiled.recorder() {
+                        Some(recorder) => self.waveform_view.show(recorder, ui),
+                        None => {
+                            ui.label("nothing is being recorded");
+                        }
+                    }
+                });
+            click_position =
+                click_position.filter(|position| !viewer.response.rect.contains(*position));
+        }
+        {
+            let mut writeable = self.map.write().expect("should be able to render map!");
+            if !memories(&writeable).is_empty() {
+                egui::SidePanel::right("memories").show(ctx, |ui| {
+                    self.render_data.show_memory_inspector(&mut writeable, ui);
+                });
+            }
+        }
+        egui::SidePanel::left("truth table").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("calculate statistics").clicked() {
+                    let readable = self.map.read().expect("should be able to read map!");
+                    let statistics = readable.statistics();
+                    self.render_data.set_highlighted(
+                        statistics.critical_connections.clone(),
+                        statistics.critical_gates.clone(),
+                    );
+                    self.statistics = Some(statistics);
+                }
+                if self.statistics.is_some() && ui.button("hide").clicked() {
+                    self.render_data
+                        .set_highlighted(HashSet::new(), HashSet::new());
+                    self.statistics = None;
+                }
+            });
+            if let Some(statistics) = &self.statistics {
+                ui.monospace(statistics.to_string());
+                ui.separator();
+            }
+            if ui.button("generate truth table").clicked() {
+                let readable = self.map.read().expect("should be able to read map!");
+                self.truth_table = Some(readable.truth_table());
+            }
+            match &self.truth_table {
+                Some(Ok(table)) => {
+                    egui::ScrollArea::both().show(ui, |ui| {
+                        egui::Grid::new("truth table grid")
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for name in table.inputs.iter().chain(&table.outputs) {
+                                    ui.strong(name);
+                                }
+                                ui.end_row();
+                                for row in &table.rows {
+                                    let cells = table.cells(row);
+                                    let (inputs, outputs) = cells.split_at(table.inputs.len());
+                                    for cell in inputs {
+                                        ui.label(cell);
+                                    }
+                                    for cell in outputs {
+                                        match row.outputs {
+                                            Ok(_) => ui.strong(cell),
+                                            Err(_) => ui.colored_label(Color32::ORANGE, cell),
+                                        };
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(Color32::RED, error.to_string());
+                }
+                None => {}
+            }
+        });
         egui::CentralPanel::default().show(ctx, |ui| {
-            // NOTE: there's a lot of allocation and deallocation here
-            // if we had some sort of double-buffer, then there would only
-            // be efficiency losses from basically memcpy-ing all the stuffs
-
+            // the compiled map is stepped in place, and the hierarchical map
+            // is only used for rendering and for picking up clicked inputs
             {
                 let mut writeable = self.map.write().expect("should be able to render map!");
-                for _ in 0..10 {
-                    *writeable = writeable.step();
-                }
+                self.compiled.load_inputs(&writeable);
+                self.compiled.load_memories(&writeable);
+                // a running clock never settles, so the map is ticked at a
+                // steady rate instead and only settled while they're paused
+                self.settled = if self.compiled.has_running_clocks() {
+                    for _ in 0..CLOCK_TICKS_PER_FRAME {
+                        self.compiled.step();
+                    }
+                    None
+                } else {
+                    Some(self.compiled.settle(10))
+                };
+                self.contentions = self.compiled.contentions();
+                self.unknown_signals = self.compiled.unknown_signals();
+                self.compiled.store(&mut writeable);
+                let click_position = match click_position {
+                    Some(position) if self.show_waveform_view && self.picking_signals => {
+                        // everything drawn there is recorded, like each bit of a bus
+                        for point in self.render_data.points_at(&writeable, ui, position) {
+                            let path = writeable.origin_name(&SignalOrigin {
+                                path: vec![],
+                                point,
+                            });
+                            if let Err(error) = self.compiled.record_signal(&writeable, &path) {
+                                self.waveform_error = Some(error.to_string());
+                            }
+                        }
+                        None
+                    }
+                    _ => click_position,
+                };
                 self.render_data
-                    .process_input_and_render(&mut writeable, click_position, ui)
+                    .process_input_and_render(
+                        &mut writeable,
+                        self.compiled.time(),
+                        click_position,
+                        ui,
+                    )
                     .expect("should be able to update and render!");
             }
 
@@ -0,0 +1,455 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    compiled::SignalOrigin,
+    flatten::{FlattenError, GateOrigin},
+    id::Id,
+    logic::Logic,
+    logic_gate::{Bus, ConnectionPoint},
+    logic_gate_map::LogicGateMap,
+};
+
+/// the passes in the order they're run. they're repeated until none of them
+/// changes anything, as each one can open up chances for the others
+pub const PASSES: [&str; 4] = [
+    "constant propagation",
+    "double NOT elimination",
+    "common subexpression merging",
+    "dead gate removal",
+];
+
+/// a flattened map after optimising, see `optimise`
+#[derive(Debug, Clone)]
+pub struct Optimised {
+    pub map: LogicGateMap,
+    /// the gate in the original map that each remaining NAND gate came from
+    #[allow(unused)]
+    pub origins: HashMap<Id, GateOrigin>,
+    /// how many NAND gates there were straight after flattening
+    pub before: usize,
+    /// how many gates each of `PASSES` removed altogether
+    pub removed: [usize; PASSES.len()],
+}
+impl Optimised {
+    pub fn after(&self) -> usize {
+        self.map.gates().count()
+    }
+}
+impl Display for Optimised {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} NAND gates after flattening", self.before)?;
+        for (pass, removed) in PASSES.iter().zip(self.removed) {
+            writeln!(f, "{pass} removed {removed}")?;
+        }
+        write!(f, "{} NAND gates after optimising", self.after())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptimiseError {
+    Flatten(FlattenError),
+    /// a point in the flattened map driven by more than one connection,
+    /// which can't be treated as a single function of its inputs
+    MultipleDrivers(String),
+}
+impl Display for OptimiseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptimiseError::Flatten(error) => write!(f, "{error}"),
+            OptimiseError::MultipleDrivers(point) => {
+                write!(f, "{point} is driven by more than one connection")
+            }
+        }
+    }
+}
+
+/// where a NAND input or an output gets its value from. anything undriven
+/// keeps whatever value it has, so it's a constant
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Source {
+    Input(Id),
+    Gate(usize),
+    Constant(Logic),
+}
+
+/// the flattened map as a list of NAND gates which can be rewritten.
+/// a removed gate is `None`, and anything reading it reads `replaced` instead
+struct Netlist {
+    gates: Vec<Option<[Source; 2]>>,
+    replaced: Vec<Option<Source>>,
+    outputs: Vec<(Id, Source)>,
+}
+impl Netlist {
+    fn resolve(&self, mut source: Source) -> Source {
+        while let Source::Gate(gate) = source
+            && let Some(replacement) = self.replaced[gate]
+        {
+            source = replacement;
+        }
+        source
+    }
+
+    fn replace(&mut self, gate: usize, source: Source) {
+        self.gates[gate] = None;
+        self.replaced[gate] = Some(source);
+    }
+
+    /// makes everything read from gates which are still there
+    fn resolve_all(&mut self) {
+        for gate in 0..self.gates.len() {
+            if let Some(inputs) = self.gates[gate] {
+                self.gates[gate] = Some(inputs.map(|source| self.resolve(source)));
+            }
+        }
+        for i in 0..self.outputs.len() {
+            self.outputs[i].1 = self.resolve(self.outputs[i].1);
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.gates.iter().flatten().count()
+    }
+
+    /// a `Zero` on either input makes the output `One`, two `One`s make it `Zero`,
+    /// and a single `One` turns the gate into a NOT of the other input
+    fn propagate_constants(&mut self) -> bool {
+        let mut changed = false;
+        for gate in 0..self.gates.len() {
+            let Some(inputs) = self.gates[gate] else {
+                continue;
+            };
+            let one = Source::Constant(Logic::One);
+            match inputs {
+                [Source::Constant(Logic::Zero), _] | [_, Source::Constant(Logic::Zero)] => {
+                    self.replace(gate, one);
+                }
+                [a, b] if a == one && b == one => {
+                    self.replace(gate, Source::Constant(Logic::Zero));
+                }
+                [a, other] | [other, a] if a == one => {
+                    self.gates[gate] = Some([other, other]);
+                }
+                _ => continue,
+            }
+            changed = true;
+            self.resolve_all();
+        }
+        changed
+    }
+
+    fn eliminate_double_nots(&mut self) -> bool {
+        let mut changed = false;
+        for gate in 0..self.gates.len() {
+            let Some([Source::Gate(a), Source::Gate(b)]) = self.gates[gate] else {
+                continue;
+            };
+            // a gate which is a NOT of itself is left alone, as there's nothing to replace it with
+            if a == b
+                && let Some([c, d]) = self.gates[a]
+                && c == d
+                && c != Source::Gate(gate)
+            {
+                self.replace(gate, c);
+                self.resolve_all();
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// keeps the first of any gates with the same inputs, either way round
+    fn merge_common_subexpressions(&mut self) -> bool {
+        let mut changed = false;
+        let mut seen: HashMap<(Source, Source), usize> = HashMap::new();
+        for gate in 0..self.gates.len() {
+            let Some([a, b]) = self.gates[gate] else {
+                continue;
+            };
+            match seen.get(&(a, b)).or_else(|| seen.get(&(b, a))) {
+                Some(first) => {
+                    self.replace(gate, Source::Gate(*first));
+                    changed = true;
+                }
+                None => {
+                    seen.insert((a, b), gate);
+                }
+            }
+        }
+        self.resolve_all();
+        changed
+    }
+
+    /// removes every gate which no output depends on
+    fn remove_dead_gates(&mut self) -> bool {
+        let mut live = vec![false; self.gates.len()];
+        let mut stack = self
+            .outputs
+            .iter()
+            .map(|(_, source)| *source)
+            .collect::<Vec<_>>();
+        while let Some(source) = stack.pop() {
+            if let Source::Gate(gate) = source
+                && !live[gate]
+            {
+                live[gate] = true;
+                stack.extend(self.gates[gate].into_iter().flatten());
+            }
+        }
+        let mut changed = false;
+        for (gate, live) in live.into_iter().enumerate() {
+            if !live && self.gates[gate].is_some() {
+                self.gates[gate] = None;
+                changed = true;
+            }
+        }
+        changed
+    }
+}
+
+/// flattens the map and then runs every one of `PASSES` on it. the result has
+/// the same outputs once it settles, but as gates are taken out of loops,
+/// it might take a different number of steps to get there
+pub fn optimise(map: &LogicGateMap) -> Result<Optimised, OptimiseError> {
+    let flattened = map.flatten().map_err(OptimiseError::Flatten)?;
+    let flat = &flattened.map;
+    let mut gate_ids = flat.gates().collect::<Vec<_>>();
+    gate_ids.sort();
+    let indices = gate_ids
+        .iter()
+        .enumerate()
+        .map(|(i, id)| (*id, i))
+        .collect::<HashMap<_, _>>();
+
+    let mut drivers: HashMap<ConnectionPoint, Vec<ConnectionPoint>> = HashMap::new();
+    for (_, connection) in flat.connections() {
+        drivers
+            .entry(connection.end)
+            .or_default()
+            .push(connection.start);
+    }
+    // an output can be read like anything else, in which case whatever drives it is used
+    let source = |point: ConnectionPoint| {
+        let mut point = point;
+        for _ in 0..=drivers.len() {
+            let start = match drivers.get(&point).map(Vec::as_slice) {
+                None | Some([]) => {
+                    return Ok(Source::Constant(flat.connection_point_value(&point)));
+                }
+                Some([start]) => *start,
+                Some(_) => {
+                    let origin = SignalOrigin {
+                        path: vec![],
+                        point,
+                    };
+                    return Err(OptimiseError::MultipleDrivers(origin.to_string()));
+                }
+            };
+            match start {
+                ConnectionPoint::Input(id) => return Ok(Source::Input(id)),
+                ConnectionPoint::GateOutput { gate, .. } => {
+                    return Ok(Source::Gate(indices[&gate]));
+                }
+                _ => point = start,
+            }
+        }
+        Ok(Source::Constant(flat.connection_point_value(&point)))
+    };
+    let mut netlist = Netlist {
+        gates: vec![],
+        replaced: vec![None; gate_ids.len()],
+        outputs: vec![],
+    };
+    for gate in &gate_ids {
+        let inputs = flat.gate_by_id(*gate).inputs();
+        let point = |i: usize| ConnectionPoint::GateInput {
+            gate: *gate,
+            input: inputs[i],
+        };
+        netlist
+            .gates
+            .push(Some([source(point(0))?, source(point(1))?]));
+    }
+    let mut outputs = flat.outputs().map(|(id, _)| id).collect::<Vec<_>>();
+    outputs.sort();
+    for output in outputs {
+        let source = source(ConnectionPoint::Output(output))?;
+        netlist.outputs.push((output, source));
+    }
+
+    let mut removed = [0; PASSES.len()];
+    loop {
+        let mut changed = false;
+        for (i, removed) in removed.iter_mut().enumerate() {
+            let before = netlist.count();
+            changed |= match i {
+                0 => netlist.propagate_constants(),
+                1 => netlist.eliminate_double_nots(),
+                2 => netlist.merge_common_subexpressions(),
+                _ => netlist.remove_dead_gates(),
+            };
+            *removed += before - netlist.count();
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let (map, origins) = rebuild(flat, &flattened.origins, &gate_ids, &netlist);
+    Ok(Optimised {
+        map,
+        origins,
+        before: gate_ids.len(),
+        removed,
+    })
+}
+
+/// a new map with the netlist's gates, and the same inputs, outputs and names as `flat`
+fn rebuild(
+    flat: &LogicGateMap,
+    flat_origins: &HashMap<Id, GateOrigin>,
+    gate_ids: &[Id],
+    netlist: &Netlist,
+) -> (LogicGateMap, HashMap<Id, GateOrigin>) {
+    let mut map = LogicGateMap::empty();
+    let mut top_level = HashMap::new();
+    let mut inputs = flat.inputs().collect::<Vec<_>>();
+    inputs.sort_by_key(|(id, _)| *id);
+    for (id, value) in inputs {
+        let new_id = map.create_input();
+        map.set_input(new_id, value);
+        top_level.insert(ConnectionPoint::Input(id), ConnectionPoint::Input(new_id));
+    }
+    for (id, _) in &netlist.outputs {
+        let new_id = map.create_output();
+        let value = flat.connection_point_value(&ConnectionPoint::Output(*id));
+        map.set_connection_point_value(&ConnectionPoint::Output(new_id), value);
+        top_level.insert(
+            ConnectionPoint::Output(*id),
+            ConnectionPoint::Output(new_id),
+        );
+    }
+    for (name, bus) in flat.signal_names() {
+        let bits = bus.bits().iter().map(|bit| top_level[bit]).collect();
+        map.set_signal_name(name.to_string(), Bus::new(bits));
+    }
+
+    let mut origins = HashMap::new();
+    let mut outputs = HashMap::new();
+    for (gate, inputs) in netlist.gates.iter().enumerate() {
+        if inputs.is_some() {
+            let info = map.create_nand_gate();
+            let old = ConnectionPoint::GateOutput {
+                gate: gate_ids[gate],
+                output: flat.gate_by_id(gate_ids[gate]).outputs()[0],
+            };
+            map.set_connection_point_value(
+                &info.output_connection(0),
+                flat.connection_point_value(&old),
+            );
+            outputs.insert(gate, info.output_connection(0));
+            origins.insert(info.gate_id(), flat_origins[&gate_ids[gate]].clone());
+        }
+    }
+    // a constant is left undriven, holding its value
+    let connect = |map: &mut LogicGateMap, source: Source, end: ConnectionPoint| {
+        let start = match source {
+            Source::Input(id) => top_level[&ConnectionPoint::Input(id)],
+            Source::Gate(gate) => outputs[&gate],
+            Source::Constant(value) => {
+                map.set_connection_point_value(&end, value);
+                return;
+            }
+        };
+        map.set_connection_point_value(&end, map.connection_point_value(&start));
+        map.create_connection((start, end));
+    };
+    for (gate, inputs) in netlist.gates.iter().enumerate() {
+        let Some(inputs) = inputs else {
+            continue;
+        };
+        let ConnectionPoint::GateOutput { gate: new_gate, .. } = outputs[&gate] else {
+            panic!("a NAND output should be a gate output!");
+        };
+        let pins = map.gate_by_id(new_gate).inputs();
+        for (source, pin) in inputs.iter().zip(pins) {
+            let end = ConnectionPoint::GateInput {
+                gate: new_gate,
+                input: pin,
+            };
+            connect(&mut map, *source, end);
+        }
+    }
+    for (id, source) in &netlist.outputs {
+        connect(&mut map, *source, top_level[&ConnectionPoint::Output(*id)]);
+    }
+
+    (map, origins)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        flatten::tests::{PRIMITIVES, assert_same_truth_table},
+        parse::tests::parse_gate,
+    };
+
+    const REDUNDANT: &str = "version 0
+define_gate not
+inputs in
+outputs out
+nands n
+connections in => n in 0, in => n in 1, n out 0 => out
+define_gate redundant
+inputs a b
+outputs same twice
+custom_gates first = not, second = not, third = not, fourth = not
+connections a => first in 0, first out 0 => second in 0, second out 0 => same
+nands join
+connections b => third in 0, b => fourth in 0
+connections third out 0 => join in 0, fourth out 0 => join in 1, join out 0 => twice
+";
+
+    #[test]
+    fn optimising_keeps_the_truth_table() {
+        let gates = include_str!("../gates.dat");
+        let maps = ["not", "and", "or", "nor"]
+            .map(|name| parse_gate(gates, name))
+            .into_iter()
+            .chain([parse_gate(PRIMITIVES, "primitives")]);
+        for map in maps {
+            let optimised = map.optimise().expect("should be able to optimise!");
+            assert!(optimised.after() <= optimised.before);
+            assert_same_truth_table(&map, &optimised.map);
+        }
+    }
+
+    #[test]
+    fn redundant_gates_are_removed() {
+        let map = parse_gate(REDUNDANT, "redundant");
+        let optimised = map.optimise().expect("should be able to optimise!");
+        assert_eq!(optimised.before, 5);
+        assert_eq!(optimised.after(), 0);
+        let removed = |pass| optimised.removed[PASSES.iter().position(|x| *x == pass).unwrap()];
+        assert!(removed("double NOT elimination") > 0);
+        assert!(removed("common subexpression merging") > 0);
+        assert_same_truth_table(&map, &optimised.map);
+    }
+
+    #[test]
+    fn points_with_several_drivers_cant_be_optimised() {
+        let map = parse_gate(
+            "version 0
+define_gate shared
+inputs a b
+outputs out
+connections a => out, b => out
+",
+            "shared",
+        );
+        assert!(matches!(
+            map.optimise(),
+            Err(OptimiseError::MultipleDrivers(_))
+        ));
+    }
+}
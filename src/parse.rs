@@ -1,12 +1,13 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::Path};
 
 use eframe::egui::Pos2;
 
 use crate::{
     id::Id,
-    logic_gate::{ConnectionPoint, GateCreationInfo},
-    logic_gate_map::LogicGateMap,
+    logic_gate::{Bus, Clock, ConnectionPoint, GateCreationInfo, PrimitiveKind},
+    logic_gate_map::{LogicGateMap, MAX_ADDRESS_WIDTH, MAX_BUS_WIDTH, MAX_DELAY},
     render::MapRenderSavedState,
+    validate::Diagnostic,
 };
 
 #[derive(Debug, Clone)]
@@ -19,11 +20,35 @@ pub enum LogicGateMapParseError {
     UnrecognisedCommand(usize, String),
     InvalidConnectionPoint(usize, String, String),
     InvalidRenderLine(usize, String),
+    InvalidBusWidth(usize, String),
+    BusWidthMismatch(usize, String),
+    InvalidDelay(usize, String),
+    InvalidInputCount(usize, String),
+    PrimitiveNotAllowed(usize, String),
+    InvalidClock(usize, String),
+    InvalidMemory(usize, String),
+    /// the line, and the reason the memory's file couldn't be loaded
+    InvalidMemoryFile(usize, String, String),
 }
 
+/// a `define_gate` from a `.dat` file
+#[derive(Debug, Clone)]
+pub struct ParsedGate {
+    pub name: String,
+    pub map: LogicGateMap,
+    /// only there if every gate in the definition has somewhere to be drawn
+    pub render_data: Option<MapRenderSavedState>,
+    /// what `LogicGateMap::validate` found wrong with the definition
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// the gates are in the order they were defined in.
+/// files referenced by the text, like the contents of a ROM,
+/// are found relative to `directory`
 pub fn parse_text(
     value: &str,
-) -> Result<Vec<(LogicGateMap, Option<MapRenderSavedState>)>, LogicGateMapParseError> {
+    directory: &Path,
+) -> Result<Vec<ParsedGate>, LogicGateMapParseError> {
     let mut lines = value.lines().filter(|l| !l.trim().is_empty());
     let Some(version_line) = lines.next() else {
         return Err(LogicGateMapParseError::MissingVersionLine);
@@ -38,7 +63,7 @@ pub fn parse_text(
     };
 
     match version {
-        0 => parse_version_0(lines),
+        0 => parse_version_0(lines, directory),
         _ => Err(LogicGateMapParseError::InvalidVersionLine(
             version_line.to_string(),
         )),
@@ -46,21 +71,35 @@ pub fn parse_text(
 }
 fn parse_version_0<'a>(
     lines: impl Iterator<Item = &'a str>,
-) -> Result<Vec<(LogicGateMap, Option<MapRenderSavedState>)>, LogicGateMapParseError> {
+    directory: &Path,
+) -> Result<Vec<ParsedGate>, LogicGateMapParseError> {
+    let mut names: Vec<String> = vec![];
     let mut results = HashMap::new();
     let mut renderers = HashMap::new();
     let mut current = None;
 
     let mut inputs = HashMap::new();
     let mut outputs = HashMap::new();
-    let mut nands = HashMap::new();
+    let mut buses = HashMap::new();
+    let mut primitive_gates = HashMap::new();
     let mut custom_gates = HashMap::new();
 
     for (line_number, line) in lines.enumerate() {
         if let Some(name) = line.strip_prefix("define_gate ") {
-            results.insert(name.to_string(), LogicGateMap::empty());
+            if !names.iter().any(|x| x == name) {
+                names.push(name.to_string());
+            }
+            let mut map = LogicGateMap::empty();
+            map.set_name(name.to_string());
+            results.insert(name.to_string(), map);
             renderers.insert(name.to_string(), MapRenderSavedState::new());
             current = Some(name.to_string());
+            // names only refer to things in the definition they're declared in
+            inputs.clear();
+            outputs.clear();
+            buses.clear();
+            primitive_gates.clear();
+            custom_gates.clear();
         } else if let Some(operands) = line.strip_prefix("inputs ") {
             let Some(current) = current.as_ref() else {
                 return Err(LogicGateMapParseError::NoCurrentGate(
@@ -73,9 +112,25 @@ fn parse_version_0<'a>(
                 .map(|name| name.trim())
                 .filter(|name| !name.is_empty())
             {
-                let id = results.get_mut(current).unwrap().create_input();
-                inputs.insert(input_name.to_string(), id);
-                renderers.get_mut(current).unwrap().add_input(id);
+                let map = results.get_mut(current).unwrap();
+                match parse_version_0_bus_declaration(line_number, line, input_name)? {
+                    (name, None) => {
+                        let id = map.create_input();
+                        map.set_signal_name(name.to_string(), ConnectionPoint::Input(id).into());
+                        inputs.insert(name.to_string(), id);
+                        renderers.get_mut(current).unwrap().add_input(id);
+                    }
+                    (name, Some(width)) => {
+                        let id = map.create_input_bus(width);
+                        let bus = map.bus_by_id(id).clone();
+                        map.set_signal_name(name.to_string(), bus.clone());
+                        renderers
+                            .get_mut(current)
+                            .unwrap()
+                            .add_input_bus(bus_ids(&bus));
+                        buses.insert(name.to_string(), bus);
+                    }
+                }
             }
         } else if let Some(operands) = line.strip_prefix("outputs ") {
             let Some(current) = current.as_ref() else {
@@ -89,9 +144,53 @@ fn parse_version_0<'a>(
                 .map(|name| name.trim())
                 .filter(|name| !name.is_empty())
             {
-                let id = results.get_mut(current).unwrap().create_output();
-                outputs.insert(output_name.to_string(), id);
-                renderers.get_mut(current).unwrap().add_output(id);
+                let map = results.get_mut(current).unwrap();
+                match parse_version_0_bus_declaration(line_number, line, output_name)? {
+                    (name, None) => {
+                        let id = map.create_output();
+                        map.set_signal_name(name.to_string(), ConnectionPoint::Output(id).into());
+                        outputs.insert(name.to_string(), id);
+                        renderers.get_mut(current).unwrap().add_output(id);
+                    }
+                    (name, Some(width)) => {
+                        let id = map.create_output_bus(width);
+                        let bus = map.bus_by_id(id).clone();
+                        map.set_signal_name(name.to_string(), bus.clone());
+                        renderers
+                            .get_mut(current)
+                            .unwrap()
+                            .add_output_bus(bus_ids(&bus));
+                        buses.insert(name.to_string(), bus);
+                    }
+                }
+            }
+        } else if let Some(operands) = line.strip_prefix("signals ") {
+            // signals name name:width, which are neither inputs nor outputs.
+            // a single signal is kept as a bus of width 1 so it can be used the same way
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            for signal_name in operands
+                .split_whitespace()
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+            {
+                let map = results.get_mut(current).unwrap();
+                let bus = match parse_version_0_bus_declaration(line_number, line, signal_name)? {
+                    (name, None) => (
+                        name,
+                        Bus::from(ConnectionPoint::MiddleSignal(map.create_middle_signal())),
+                    ),
+                    (name, Some(width)) => {
+                        let id = map.create_middle_signal_bus(width);
+                        (name, map.bus_by_id(id).clone())
+                    }
+                };
+                map.set_signal_name(bus.0.to_string(), bus.1.clone());
+                buses.insert(bus.0.to_string(), bus.1);
             }
         } else if let Some(operands) = line.strip_prefix("nands ") {
             let Some(current) = current.as_ref() else {
@@ -100,13 +199,264 @@ fn parse_version_0<'a>(
                     line.to_string(),
                 ));
             };
-            for nand_name in operands
+            for gate_name in operands
+                .split_whitespace()
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+            {
+                let map = results.get_mut(current).unwrap();
+                let id = map.create_nand_gate();
+                map.set_gate_name(id.gate_id(), gate_name.to_string());
+                primitive_gates.insert(gate_name.to_string(), id);
+            }
+        } else if let Some(operands) = line.strip_prefix("tri_states ") {
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            if results[current].nand_only() {
+                return Err(LogicGateMapParseError::PrimitiveNotAllowed(
+                    line_number,
+                    line.to_string(),
+                ));
+            }
+            for gate_name in operands
+                .split_whitespace()
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+            {
+                let map = results.get_mut(current).unwrap();
+                let id = map.create_tri_state_buffer();
+                map.set_gate_name(id.gate_id(), gate_name.to_string());
+                primitive_gates.insert(gate_name.to_string(), id);
+            }
+        } else if line.trim() == "nand_only" {
+            // only applies to the current definition, and has to come before
+            // anything it would have refused
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            let map = results.get_mut(current).unwrap();
+            if !map.only_has_nand_gates() {
+                return Err(LogicGateMapParseError::PrimitiveNotAllowed(
+                    line_number,
+                    line.to_string(),
+                ));
+            }
+            map.set_nand_only(true);
+        } else if line.trim() == "expect_feedback" {
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            results.get_mut(current).unwrap().set_expects_feedback(true);
+        } else if let Some(operand) = line.strip_prefix("default_delay ") {
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            let Some(delay) = operand
+                .trim()
+                .parse()
+                .ok()
+                .filter(|delay| (1..=MAX_DELAY).contains(delay))
+            else {
+                return Err(LogicGateMapParseError::InvalidDelay(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            results.get_mut(current).unwrap().set_default_delay(delay);
+        } else if let Some(operands) = line.strip_prefix("delay ") {
+            // delay ticks gate_a gate_b ...
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            let mut parts = operands.split_whitespace();
+            let Some(delay) = parts
+                .next()
+                .and_then(|x| x.parse().ok())
+                .filter(|delay| (1..=MAX_DELAY).contains(delay))
+            else {
+                return Err(LogicGateMapParseError::InvalidDelay(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            for gate_name in parts {
+                let Some(gate) = primitive_gates
+                    .get(gate_name)
+                    .or_else(|| custom_gates.get(gate_name))
+                else {
+                    return Err(LogicGateMapParseError::InvalidDelay(
+                        line_number,
+                        line.to_string(),
+                    ));
+                };
+                results
+                    .get_mut(current)
+                    .unwrap()
+                    .set_gate_delay(gate.gate_id(), delay);
+            }
+        } else if let Some(operands) = line.strip_prefix("clocks ") {
+            // clocks name:period:duty:phase, where the duty defaults to half the period
+            // and the phase defaults to 0
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            for definition in operands
+                .split_whitespace()
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+            {
+                let parts: Vec<_> = definition.split(':').collect();
+                let numbers: Option<Vec<u32>> = parts[1..].iter().map(|x| x.parse().ok()).collect();
+                let clock = match numbers.as_deref() {
+                    Some([period]) => Some((*period, period / 2, 0)),
+                    Some([period, duty]) => Some((*period, *duty, 0)),
+                    Some([period, duty, phase]) => Some((*period, *duty, *phase)),
+                    _ => None,
+                }
+                .map(|(period, duty, phase)| Clock {
+                    period,
+                    duty,
+                    phase,
+                });
+                let Some(clock) = clock.filter(|x| x.period > 0 && x.duty <= x.period) else {
+                    return Err(LogicGateMapParseError::InvalidClock(
+                        line_number,
+                        line.to_string(),
+                    ));
+                };
+                let map = results.get_mut(current).unwrap();
+                let id = map.create_clock(clock);
+                map.set_gate_name(id.gate_id(), parts[0].to_string());
+                primitive_gates.insert(parts[0].to_string(), id);
+            }
+        } else if let Some((writable, operands)) = line
+            .strip_prefix("roms ")
+            .map(|operands| (false, operands))
+            .or_else(|| line.strip_prefix("rams ").map(|operands| (true, operands)))
+        {
+            // roms name:address_width:data_width:file, where a ram's file is optional
+            // and it starts off with every word at zero without one
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            if results[current].nand_only() {
+                return Err(LogicGateMapParseError::PrimitiveNotAllowed(
+                    line_number,
+                    line.to_string(),
+                ));
+            }
+            for definition in operands
+                .split_whitespace()
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+            {
+                let parts: Vec<_> = definition.splitn(4, ':').collect();
+                let widths = match parts.as_slice() {
+                    [_, address_width, data_width, ..] => {
+                        address_width.parse().ok().zip(data_width.parse().ok())
+                    }
+                    _ => None,
+                };
+                let Some((address_width, data_width)) =
+                    widths.filter(|(address_width, data_width)| {
+                        *address_width <= MAX_ADDRESS_WIDTH && (1..=64).contains(data_width)
+                    })
+                else {
+                    return Err(LogicGateMapParseError::InvalidMemory(
+                        line_number,
+                        line.to_string(),
+                    ));
+                };
+                let contents = match parts.get(3) {
+                    Some(file) => {
+                        read_memory_file(&directory.join(file), data_width).map_err(|reason| {
+                            LogicGateMapParseError::InvalidMemoryFile(
+                                line_number,
+                                line.to_string(),
+                                reason,
+                            )
+                        })?
+                    }
+                    None if writable => vec![],
+                    None => {
+                        return Err(LogicGateMapParseError::InvalidMemory(
+                            line_number,
+                            line.to_string(),
+                        ));
+                    }
+                };
+                if contents.len() > 1usize << address_width {
+                    return Err(LogicGateMapParseError::InvalidMemoryFile(
+                        line_number,
+                        line.to_string(),
+                        format!("{} words don't fit in the memory", contents.len()),
+                    ));
+                }
+                let map = results.get_mut(current).unwrap();
+                let id = if writable {
+                    map.create_ram(address_width, data_width, contents)
+                } else {
+                    map.create_rom(address_width, data_width, contents)
+                };
+                map.set_gate_name(id.gate_id(), parts[0].to_string());
+                primitive_gates.insert(parts[0].to_string(), id);
+            }
+        } else if let Some((kind, operands)) = parse_version_0_primitive_command(line) {
+            // ands a b:3, where the number after the colon is the input count
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            if results[current].nand_only() {
+                return Err(LogicGateMapParseError::PrimitiveNotAllowed(
+                    line_number,
+                    line.to_string(),
+                ));
+            }
+            for definition in operands
                 .split_whitespace()
                 .map(|name| name.trim())
                 .filter(|name| !name.is_empty())
             {
-                let id = results.get_mut(current).unwrap().create_nand_gate();
-                nands.insert(nand_name.to_string(), id);
+                let (gate_name, input_count) = match definition.split_once(':') {
+                    Some((gate_name, input_count)) => (gate_name, input_count.parse().ok()),
+                    None if kind.allows_input_count(1) => (definition, Some(1)),
+                    None => (definition, Some(2)),
+                };
+                let Some(input_count) = input_count.filter(|x| kind.allows_input_count(*x)) else {
+                    return Err(LogicGateMapParseError::InvalidInputCount(
+                        line_number,
+                        line.to_string(),
+                    ));
+                };
+                let map = results.get_mut(current).unwrap();
+                let id = map.create_primitive_gate(kind, input_count);
+                map.set_gate_name(id.gate_id(), gate_name.to_string());
+                primitive_gates.insert(gate_name.to_string(), id);
             }
         } else if let Some(operands) = line.strip_prefix("custom_gates ") {
             let Some(current) = current.as_ref() else {
@@ -143,11 +493,16 @@ fn parse_version_0<'a>(
                         line.to_string(),
                     ));
                 };
+                if results[current].nand_only() && !chosen_custom_gate.only_has_nand_gates() {
+                    return Err(LogicGateMapParseError::PrimitiveNotAllowed(
+                        line_number,
+                        line.to_string(),
+                    ));
+                }
                 let current = results.get_mut(current).unwrap();
-                custom_gates.insert(
-                    parts[0].to_string(),
-                    current.create_custom_gate(chosen_custom_gate),
-                );
+                let id = current.create_custom_gate(chosen_custom_gate);
+                current.set_gate_name(id.gate_id(), parts[0].to_string());
+                custom_gates.insert(parts[0].to_string(), id);
             }
         } else if let Some(operands) = line.strip_prefix("connections ") {
             for definition in operands
@@ -168,24 +523,32 @@ fn parse_version_0<'a>(
                     ));
                 };
 
-                let start = parse_version_0_connection_point(
+                let start = parse_version_0_bus(
                     line_number,
                     line,
                     parts[0],
                     &inputs,
                     &outputs,
-                    &nands,
+                    &buses,
+                    &primitive_gates,
                     &custom_gates,
                 )?;
-                let end = parse_version_0_connection_point(
+                let end = parse_version_0_bus(
                     line_number,
                     line,
                     parts[1],
                     &inputs,
                     &outputs,
-                    &nands,
+                    &buses,
+                    &primitive_gates,
                     &custom_gates,
                 )?;
+                if start.width() != end.width() {
+                    return Err(LogicGateMapParseError::BusWidthMismatch(
+                        line_number,
+                        line.to_string(),
+                    ));
+                }
 
                 let Some(current) = current.as_ref() else {
                     return Err(LogicGateMapParseError::NoCurrentGate(
@@ -194,17 +557,24 @@ fn parse_version_0<'a>(
                     ));
                 };
                 let current = results.get_mut(current).unwrap();
-                current.create_connection((start, end));
+                if start.width() == 1 {
+                    current.create_connection((start.bit(0), end.bit(0)));
+                } else {
+                    current.create_bus_connection(start, end);
+                }
             }
-        } else if let Some(operands) = line.strip_prefix("render_nand_gate ") {
+        } else if let Some(operands) = line
+            .strip_prefix("render_nand_gate ")
+            .or_else(|| line.strip_prefix("render_gate "))
+        {
             let parts: Vec<_> = operands
                 .split_whitespace()
                 .map(|x| x.trim())
                 .filter(|x| !x.is_empty())
                 .collect();
-            // render_nand_gate x y some name
+            // render_gate name x y some name, for any gate which isn't a custom gate
             if parts.len() >= 4 {
-                let Some(id) = nands.get(parts[0]).map(|gate| gate.gate_id()) else {
+                let Some(id) = primitive_gates.get(parts[0]).map(|gate| gate.gate_id()) else {
                     return Err(LogicGateMapParseError::InvalidRenderLine(
                         line_number,
                         line.to_string(),
@@ -235,6 +605,42 @@ fn parse_version_0<'a>(
                     name,
                 );
             }
+        } else if let Some(operands) = line.strip_prefix("render_signal ") {
+            // render_signal name x y, with an optional label to show instead of the name
+            let parts: Vec<_> = operands.split_whitespace().collect();
+            let position = match parts.as_slice() {
+                [_, x, y, ..] => x.parse::<usize>().ok().zip(y.parse::<usize>().ok()),
+                _ => None,
+            };
+            let bus = parts
+                .first()
+                .and_then(|name| buses.get(*name))
+                .filter(|bus| {
+                    bus.bits()
+                        .iter()
+                        .all(|bit| matches!(bit, ConnectionPoint::MiddleSignal(_)))
+                });
+            let (Some((x, y)), Some(bus)) = (position, bus) else {
+                return Err(LogicGateMapParseError::InvalidRenderLine(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            let label = match parts[3..].join(" ") {
+                label if label.is_empty() => parts[0].to_string(),
+                label => label,
+            };
+            let Some(current) = current.as_ref() else {
+                return Err(LogicGateMapParseError::NoCurrentGate(
+                    line_number,
+                    line.to_string(),
+                ));
+            };
+            renderers.get_mut(current).unwrap().add_signal(
+                bus_ids(bus),
+                Pos2::new(x as f32, y as f32),
+                label,
+            );
         } else if let Some(operands) = line.strip_prefix("render_custom_gate ") {
             let parts: Vec<_> = operands
                 .split_whitespace()
@@ -281,26 +687,196 @@ fn parse_version_0<'a>(
             ));
         }
     }
-    Ok(results
+    Ok(names
         .into_iter()
-        .map(|(name, map)| (map, renderers.remove(&name).unwrap()))
-        .map(|(map, renderer)| {
-            let renderer = map
-                .gates()
-                .all(|x| renderer.has_gate(x))
-                .then_some(renderer);
-            (map, renderer)
+        .map(|name| {
+            let map = results.remove(&name).unwrap();
+            let renderer = renderers.remove(&name).unwrap();
+            let render_data = (map.gates().all(|x| renderer.has_gate(x))
+                && map.middle_signals().all(|(x, _)| renderer.has_signal(x)))
+            .then_some(renderer);
+            ParsedGate {
+                diagnostics: map.validate(),
+                name,
+                map,
+                render_data,
+            }
         })
         .collect())
 }
 
+fn parse_version_0_primitive_command(line: &str) -> Option<(PrimitiveKind, &str)> {
+    [
+        ("ands ", PrimitiveKind::And),
+        ("ors ", PrimitiveKind::Or),
+        ("nots ", PrimitiveKind::Not),
+        ("xors ", PrimitiveKind::Xor),
+        ("xnors ", PrimitiveKind::Xnor),
+        ("nors ", PrimitiveKind::Nor),
+        ("buffers ", PrimitiveKind::Buffer),
+    ]
+    .into_iter()
+    .find_map(|(command, kind)| line.strip_prefix(command).map(|operands| (kind, operands)))
+}
+
+/// a `.bin` file is raw bytes, with each word taking as many bytes as it needs,
+/// least significant byte first. anything else is text with one word in hex
+/// on each line or separated by spaces
+fn read_memory_file(path: &Path, data_width: usize) -> Result<Vec<u64>, String> {
+    let mask = u64::MAX >> (64 - data_width);
+    if path.extension().is_some_and(|extension| extension == "bin") {
+        let bytes = std::fs::read(path).map_err(|error| format!("{}: {error}", path.display()))?;
+        return Ok(bytes
+            .chunks(data_width.div_ceil(8))
+            .map(|word| {
+                word.iter()
+                    .rev()
+                    .fold(0, |value, byte| (value << 8) | *byte as u64)
+                    & mask
+            })
+            .collect());
+    }
+    std::fs::read_to_string(path)
+        .map_err(|error| format!("{}: {error}", path.display()))?
+        .split_whitespace()
+        .map(|word| {
+            let digits = word.strip_prefix("0x").unwrap_or(word);
+            u64::from_str_radix(digits, 16)
+                .ok()
+                .filter(|value| *value <= mask)
+                .ok_or_else(|| format!("{word} isn't a {data_width} bit hex word"))
+        })
+        .collect()
+}
+
+/// `name` declares a single bit, and `name:width` declares a bus of up to 64 bits
+fn parse_version_0_bus_declaration<'a>(
+    line_number: usize,
+    line: &str,
+    text: &'a str,
+) -> Result<(&'a str, Option<usize>), LogicGateMapParseError> {
+    let Some((name, width)) = text.split_once(':') else {
+        return Ok((text, None));
+    };
+    match width.parse() {
+        Ok(width) if (1..=MAX_BUS_WIDTH).contains(&width) => Ok((name, Some(width))),
+        _ => Err(LogicGateMapParseError::InvalidBusWidth(
+            line_number,
+            line.to_string(),
+        )),
+    }
+}
+
+fn bus_ids(bus: &Bus) -> Vec<Id> {
+    bus.bits()
+        .iter()
+        .map(|bit| match bit {
+            ConnectionPoint::Input(id)
+            | ConnectionPoint::Output(id)
+            | ConnectionPoint::MiddleSignal(id) => *id,
+            _ => panic!("declared buses should only contain signals!"),
+        })
+        .collect()
+}
+
+/// a connection endpoint made of one or more pieces joined with ` + `,
+/// the first piece ending up in the lowest bits. each piece is one of:
+/// - anything `parse_version_0_connection_point` accepts
+/// - `bus`, for a whole bus
+/// - `bus[3]` or `bus[0..4]`, for a bit or a range of bits of a bus
+/// - `gate in 0..4` or `gate out 0..4`, for a range of gate pins
+#[allow(clippy::too_many_arguments)]
+fn parse_version_0_bus(
+    line_number: usize,
+    line: &str,
+    text: &str,
+    inputs: &HashMap<String, Id>,
+    outputs: &HashMap<String, Id>,
+    buses: &HashMap<String, Bus>,
+    primitive_gates: &HashMap<String, GateCreationInfo>,
+    custom_gates: &HashMap<String, GateCreationInfo>,
+) -> Result<Bus, LogicGateMapParseError> {
+    let error = || {
+        LogicGateMapParseError::InvalidConnectionPoint(
+            line_number,
+            line.to_string(),
+            text.to_string(),
+        )
+    };
+    let parse_range = |range: &str, width: usize| {
+        let range = match range.split_once("..") {
+            Some((start, end)) => start.trim().parse().ok()..end.trim().parse().ok(),
+            None => {
+                let index = range.trim().parse().ok();
+                index..index.map(|i: usize| i + 1)
+            }
+        };
+        match range {
+            std::ops::Range {
+                start: Some(start),
+                end: Some(end),
+            } if start < end && end <= width => Ok(start..end),
+            _ => Err(error()),
+        }
+    };
+
+    let mut result: Option<Bus> = None;
+    for piece in text.split(" + ").map(|x| x.trim()) {
+        let parts: Vec<_> = piece.split_whitespace().collect();
+        let bus = if let Some((name, range)) = piece
+            .strip_suffix(']')
+            .and_then(|piece| piece.split_once('['))
+        {
+            let bus = if let Some(bus) = buses.get(name.trim()) {
+                bus.clone()
+            } else if let Some(id) = inputs.get(name.trim()) {
+                Bus::from(ConnectionPoint::Input(*id))
+            } else if let Some(id) = outputs.get(name.trim()) {
+                Bus::from(ConnectionPoint::Output(*id))
+            } else {
+                return Err(error());
+            };
+            bus.slice(parse_range(range, bus.width())?)
+        } else if let Some(bus) = buses.get(piece) {
+            bus.clone()
+        } else if parts.len() == 3 && parts[2].contains("..") {
+            let Some(gate) = primitive_gates
+                .get(parts[0])
+                .or_else(|| custom_gates.get(parts[0]))
+            else {
+                return Err(error());
+            };
+            match parts[1] {
+                "in" => gate.input_bus(parse_range(parts[2], gate.input_count())?),
+                "out" => gate.output_bus(parse_range(parts[2], gate.output_count())?),
+                _ => return Err(error()),
+            }
+        } else {
+            Bus::from(parse_version_0_connection_point(
+                line_number,
+                line,
+                piece,
+                inputs,
+                outputs,
+                primitive_gates,
+                custom_gates,
+            )?)
+        };
+        result = Some(match result {
+            Some(result) => result.concat(&bus),
+            None => bus,
+        });
+    }
+    result.ok_or_else(error)
+}
+
 fn parse_version_0_connection_point(
     line_number: usize,
     line: &str,
     text: &str,
     inputs: &HashMap<String, Id>,
     outputs: &HashMap<String, Id>,
-    nands: &HashMap<String, GateCreationInfo>,
+    primitive_gates: &HashMap<String, GateCreationInfo>,
     custom_gates: &HashMap<String, GateCreationInfo>,
 ) -> Result<ConnectionPoint, LogicGateMapParseError> {
     let parts: Vec<_> = text
@@ -346,7 +922,7 @@ fn parse_version_0_connection_point(
                     text.to_string(),
                 ));
             };
-            let gate = if let Some(gate) = nands.get(parts[0]) {
+            let gate = if let Some(gate) = primitive_gates.get(parts[0]) {
                 gate
             } else if let Some(gate) = custom_gates.get(parts[0]) {
                 gate
@@ -389,3 +965,153 @@ fn parse_version_0_connection_point(
         )),
     }
 }
+
+#[cfg(test)]
+pub mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    /// the gate called `name` from a `.dat` file's text
+    pub fn parse_gate(text: &str, name: &str) -> LogicGateMap {
+        parse_text(text, Path::new("."))
+            .expect("should be able to parse the gates!")
+            .into_iter()
+            .find(|gate| gate.name == name)
+            .expect("should have parsed the gate!")
+            .map
+    }
+
+    fn parse_error(text: &str) -> LogicGateMapParseError {
+        parse_text(text, Path::new(".")).expect_err("shouldn't be able to parse the gates!")
+    }
+
+    #[test]
+    fn rejects_delays_out_of_range() {
+        for line in [
+            "delay 0 n",
+            "delay 1025 n",
+            "default_delay 0",
+            "default_delay 2000",
+        ] {
+            let text = format!("version 0\ndefine_gate slow\nnots n\n{line}\n");
+            assert!(matches!(
+                parse_error(&text),
+                LogicGateMapParseError::InvalidDelay(..)
+            ));
+        }
+        let map = parse_gate(
+            "version 0\ndefine_gate slow\nnots n\ndelay 1024 n\n",
+            "slow",
+        );
+        let gate = map.gate_by_name("n").expect("should have a gate called n!");
+        assert_eq!(map.gate_delay(gate), Some(MAX_DELAY));
+    }
+
+    #[test]
+    fn names_only_refer_to_their_own_definition() {
+        let text = "version 0\ndefine_gate first\nnots n\ndefine_gate second\ndelay 5 n\n";
+        assert!(matches!(
+            parse_error(text),
+            LogicGateMapParseError::InvalidDelay(3, _)
+        ));
+        let text = "version 0\ndefine_gate first\ninputs a\ndefine_gate second\noutputs q\nconnections a => q\n";
+        assert!(matches!(
+            parse_error(text),
+            LogicGateMapParseError::InvalidConnectionPoint(..)
+        ));
+    }
+
+    #[test]
+    fn rejects_buses_over_64_bits() {
+        assert!(matches!(
+            parse_error("version 0\ndefine_gate wide\ninputs a:65\n"),
+            LogicGateMapParseError::InvalidBusWidth(..)
+        ));
+        let map = parse_gate("version 0\ndefine_gate wide\ninputs a:64\n", "wide");
+        let bus = map.signal_by_name("a").expect("should have a bus a!");
+        assert_eq!(bus.width(), MAX_BUS_WIDTH);
+    }
+
+    #[test]
+    fn nand_only_applies_to_its_own_definition() {
+        let text = "version 0
+define_gate teaching
+nand_only
+inputs a
+outputs out
+nands n
+connections a => n in 0, a => n in 1, n out 0 => out
+define_gate other
+inputs a
+outputs out
+nots n
+connections a => n in 0, n out 0 => out
+";
+        assert!(parse_gate(text, "teaching").nand_only());
+        assert!(!parse_gate(text, "other").nand_only());
+        for line in [
+            "nots n",
+            "ands n:3",
+            "tri_states t",
+            "roms r:1:1:missing.hex",
+        ] {
+            let text = format!("version 0\ndefine_gate teaching\nnand_only\n{line}\n");
+            assert!(matches!(
+                parse_error(&text),
+                LogicGateMapParseError::PrimitiveNotAllowed(..)
+            ));
+        }
+        // it has to come before anything it wouldn't allow
+        assert!(matches!(
+            parse_error("version 0\ndefine_gate teaching\nnots n\nnand_only\n"),
+            LogicGateMapParseError::PrimitiveNotAllowed(..)
+        ));
+        assert!(matches!(
+            parse_error("version 0\nnand_only\n"),
+            LogicGateMapParseError::NoCurrentGate(..)
+        ));
+    }
+
+    #[test]
+    fn nand_only_checks_inside_custom_gates() {
+        let inverters = "version 0
+define_gate nand_not
+inputs a
+outputs out
+nands n
+connections a => n in 0, a => n in 1, n out 0 => out
+define_gate primitive_not
+inputs a
+outputs out
+nots n
+connections a => n in 0, n out 0 => out
+define_gate wrapped
+inputs a
+outputs out
+custom_gates inner = primitive_not
+connections a => inner in 0, inner out 0 => out
+";
+        for (custom_gate, allowed) in [
+            ("nand_not", true),
+            ("primitive_not", false),
+            ("wrapped", false),
+        ] {
+            let before = format!(
+                "{inverters}define_gate teaching\nnand_only\ncustom_gates c = {custom_gate}\n"
+            );
+            let after = format!(
+                "{inverters}define_gate teaching\ncustom_gates c = {custom_gate}\nnand_only\n"
+            );
+            for text in [before, after] {
+                match allowed {
+                    true => assert!(parse_gate(&text, "teaching").nand_only()),
+                    false => assert!(matches!(
+                        parse_error(&text),
+                        LogicGateMapParseError::PrimitiveNotAllowed(..)
+                    )),
+                }
+            }
+        }
+    }
+}
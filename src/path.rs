@@ -0,0 +1,151 @@
+use std::fmt::Display;
+
+use crate::{
+    compiled::SignalOrigin, flatten::GateOrigin, id::Id, logic_gate::ConnectionPoint,
+    logic_gate_map::LogicGateMap,
+};
+
+/// the part of a path passed to `LogicGateMap::resolve_path` which couldn't be found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathError {
+    Gate(String),
+    NotCustom(String),
+    Pin(String),
+    Signal(String),
+}
+impl Display for PathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathError::Gate(name) => write!(f, "there is no gate named {name}"),
+            PathError::NotCustom(name) => write!(f, "{name} isn't a custom gate"),
+            PathError::Pin(name) => write!(f, "there is no pin {name}"),
+            PathError::Signal(name) => write!(f, "there is no signal named {name}"),
+        }
+    }
+}
+
+/// a path is the names of custom gates separated by `/`, and then either
+/// a signal in the innermost map or a pin on one of its gates, like
+/// `d_latch/sr_latch/q` or `d_latch/sr_latch/nor_a.out0`.
+/// a pin is `in<n>` or `out<n>`, or for a custom gate the name of
+/// one of its inputs or outputs. a bit of a bus is picked with `name[n]`
+pub fn resolve(map: &LogicGateMap, path: &str) -> Result<SignalOrigin, PathError> {
+    let mut segments = path.split('/').collect::<Vec<_>>();
+    let last = segments
+        .pop()
+        .expect("split should give at least one segment");
+    let (ids, current) = walk(map, &segments)?;
+    let point = match last.split_once('.') {
+        Some((name, pin)) => {
+            pin_point(current, gate(current, name)?, pin).ok_or(PathError::Pin(last.to_string()))?
+        }
+        None => signal_point(current, last).ok_or(PathError::Signal(last.to_string()))?,
+    };
+    Ok(SignalOrigin { path: ids, point })
+}
+
+/// a path to a gate rather than a signal, like `d_latch/sr_latch/nor_a`,
+/// which is the opposite of `GateOrigin::name`
+pub fn resolve_gate(map: &LogicGateMap, path: &str) -> Result<GateOrigin, PathError> {
+    let mut segments = path.split('/').collect::<Vec<_>>();
+    let last = segments
+        .pop()
+        .expect("split should give at least one segment");
+    let (ids, current) = walk(map, &segments)?;
+    Ok(GateOrigin {
+        path: ids,
+        gate: gate(current, last)?,
+    })
+}
+
+/// goes through the custom gates named by `segments`
+fn walk<'a>(
+    map: &'a LogicGateMap,
+    segments: &[&str],
+) -> Result<(Vec<Id>, &'a LogicGateMap), PathError> {
+    let mut ids = vec![];
+    let mut current = map;
+    for segment in segments {
+        let gate = gate(current, segment)?;
+        let Some(inner) = current.custom_gate(gate) else {
+            return Err(PathError::NotCustom(segment.to_string()));
+        };
+        ids.push(gate);
+        current = inner;
+    }
+    Ok((ids, current))
+}
+
+fn gate(map: &LogicGateMap, name: &str) -> Result<Id, PathError> {
+    map.gate_by_name(name)
+        .ok_or(PathError::Gate(name.to_string()))
+}
+
+/// `name`, or `name[n]` for a bit of a bus
+fn signal_point(map: &LogicGateMap, text: &str) -> Option<ConnectionPoint> {
+    let (name, index) = match text.strip_suffix(']').and_then(|text| text.split_once('[')) {
+        Some((name, index)) => (name, Some(index.parse::<usize>().ok()?)),
+        None => (text, None),
+    };
+    let bus = map.signal_by_name(name)?;
+    match index {
+        Some(index) if index < bus.width() => Some(bus.bit(index)),
+        None if bus.width() == 1 => Some(bus.bit(0)),
+        _ => None,
+    }
+}
+
+fn pin_point(map: &LogicGateMap, gate: Id, pin: &str) -> Option<ConnectionPoint> {
+    let logic_gate = map.gate_by_id(gate);
+    let index = |prefix| pin.strip_prefix(prefix)?.parse::<usize>().ok();
+    if let Some(index) = index("in") {
+        let input = *logic_gate.inputs().get(index)?;
+        return Some(ConnectionPoint::GateInput { gate, input });
+    }
+    if let Some(index) = index("out") {
+        let output = *logic_gate.outputs().get(index)?;
+        return Some(ConnectionPoint::GateOutput { gate, output });
+    }
+    let inner = map.custom_gate(gate)?;
+    match signal_point(inner, pin)? {
+        ConnectionPoint::Input(input) => Some(ConnectionPoint::GateInput { gate, input }),
+        ConnectionPoint::Output(output) => Some(ConnectionPoint::GateOutput { gate, output }),
+        _ => None,
+    }
+}
+
+/// the opposite of `resolve`, giving a path which resolves back to `origin`.
+/// a gate without a name uses its id, which won't resolve
+pub fn name(map: &LogicGateMap, origin: &SignalOrigin) -> String {
+    let mut names = vec![];
+    let mut current = map;
+    let gate_name = |map: &LogicGateMap, gate: Id| {
+        map.gate_name(gate)
+            .map_or_else(|| gate.to_string(), |name| name.to_string())
+    };
+    for gate in &origin.path {
+        names.push(gate_name(current, *gate));
+        current = current
+            .custom_gate(*gate)
+            .expect("path should only go through custom gates!");
+    }
+    let point = match origin.point {
+        ConnectionPoint::GateInput { gate, input } => {
+            let index = current.gate_by_id(gate).get_input_index(input);
+            format!("{}.in{index}", gate_name(current, gate))
+        }
+        ConnectionPoint::GateOutput { gate, output } => {
+            let index = current.gate_by_id(gate).get_output_index(output);
+            format!("{}.out{index}", gate_name(current, gate))
+        }
+        point => current.point_name(&point).unwrap_or_else(|| {
+            SignalOrigin {
+                path: vec![],
+                point,
+            }
+            .to_string()
+        }),
+    };
+    names.push(point);
+    names.join("/")
+}
@@ -1,28 +1,145 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
-use eframe::egui::{Color32, Pos2, Rect, Stroke, StrokeKind, Ui, Vec2};
+use eframe::egui::{
+    Align2, CollapsingHeader, Color32, DragValue, FontId, Grid, Painter, Pos2, Rect, ScrollArea,
+    Stroke, StrokeKind, Ui, Vec2,
+};
 
-use crate::{id::Id, logic_gate::ConnectionPoint, logic_gate_map::LogicGateMap};
+use crate::{
+    flatten::GateOrigin,
+    id::Id,
+    logic::Logic,
+    logic_gate::{Bus, ConnectionPoint, LogicGate},
+    logic_gate_map::{EditError, LogicGateMap},
+};
 
 /// the result of calculating the layout of items on the screen
 /// we're using an immediate-mode GUI, so this is reconstructed every frame
 /// and state is not saved
 #[derive(Debug, Clone, Default)]
 pub struct MapRenderSavedState {
-    inputs: Vec<Id>,
-    outputs: Vec<Id>,
-    middle_signals: HashMap<Id, SignalRenderSavedState>,
+    /// each input is drawn in its own slot, apart from the bits of
+    /// an input bus which all share one slot
+    inputs: Vec<Vec<Id>>,
+    outputs: Vec<Vec<Id>>,
+    /// like inputs, all the bits of a bus of middle signals are drawn together
+    middle_signals: Vec<SignalRenderSavedState>,
     gates: HashMap<Id, GateRenderSavedState>,
+    /// connections and gates drawn in `HIGHLIGHT_COLOUR`, like a critical path
+    highlighted_connections: HashSet<Id>,
+    highlighted_gates: HashSet<Id>,
 }
 impl MapRenderSavedState {
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// lays out a map which has no saved positions, like a flattened one.
+    /// every input and output gets its own slot, and each gate goes in a column
+    /// one further right than the furthest gate driving it, with gates in a
+    /// feedback loop put after whatever else drives them. `name` labels each gate
+    pub fn layered(map: &LogicGateMap, name: impl Fn(Id) -> String) -> Self {
+        let mut result = Self::new();
+        let mut inputs = map.inputs().map(|(id, _)| id).collect::<Vec<_>>();
+        inputs.sort();
+        for id in inputs {
+            result.add_input(id);
+        }
+        let mut outputs = map.outputs().map(|(id, _)| id).collect::<Vec<_>>();
+        outputs.sort();
+        for id in outputs {
+            result.add_output(id);
+        }
+
+        let mut gates = map.gates().collect::<Vec<_>>();
+        gates.sort();
+        let mut drivers: HashMap<Id, Vec<Id>> = HashMap::new();
+        for (_, connection) in map.connections() {
+            if let (
+                ConnectionPoint::GateOutput { gate: start, .. },
+                ConnectionPoint::GateInput { gate: end, .. },
+            ) = (connection.start, connection.end)
+            {
+                drivers.entry(end).or_default().push(start);
+            }
+        }
+        // repeatedly places every gate whose drivers have all been placed,
+        // and when that gets stuck on a loop, places the first gate left anyway
+        let mut columns: HashMap<Id, usize> = HashMap::new();
+        while columns.len() < gates.len() {
+            let ready = gates
+                .iter()
+                .filter(|gate| !columns.contains_key(gate))
+                .filter(|gate| {
+                    drivers
+                        .get(gate)
+                        .into_iter()
+                        .flatten()
+                        .all(|driver| columns.contains_key(driver))
+                })
+                .copied()
+                .collect::<Vec<_>>();
+            let ready = if ready.is_empty() {
+                gates
+                    .iter()
+                    .find(|gate| !columns.contains_key(gate))
+                    .into_iter()
+                    .copied()
+                    .collect()
+            } else {
+                ready
+            };
+            for gate in ready {
+                let column = drivers
+                    .get(&gate)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|driver| columns.get(driver))
+                    .map(|column| column + 1)
+                    .max()
+                    .unwrap_or(0);
+                columns.insert(gate, column);
+            }
+        }
+        let mut rows: HashMap<usize, usize> = HashMap::new();
+        for gate in gates {
+            let row = rows.entry(columns[&gate]).or_default();
+            let position = Pos2::new(
+                200.0 + 200.0 * columns[&gate] as f32,
+                60.0 + 120.0 * *row as f32,
+            );
+            *row += 1;
+            result.add_gate(gate, position, name(gate));
+        }
+        result
+    }
+
+    pub fn set_highlighted(&mut self, connections: HashSet<Id>, gates: HashSet<Id>) {
+        self.highlighted_connections = connections;
+        self.highlighted_gates = gates;
+    }
+
     pub fn has_gate(&self, gate_id: Id) -> bool {
         self.gates.contains_key(&gate_id)
     }
 
+    pub fn has_signal(&self, id: Id) -> bool {
+        self.middle_signals
+            .iter()
+            .any(|signal| signal.ids.contains(&id))
+    }
+
+    fn middle_signal_position(&self, id: Id) -> Pos2 {
+        self.middle_signals
+            .iter()
+            .find(|signal| signal.ids.contains(&id))
+            .map(|signal| signal.position)
+            .unwrap()
+    }
+
     fn input_position(&self, id: Id) -> Pos2 {
         Pos2::new(
             30.0,
@@ -31,7 +148,7 @@ impl MapRenderSavedState {
                     .inputs
                     .iter()
                     .enumerate()
-                    .find_map(|(i, x)| (id == *x).then_some(i))
+                    .find_map(|(i, x)| x.contains(&id).then_some(i))
                     .unwrap() as f32,
         )
     }
@@ -44,7 +161,7 @@ impl MapRenderSavedState {
                     .outputs
                     .iter()
                     .enumerate()
-                    .find(|(_, x)| **x == id)
+                    .find(|(_, x)| x.contains(&id))
                     .map(|(i, _)| i)
                     .unwrap() as f32,
         )
@@ -72,14 +189,29 @@ impl MapRenderSavedState {
         let y = output_offset - output_array_height / 2.0 + gate_position.y;
         Pos2::new(x, y)
     }
-    // TODO: updating functions for adding things to the renderer
-    // and functions for deleting things as well
     pub fn add_input(&mut self, id: Id) {
-        self.inputs.push(id);
+        self.inputs.push(vec![id]);
+    }
+
+    pub fn add_input_bus(&mut self, ids: Vec<Id>) {
+        self.inputs.push(ids);
     }
 
     pub fn add_output(&mut self, id: Id) {
-        self.outputs.push(id);
+        self.outputs.push(vec![id]);
+    }
+
+    pub fn add_output_bus(&mut self, ids: Vec<Id>) {
+        self.outputs.push(ids);
+    }
+
+    /// `ids` is every bit of the signal, which is drawn with `name` above it
+    pub fn add_signal(&mut self, ids: Vec<Id>, position: Pos2, name: String) {
+        self.middle_signals.push(SignalRenderSavedState {
+            ids,
+            position,
+            name,
+        });
     }
 
     pub fn add_gate(&mut self, id: Id, position: Pos2, name: String) {
@@ -87,56 +219,176 @@ impl MapRenderSavedState {
             .insert(id, GateRenderSavedState { position, name });
     }
 
+    /// the `remove_*` functions edit `map` and this at the same time,
+    /// so nothing is left being drawn for something that's been removed
+    pub fn remove_input(&mut self, map: &mut LogicGateMap, id: Id) -> Result<(), EditError> {
+        map.remove_input(id)?;
+        remove_from_slots(&mut self.inputs, id);
+        Ok(())
+    }
+
+    pub fn remove_output(&mut self, map: &mut LogicGateMap, id: Id) -> Result<(), EditError> {
+        map.remove_output(id)?;
+        remove_from_slots(&mut self.outputs, id);
+        Ok(())
+    }
+
+    pub fn remove_middle_signal(
+        &mut self,
+        map: &mut LogicGateMap,
+        id: Id,
+    ) -> Result<(), EditError> {
+        map.remove_middle_signal(id)?;
+        for signal in &mut self.middle_signals {
+            signal.ids.retain(|x| *x != id);
+        }
+        self.middle_signals.retain(|signal| !signal.ids.is_empty());
+        Ok(())
+    }
+
+    pub fn remove_gate(&mut self, map: &mut LogicGateMap, id: Id) -> Result<(), EditError> {
+        map.remove_gate(id)?;
+        self.gates.remove(&id);
+        Ok(())
+    }
+
+    /// renames the gate in `map` as well as its label, so paths to it use the new name
+    pub fn rename_gate(
+        &mut self,
+        map: &mut LogicGateMap,
+        id: Id,
+        name: String,
+    ) -> Result<(), EditError> {
+        let gate = self.gates.get_mut(&id).ok_or(EditError::Gate(id))?;
+        map.rename_gate(id, name.clone())?;
+        gate.name = name;
+        Ok(())
+    }
+
+    /// renames the signal in `map`, and the label of a middle signal
+    /// which was drawn with its name rather than a label of its own
+    pub fn rename_signal(
+        &mut self,
+        map: &mut LogicGateMap,
+        old: &str,
+        new: String,
+    ) -> Result<(), EditError> {
+        map.rename_signal(old, new.clone())?;
+        for signal in &mut self.middle_signals {
+            if signal.name == old {
+                signal.name = new.clone();
+            }
+        }
+        Ok(())
+    }
+
     /// This method uses the logic gate and the saved state
-    /// to render to the screen
+    /// to render to the screen, along with the current simulation time
     /// If saved state is required for an element but isn't available
     /// this function for now just ignores that element
     pub fn process_input_and_render(
         &self,
         map: &mut LogicGateMap,
+        time: u64,
         click_position: Option<Pos2>,
         ui: &mut Ui,
     ) -> Result<(), ()> {
         let painter = ui.painter();
 
+        painter.text(
+            ui.max_rect().right_bottom() - Vec2::new(10.0, 10.0),
+            Align2::RIGHT_BOTTOM,
+            format!("t = {time}"),
+            FontId::monospace(14.0),
+            Color32::WHITE,
+        );
+
         // draw inputs
-        for (id, input) in map.inputs_mut() {
-            let shape = CircleCollider::new(self.input_position(id), 20.0);
+        // clicking a single input toggles it, and clicking a bus counts up through its values
+        for ids in &self.inputs {
+            let shape = CircleCollider::new(self.input_position(ids[0]), 20.0);
+            let bus = Bus::new(ids.iter().map(|id| ConnectionPoint::Input(*id)).collect());
             if let Some(click_position) = click_position
                 && shape.intersects_point(click_position)
             {
-                *input = !*input;
+                let value = map
+                    .bus_value(&bus)
+                    .map_or(0, |value| (value + 1) & bus_mask(bus.width()));
+                map.set_bus_value(&bus, value);
             }
-            painter.circle_filled(
-                shape.position(),
-                shape.radius(),
-                if *input { ON_COLOUR } else { OFF_COLOUR },
+            draw_signal(painter, shape.position(), map, &bus);
+        }
+
+        for ids in &self.outputs {
+            let bus = Bus::new(ids.iter().map(|id| ConnectionPoint::Output(*id)).collect());
+            draw_signal(
+                painter,
+                self.output_position(ids[0], ui.available_width()),
+                map,
+                &bus,
             );
         }
 
-        for (id, output) in map.outputs() {
-            painter.circle_filled(
-                self.output_position(id, ui.available_width()),
-                20.0,
-                if output { ON_COLOUR } else { OFF_COLOUR },
+        for signal in &self.middle_signals {
+            let bus = Bus::new(
+                signal
+                    .ids
+                    .iter()
+                    .map(|id| ConnectionPoint::MiddleSignal(*id))
+                    .collect(),
+            );
+            draw_signal(painter, signal.position, map, &bus);
+            painter.text(
+                signal.position - Vec2::new(0.0, 22.0),
+                Align2::CENTER_BOTTOM,
+                &signal.name,
+                FontId::proportional(12.0),
+                Color32::LIGHT_GRAY,
             );
         }
 
-        for (id, value) in map.middle_signals() {
-            painter.circle_filled(
-                self.middle_signals[&id].position,
-                20.0,
-                if value { ON_COLOUR } else { OFF_COLOUR },
+        let mut bus_connection_bits = HashSet::new();
+        for (_, bus_connection) in map.bus_connections() {
+            bus_connection_bits.extend(bus_connection.connections.iter().copied());
+            let start_position = self.bus_position(map, ui, &bus_connection.start);
+            let end_position = self.bus_position(map, ui, &bus_connection.end);
+            let value = map.bus_value(&bus_connection.start);
+            if bus_connection
+                .connections
+                .iter()
+                .any(|id| self.highlighted_connections.contains(id))
+            {
+                painter.line_segment(
+                    [start_position, end_position],
+                    Stroke::new(14.0, HIGHLIGHT_COLOUR),
+                );
+            }
+            painter.line_segment([start_position, end_position], Stroke::new(8.0, BUS_COLOUR));
+            painter.text(
+                start_position.lerp(end_position, 0.5),
+                Align2::CENTER_BOTTOM,
+                format_bus_value(bus_connection.start.width(), value),
+                FontId::monospace(14.0),
+                Color32::WHITE,
             );
         }
 
-        for (_, connection) in map.connections() {
+        for (id, connection) in map.connections() {
+            if bus_connection_bits.contains(&id) {
+                continue;
+            }
             let start_position = self.connection_point_position(map, ui, connection.start);
             let end_position = self.connection_point_position(map, ui, connection.end);
             let value = map.connection_point_value(&connection.start);
+            if self.highlighted_connections.contains(&id) {
+                painter.line_segment(
+                    [start_position, end_position],
+                    Stroke::new(9.0, HIGHLIGHT_COLOUR),
+                );
+            }
             painter.line_segment(
                 [start_position, end_position],
-                Stroke::new(3.0, if value { ON_COLOUR } else { OFF_COLOUR }),
+                Stroke::new(3.0, logic_colour(value)),
             );
         }
 
@@ -147,28 +399,194 @@ impl MapRenderSavedState {
                 .max(map.gate_by_id(*id).output_count()) as f32
                 * 20.0
                 * 2.0;
+            let colour = if self.highlighted_gates.contains(id) {
+                HIGHLIGHT_COLOUR
+            } else {
+                Color32::LIGHT_GRAY
+            };
             // TODO: draw block
             painter.rect_stroke(
                 Rect::from_center_size(gate.position, Vec2::new(100.0, height)),
                 0.0,
-                Stroke::new(3.0, Color32::LIGHT_GRAY),
+                Stroke::new(3.0, colour),
                 StrokeKind::Middle,
             );
+            if let LogicGate::Clock { clock, .. } = map.gate_by_id(*id) {
+                painter.text(
+                    gate.position + Vec2::new(0.0, height / 2.0 + 4.0),
+                    Align2::CENTER_TOP,
+                    format!("f = {:.3} per tick", clock.frequency()),
+                    FontId::proportional(12.0),
+                    Color32::LIGHT_GRAY,
+                );
+            }
             // TODO: draw input array
-            for (input_id, value) in map.gate_by_id(*id).inputs().into_iter() {
+            for input_id in map.gate_by_id(*id).inputs() {
+                let value = map.connection_point_value(&ConnectionPoint::GateInput {
+                    gate: *id,
+                    input: input_id,
+                });
                 let position = self.gate_input_position(map, *id, input_id);
-                painter.circle_filled(position, 20.0, if value { ON_COLOUR } else { OFF_COLOUR });
+                painter.circle_filled(position, 20.0, logic_colour(value));
             }
             // TODO: draw output array
-            for (output_id, value) in map.gate_by_id(*id).outputs().into_iter() {
+            for output_id in map.gate_by_id(*id).outputs() {
+                let value = map.connection_point_value(&ConnectionPoint::GateOutput {
+                    gate: *id,
+                    output: output_id,
+                });
                 let position = self.gate_output_position(map, *id, output_id);
-                painter.circle_filled(position, 20.0, if value { ON_COLOUR } else { OFF_COLOUR });
+                painter.circle_filled(position, 20.0, logic_colour(value));
             }
         }
 
         Ok(())
     }
 
+    /// every signal drawn under `position`, which is all the bits of a bus
+    pub fn points_at(&self, map: &LogicGateMap, ui: &Ui, position: Pos2) -> Vec<ConnectionPoint> {
+        let hit = |centre| CircleCollider::new(centre, 20.0).intersects_point(position);
+        let slots = self
+            .inputs
+            .iter()
+            .map(|ids| {
+                let points = ids.iter().map(|id| ConnectionPoint::Input(*id)).collect();
+                (self.input_position(ids[0]), points)
+            })
+            .chain(self.outputs.iter().map(|ids| {
+                let points = ids.iter().map(|id| ConnectionPoint::Output(*id)).collect();
+                (self.output_position(ids[0], ui.available_width()), points)
+            }))
+            .chain(self.middle_signals.iter().map(|signal| {
+                let points = signal
+                    .ids
+                    .iter()
+                    .map(|id| ConnectionPoint::MiddleSignal(*id))
+                    .collect();
+                (signal.position, points)
+            }));
+        for (centre, points) in slots {
+            if hit(centre) {
+                return points;
+            }
+        }
+        for gate in self.gates.keys() {
+            let logic_gate = map.gate_by_id(*gate);
+            let inputs = logic_gate.inputs().into_iter().map(|input| {
+                (
+                    self.gate_input_position(map, *gate, input),
+                    ConnectionPoint::GateInput { gate: *gate, input },
+                )
+            });
+            let outputs = logic_gate.outputs().into_iter().map(|output| {
+                (
+                    self.gate_output_position(map, *gate, output),
+                    ConnectionPoint::GateOutput {
+                        gate: *gate,
+                        output,
+                    },
+                )
+            });
+            for (centre, point) in inputs.chain(outputs) {
+                if hit(centre) {
+                    return vec![point];
+                }
+            }
+        }
+        vec![]
+    }
+
+    /// lists every ROM and RAM in the map, with their contents shown
+    /// 16 words to a row. any word can be edited while the simulation is running,
+    /// and `CompiledMap::load_memories` picks up the change
+    pub fn show_memory_inspector(&self, map: &mut LogicGateMap, ui: &mut Ui) {
+        let mut memories = memories(map)
+            .into_iter()
+            .map(|origin| {
+                let name = origin.name(map);
+                (origin, name)
+            })
+            .collect::<Vec<_>>();
+        memories.sort_by(|(_, a), (_, b)| a.cmp(b));
+        for (origin, name) in memories {
+            let inner = map.inner_map_mut(&origin.path);
+            let logic_gate = inner.gate_by_id(origin.gate);
+            let kind = match logic_gate {
+                LogicGate::Rom { .. } => "rom",
+                _ => "ram",
+            };
+            let address_width = inner
+                .memory(origin.gate)
+                .expect("should only be showing memories!")
+                .address_width;
+            let address = logic_gate
+                .inputs()
+                .into_iter()
+                .take(address_width)
+                .enumerate()
+                .try_fold(0, |value, (i, input)| {
+                    let bit = inner.connection_point_value(&ConnectionPoint::GateInput {
+                        gate: origin.gate,
+                        input,
+                    });
+                    Some(value | (bit.to_bool()? as u64) << i)
+                });
+            let memory = inner
+                .memory_mut(origin.gate)
+                .expect("should only be showing memories!");
+            let id = &origin;
+            let (address_width, data_width) = (memory.address_width, memory.data_width());
+            let mask = memory.word_mask();
+            CollapsingHeader::new(format!(
+                "{name} ({kind}, {} x {data_width} bits) at {}",
+                memory.contents.len(),
+                format_bus_value(address_width, address)
+            ))
+            .id_salt(id)
+            .default_open(true)
+            .show(ui, |ui| {
+                let rows = memory.contents.len().div_ceil(16);
+                ScrollArea::vertical()
+                    .id_salt(id)
+                    .max_height(300.0)
+                    .show_rows(ui, ui.spacing().interact_size.y, rows, |ui, range| {
+                        Grid::new(id).striped(true).show(ui, |ui| {
+                            for row in range {
+                                ui.monospace(format_bus_value(
+                                    address_width,
+                                    Some(row as u64 * 16),
+                                ));
+                                let end = (row * 16 + 16).min(memory.contents.len());
+                                for address in row * 16..end {
+                                    // the contents are only copied if a word is actually edited
+                                    let mut word = memory.contents[address];
+                                    let response = ui.add(
+                                        DragValue::new(&mut word)
+                                            .hexadecimal(data_width.div_ceil(4), false, true)
+                                            .range(0..=mask),
+                                    );
+                                    if response.changed() {
+                                        Arc::make_mut(&mut memory.contents)[address] = word;
+                                    }
+                                }
+                                ui.end_row();
+                            }
+                        });
+                    });
+            });
+        }
+    }
+
+    /// buses are drawn from the middle of all their bits
+    fn bus_position(&self, map: &LogicGateMap, ui: &Ui, bus: &Bus) -> Pos2 {
+        let total = bus
+            .bits()
+            .iter()
+            .map(|bit| self.connection_point_position(map, ui, *bit).to_vec2())
+            .fold(Vec2::ZERO, |total, position| total + position);
+        (total / bus.width() as f32).to_pos2()
+    }
+
     fn connection_point_position(
         &self,
         map: &LogicGateMap,
@@ -178,7 +596,7 @@ impl MapRenderSavedState {
         match connection_point {
             ConnectionPoint::Input(id) => self.input_position(id),
             ConnectionPoint::Output(id) => self.output_position(id, ui.available_width()),
-            ConnectionPoint::MiddleSignal(id) => self.middle_signals[&id].position,
+            ConnectionPoint::MiddleSignal(id) => self.middle_signal_position(id),
             ConnectionPoint::GateInput { gate, input } => {
                 self.gate_input_position(map, gate, input)
             }
@@ -191,11 +609,12 @@ impl MapRenderSavedState {
 
 #[derive(Debug, Clone)]
 struct SignalRenderSavedState {
+    ids: Vec<Id>,
     position: Pos2,
+    name: String,
 }
 
 #[derive(Debug, Clone)]
-#[allow(unused)]
 struct GateRenderSavedState {
     position: Pos2,
     name: String,
@@ -219,10 +638,208 @@ impl CircleCollider {
         self.position
     }
 
+    #[allow(unused)]
     pub fn radius(&self) -> f32 {
         self.radius
     }
 }
 
+/// removes `id` from whichever slot it's in, and the slot too if that leaves it empty
+fn remove_from_slots(slots: &mut Vec<Vec<Id>>, id: Id) {
+    for slot in slots.iter_mut() {
+        slot.retain(|x| *x != id);
+    }
+    slots.retain(|slot| !slot.is_empty());
+}
+
+fn bus_mask(width: usize) -> u64 {
+    if width >= 64 {
+        u64::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// a bus with any bit that isn't `Zero` or `One` is shown as all `X`s
+fn format_bus_value(width: usize, value: Option<u64>) -> String {
+    let digits = width.div_ceil(4);
+    match value {
+        Some(value) => format!("0x{value:0digits$X}"),
+        None => format!("0x{}", "X".repeat(digits)),
+    }
+}
+
+/// single bits are drawn with their colour, and buses are drawn with their value in hex
+fn draw_signal(painter: &Painter, position: Pos2, map: &LogicGateMap, bus: &Bus) {
+    if bus.width() == 1 {
+        painter.circle_filled(
+            position,
+            20.0,
+            logic_colour(map.connection_point_value(&bus.bit(0))),
+        );
+    } else {
+        painter.circle_filled(position, 20.0, BUS_COLOUR);
+        painter.text(
+            position,
+            Align2::CENTER_CENTER,
+            format_bus_value(bus.width(), map.bus_value(bus)),
+            FontId::monospace(10.0),
+            Color32::BLACK,
+        );
+    }
+}
+
+pub fn logic_colour(value: Logic) -> Color32 {
+    match value {
+        Logic::Zero => OFF_COLOUR,
+        Logic::One => ON_COLOUR,
+        Logic::Unknown => UNKNOWN_COLOUR,
+        Logic::HighImpedance => HIGH_IMPEDANCE_COLOUR,
+    }
+}
+
 const ON_COLOUR: Color32 = Color32::GREEN;
 const OFF_COLOUR: Color32 = Color32::RED;
+const UNKNOWN_COLOUR: Color32 = Color32::GRAY;
+const HIGH_IMPEDANCE_COLOUR: Color32 = Color32::from_rgb(160, 0, 255);
+const BUS_COLOUR: Color32 = Color32::LIGHT_BLUE;
+const HIGHLIGHT_COLOUR: Color32 = Color32::from_rgb(255, 160, 0);
+
+/// every rom and ram in `map`, including the ones inside custom gates
+pub fn memories(map: &LogicGateMap) -> Vec<GateOrigin> {
+    let mut memories = vec![];
+    add_memories(map, &mut vec![], &mut memories);
+    memories
+}
+
+fn add_memories(map: &LogicGateMap, path: &mut Vec<Id>, memories: &mut Vec<GateOrigin>) {
+    for gate in map.gates() {
+        if let Some(inner) = map.custom_gate(gate) {
+            path.push(gate);
+            add_memories(inner, path, memories);
+            path.pop();
+        } else if map.memory(gate).is_some() {
+            memories.push(GateOrigin {
+                path: path.clone(),
+                gate,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    #[test]
+    fn memories_are_found_inside_custom_gates() {
+        let map = parse_gate(
+            "version 0
+define_gate inner
+rams m:1:1
+define_gate outer
+rams r:1:1
+custom_gates first = inner, second = inner
+",
+            "outer",
+        );
+        let mut names = memories(&map)
+            .iter()
+            .map(|origin| origin.name(&map))
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["first/m", "r", "second/m"]);
+    }
+
+    const TAPPED: &str = "version 0
+define_gate tapped
+inputs a b enable_a enable_b
+outputs out
+signals wire spare:2
+tri_states ta tb
+connections a => ta in 0, enable_a => ta in 1, b => tb in 0, enable_b => tb in 1
+connections ta out 0 => wire, tb out 0 => wire, wire => out
+render_gate ta 100 50 ta
+render_gate tb 100 150 tb
+render_signal wire 200 100
+render_signal spare 200 200 two spare bits
+";
+
+    fn middle_signal_ids(map: &LogicGateMap, name: &str) -> Vec<Id> {
+        map.signal_by_name(name)
+            .expect("should have a signal with that name!")
+            .bits()
+            .iter()
+            .map(|bit| match bit {
+                ConnectionPoint::MiddleSignal(id) => *id,
+                _ => panic!("should only have middle signals!"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn middle_signals_are_drawn_where_they_are_placed() {
+        let map = parse_gate(TAPPED, "tapped");
+        let render_data = crate::parse::parse_text(TAPPED, std::path::Path::new("."))
+            .expect("should be able to parse tapped!")
+            .remove(0)
+            .render_data
+            .expect("should be able to draw every gate!");
+        let (wire, spare) = (
+            middle_signal_ids(&map, "wire"),
+            middle_signal_ids(&map, "spare"),
+        );
+        assert!(
+            wire.iter()
+                .chain(&spare)
+                .all(|id| render_data.has_signal(*id))
+        );
+        assert_eq!(
+            render_data.middle_signal_position(wire[0]),
+            Pos2::new(200.0, 100.0)
+        );
+        // every bit of a bus is drawn together, under the label if there is one
+        let drawn = render_data
+            .middle_signals
+            .iter()
+            .map(|signal| (signal.name.as_str(), signal.ids.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(drawn, [("wire", wire), ("two spare bits", spare)]);
+
+        // only middle signals can be drawn like this
+        let input = TAPPED.replace("render_signal wire", "render_signal a");
+        assert!(matches!(
+            crate::parse::parse_text(&input, std::path::Path::new(".")),
+            Err(crate::parse::LogicGateMapParseError::InvalidRenderLine(..))
+        ));
+    }
+
+    #[test]
+    fn middle_signals_resolve_their_drivers() {
+        let mut map = parse_gate(TAPPED, "tapped");
+        let mut drive = |values: [(&str, Logic); 4]| {
+            for (path, value) in values {
+                let point = map.resolve_path(path).unwrap().point;
+                map.set_connection_point_value(&point, value);
+            }
+            assert!(map.settle(100).is_ok());
+            assert_eq!(map.probe("wire"), map.probe("out"));
+            map.probe("wire").unwrap()
+        };
+        use Logic::{HighImpedance, One, Unknown, Zero};
+        let values = |enable_a, enable_b| {
+            [
+                ("a", One),
+                ("b", Zero),
+                ("enable_a", enable_a),
+                ("enable_b", enable_b),
+            ]
+        };
+        assert_eq!(drive(values(Zero, Zero)), HighImpedance);
+        assert_eq!(drive(values(One, Zero)), One);
+        assert_eq!(drive(values(Zero, One)), Zero);
+        assert_eq!(drive(values(One, One)), Unknown);
+        assert_eq!(map.contentions().len(), 1);
+    }
+}
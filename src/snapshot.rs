@@ -0,0 +1,370 @@
+use std::{fmt::Display, sync::Arc};
+
+use crate::{
+    compiled::SignalOrigin, flatten::GateOrigin, id::Id, logic::Logic, logic_gate::ConnectionPoint,
+    logic_gate_map::LogicGateMap, path::resolve_gate,
+};
+
+/// the contents of a ROM or RAM, and the clock it last saw so a RAM
+/// doesn't see a rising edge straight after being restored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryState {
+    pub gate: GateOrigin,
+    pub contents: Arc<Vec<u64>>,
+    pub last_clock: Logic,
+}
+
+/// every value in a map at every depth, which `restore` puts back.
+/// forced signals aren't part of it, as they stay forced until they're released
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub clock_tick: u64,
+    /// the inputs of a custom gate are only stored inside it
+    pub values: Vec<(SignalOrigin, Logic)>,
+    pub memories: Vec<MemoryState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// a signal or memory in the snapshot which isn't in the map
+    Missing(String),
+    /// a memory with a different number of words to the one in the snapshot
+    MemorySize(String),
+    /// a line of a snapshot file which couldn't be read, and why
+    Parse(usize, String),
+    /// snapshots saved for a different gate
+    WrongGate(String),
+}
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Missing(name) => write!(f, "{name} isn't in the map"),
+            SnapshotError::MemorySize(name) => {
+                write!(f, "{name} is a different size to the one in the snapshot")
+            }
+            SnapshotError::Parse(line_number, reason) => {
+                write!(f, "line {}: {reason}", line_number + 1)
+            }
+            SnapshotError::WrongGate(name) => write!(f, "the snapshots are for {name}"),
+        }
+    }
+}
+
+pub fn take(map: &LogicGateMap) -> Snapshot {
+    let mut snapshot = Snapshot {
+        clock_tick: map.clock_tick(),
+        values: vec![],
+        memories: vec![],
+    };
+    add_map(&mut snapshot, map, &mut vec![]);
+    snapshot
+}
+
+fn add_map(snapshot: &mut Snapshot, map: &LogicGateMap, path: &mut Vec<Id>) {
+    // each kind of signal in id order, so a saved snapshot comes out the same every time
+    let kinds = [
+        (
+            ConnectionPoint::Input as fn(Id) -> ConnectionPoint,
+            map.inputs().collect::<Vec<_>>(),
+        ),
+        (ConnectionPoint::Output, map.outputs().collect()),
+        (
+            ConnectionPoint::MiddleSignal,
+            map.middle_signals().collect(),
+        ),
+    ];
+    for (point, mut values) in kinds {
+        values.sort_by_key(|(id, _)| *id);
+        for (id, value) in values {
+            let origin = SignalOrigin {
+                path: path.clone(),
+                point: point(id),
+            };
+            snapshot.values.push((origin, value));
+        }
+    }
+
+    let mut gates = map.gates().collect::<Vec<_>>();
+    gates.sort();
+    for gate in gates {
+        let logic_gate = map.gate_by_id(gate);
+        if let Some(inner) = map.custom_gate(gate) {
+            path.push(gate);
+            add_map(snapshot, inner, path);
+            path.pop();
+            continue;
+        }
+        let inputs = logic_gate
+            .inputs()
+            .into_iter()
+            .map(|input| ConnectionPoint::GateInput { gate, input });
+        let outputs = logic_gate
+            .outputs()
+            .into_iter()
+            .map(|output| ConnectionPoint::GateOutput { gate, output });
+        for point in inputs.chain(outputs) {
+            let value = map.connection_point_value(&point);
+            let origin = SignalOrigin {
+                path: path.clone(),
+                point,
+            };
+            snapshot.values.push((origin, value));
+        }
+        if let Some(memory) = map.memory(gate) {
+            snapshot.memories.push(MemoryState {
+                gate: GateOrigin {
+                    path: path.clone(),
+                    gate,
+                },
+                contents: memory.contents.clone(),
+                last_clock: memory.last_clock,
+            });
+        }
+    }
+}
+
+/// checks the whole snapshot fits the map before changing anything,
+/// so a snapshot from a different map leaves it as it was
+pub fn restore(map: &mut LogicGateMap, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+    for (origin, _) in &snapshot.values {
+        if !inner_map(map, &origin.path)
+            .is_some_and(|inner| inner.has_connection_point(&origin.point))
+        {
+            return Err(SnapshotError::Missing(origin.to_string()));
+        }
+    }
+    for memory in &snapshot.memories {
+        let name = || {
+            let ids = memory.gate.path.iter().chain([&memory.gate.gate]);
+            ids.map(|id| id.to_string()).collect::<Vec<_>>().join("/")
+        };
+        let found =
+            inner_map(map, &memory.gate.path).and_then(|inner| inner.memory(memory.gate.gate));
+        match found {
+            None => return Err(SnapshotError::Missing(name())),
+            Some(found) if found.contents.len() != memory.contents.len() => {
+                return Err(SnapshotError::MemorySize(name()));
+            }
+            Some(_) => {}
+        }
+    }
+
+    map.set_clock_tick(snapshot.clock_tick);
+    for (origin, value) in &snapshot.values {
+        map.inner_map_mut(&origin.path)
+            .set_connection_point_value(&origin.point, *value);
+    }
+    for memory in &snapshot.memories {
+        let stored = map
+            .inner_map_mut(&memory.gate.path)
+            .memory_mut(memory.gate.gate)
+            .expect("should have checked memory exists!");
+        stored.contents = memory.contents.clone();
+        stored.last_clock = memory.last_clock;
+    }
+    Ok(())
+}
+
+/// like `LogicGateMap::inner_map`, but `None` if the path doesn't go through custom gates
+fn inner_map<'a>(map: &'a LogicGateMap, path: &[Id]) -> Option<&'a LogicGateMap> {
+    path.iter()
+        .try_fold(map, |map, gate| map.custom_gate(*gate))
+}
+
+/// the text of a snapshot file for the named snapshots of `map`. signals and
+/// memories are written as paths like `LogicGateMap::resolve_path` takes, so
+/// everything in the map needs a name, which it has if it was parsed:
+///
+/// ```text
+/// version 0
+/// gate sr_latch
+/// snapshot set
+/// clock_tick 12
+/// value nor_a/not_a/n.out0 1
+/// memory ram 0 3 ff 0 1
+/// ```
+///
+/// a memory line has the memory's path, its last clock and then its words in hex
+pub fn format_snapshots(map: &LogicGateMap, snapshots: &[(String, Snapshot)]) -> String {
+    let mut result = "version 0\n".to_string();
+    if let Some(name) = map.name() {
+        result.push_str(&format!("gate {name}\n"));
+    }
+    for (name, snapshot) in snapshots {
+        result.push_str(&format!("snapshot {name}\n"));
+        result.push_str(&format!("clock_tick {}\n", snapshot.clock_tick));
+        for (origin, value) in &snapshot.values {
+            result.push_str(&format!("value {} {value}\n", map.origin_name(origin)));
+        }
+        for memory in &snapshot.memories {
+            let words = memory
+                .contents
+                .iter()
+                .map(|word| format!("{word:x}"))
+                .collect::<Vec<_>>();
+            result.push_str(&format!(
+                "memory {} {} {}\n",
+                memory.gate.name(map),
+                memory.last_clock,
+                words.join(" ")
+            ));
+        }
+    }
+    result
+}
+
+/// reads a file written by `format_snapshots`, finding every path in `map`
+pub fn parse_snapshots(
+    map: &LogicGateMap,
+    text: &str,
+) -> Result<Vec<(String, Snapshot)>, SnapshotError> {
+    let mut lines = text.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some("version 0") {
+        return Err(SnapshotError::Parse(0, "expected version 0".to_string()));
+    }
+    let mut snapshots: Vec<(String, Snapshot)> = vec![];
+    for (line_number, line) in lines {
+        let error = |reason: String| SnapshotError::Parse(line_number, reason);
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("gate ") {
+            if map.name().is_some_and(|map_name| map_name != name) {
+                return Err(SnapshotError::WrongGate(name.to_string()));
+            }
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("snapshot ") {
+            let snapshot = Snapshot {
+                clock_tick: 0,
+                values: vec![],
+                memories: vec![],
+            };
+            snapshots.push((name.to_string(), snapshot));
+            continue;
+        }
+        let Some((_, snapshot)) = snapshots.last_mut() else {
+            return Err(error(format!("{line} isn't in a snapshot")));
+        };
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("clock_tick") => {
+                snapshot.clock_tick = words
+                    .next()
+                    .and_then(|tick| tick.parse().ok())
+                    .ok_or(error(format!("{line} should have a tick number")))?;
+            }
+            Some("value") => {
+                let (Some(path), Some(value)) = (words.next(), words.next()) else {
+                    return Err(error(format!("{line} should have a path and a value")));
+                };
+                let origin = map
+                    .resolve_path(path)
+                    .map_err(|path_error| error(path_error.to_string()))?;
+                snapshot
+                    .values
+                    .push((origin, value.parse().map_err(error)?));
+            }
+            Some("memory") => {
+                let (Some(path), Some(last_clock)) = (words.next(), words.next()) else {
+                    return Err(error(format!(
+                        "{line} should have a path, a clock value and some words"
+                    )));
+                };
+                let gate =
+                    resolve_gate(map, path).map_err(|path_error| error(path_error.to_string()))?;
+                let contents = words
+                    .map(|word| u64::from_str_radix(word, 16))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|parse_error| error(parse_error.to_string()))?;
+                snapshot.memories.push(MemoryState {
+                    gate,
+                    contents: Arc::new(contents),
+                    last_clock: last_clock.parse().map_err(error)?,
+                });
+            }
+            _ => return Err(error(format!("unrecognised line {line}"))),
+        }
+    }
+    Ok(snapshots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    const MEMORY: &str = "version 0
+define_gate cell
+inputs address data write clock
+outputs q
+rams m:1:1
+connections address => m in 0, data => m in 1, write => m in 2, clock => m in 3
+connections m out 0 => q
+define_gate memory
+inputs address data write clock
+outputs q
+custom_gates cell = cell
+connections address => cell in 0, data => cell in 1, write => cell in 2, clock => cell in 3
+connections cell out 0 => q
+";
+
+    /// a map with a value written into the RAM inside it
+    fn written() -> LogicGateMap {
+        let mut map = parse_gate(MEMORY, "memory");
+        for (path, value) in [("address", 1), ("data", 1), ("write", 1), ("clock", 0)] {
+            let input = map.resolve_path(path).unwrap().point;
+            map.set_connection_point_value(&input, (value == 1).into());
+        }
+        assert!(map.settle(10).is_ok());
+        let clock = map.resolve_path("clock").unwrap().point;
+        map.set_connection_point_value(&clock, Logic::One);
+        assert!(map.settle(10).is_ok());
+        map
+    }
+
+    #[test]
+    fn snapshots_round_trip_through_text() {
+        let map = written();
+        let snapshots = [
+            ("written".to_string(), map.snapshot()),
+            ("empty".to_string(), parse_gate(MEMORY, "memory").snapshot()),
+        ];
+        assert_eq!(snapshots[0].1.memories[0].contents, Arc::new(vec![0, 1]));
+        let text = format_snapshots(&map, &snapshots);
+        assert!(text.contains("memory cell/m 1 0 1\n"));
+        assert_eq!(parse_snapshots(&map, &text), Ok(snapshots.to_vec()));
+    }
+
+    #[test]
+    fn restoring_puts_every_value_back() {
+        let map = written();
+        let snapshot = map.snapshot();
+        let mut restored = parse_gate(MEMORY, "memory");
+        assert_eq!(restored.probe("q"), Ok(Logic::Zero));
+        restored
+            .restore(&snapshot)
+            .expect("should be able to restore the snapshot!");
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.probe("q"), Ok(Logic::One));
+        assert_eq!(restored.probe("cell/m.out0"), Ok(Logic::One));
+    }
+
+    #[test]
+    fn snapshots_have_to_match_the_map() {
+        let map = parse_gate(MEMORY, "memory");
+        assert_eq!(
+            parse_snapshots(&map, "version 0\ngate other\n"),
+            Err(SnapshotError::WrongGate("other".to_string()))
+        );
+        assert!(matches!(
+            parse_snapshots(&map, "version 0\nsnapshot a\nvalue missing 1\n"),
+            Err(SnapshotError::Parse(2, _))
+        ));
+        assert!(matches!(
+            parse_snapshots(&map, "version 0\nvalue q 1\n"),
+            Err(SnapshotError::Parse(1, _))
+        ));
+    }
+}
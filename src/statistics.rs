@@ -0,0 +1,408 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+};
+
+use crate::{
+    compiled::{CompiledMap, Operation},
+    flatten::{FlattenError, flatten},
+    id::Id,
+    logic_gate::{ConnectionPoint, LogicGate, PrimitiveKind},
+    logic_gate_map::LogicGateMap,
+    truth_table::ports,
+};
+
+/// how big a map is and how long signals take to get through it, see `statistics`
+#[derive(Debug, Clone)]
+pub struct Statistics {
+    /// how many NAND gates the map turns into with `flatten`
+    pub nand_count: Result<usize, FlattenError>,
+    /// how many of each kind of primitive gate there are at every level of the map,
+    /// sorted by kind
+    pub primitive_counts: Vec<(String, usize)>,
+    /// how many of each custom gate there are at every level of the map, by the
+    /// name of its definition. these are separate from the primitives, as a
+    /// definition can have the same name as one, like `and`
+    pub custom_counts: Vec<(String, usize)>,
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    /// the most gates between each input and each output, indexed by input then output.
+    /// `None` if the output doesn't depend on the input
+    pub depths: Vec<Vec<Option<usize>>>,
+    /// every signal along the deepest path, starting at an input and ending at an output,
+    /// leaving out the ones which are just wires between gates
+    pub critical_path: Vec<String>,
+    /// the top-level connections and gates which the critical path goes through
+    pub critical_connections: HashSet<Id>,
+    pub critical_gates: HashSet<Id>,
+}
+impl Statistics {
+    pub fn critical_depth(&self) -> usize {
+        self.depths
+            .iter()
+            .flatten()
+            .flatten()
+            .max()
+            .copied()
+            .unwrap_or(0)
+    }
+}
+impl Display for Statistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.nand_count {
+            Ok(count) => writeln!(f, "{count} NAND gates when flattened")?,
+            Err(error) => writeln!(f, "can't count NAND gates, as {error}")?,
+        }
+        writeln!(f)?;
+        for (heading, counts) in [
+            ("primitive gates:", &self.primitive_counts),
+            ("custom gates:", &self.custom_counts),
+        ] {
+            if counts.is_empty() {
+                continue;
+            }
+            writeln!(f, "{heading}")?;
+            for (kind, count) in counts {
+                writeln!(f, "  {kind} {count}")?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "depth from each input to each output:")?;
+        let header = [String::new()]
+            .into_iter()
+            .chain(self.outputs.iter().cloned())
+            .collect::<Vec<_>>();
+        let rows = self
+            .inputs
+            .iter()
+            .zip(&self.depths)
+            .map(|(input, depths)| {
+                [input.clone()]
+                    .into_iter()
+                    .chain(depths.iter().map(|depth| {
+                        depth.map_or_else(|| "-".to_string(), |depth| depth.to_string())
+                    }))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let widths = (0..header.len())
+            .map(|column| {
+                rows.iter()
+                    .map(|row| row[column].len())
+                    .chain([header[column].len()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        for row in [&header].into_iter().chain(&rows) {
+            let cells = row
+                .iter()
+                .zip(&widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>();
+            writeln!(f, "  {}", cells.join(" ").trim_end())?;
+        }
+        writeln!(f)?;
+
+        if self.critical_path.is_empty() {
+            writeln!(f, "no output depends on an input")
+        } else {
+            writeln!(
+                f,
+                "critical path through {} gates:\n  {}",
+                self.critical_depth(),
+                self.critical_path.join(" -> ")
+            )
+        }
+    }
+}
+
+/// works out the depths and critical path on the compiled map, where a signal
+/// through a custom gate goes straight from its inputs to whatever is inside.
+/// every gate counts as one level, however many NAND gates it's made of or
+/// what its delay is. a feedback loop is only followed once from each input,
+/// so the depth of anything after one is how far it is without going around again
+pub fn statistics(map: &LogicGateMap) -> Statistics {
+    let mut primitive_counts = BTreeMap::new();
+    let mut custom_counts = BTreeMap::new();
+    count_gates(map, &mut primitive_counts, &mut custom_counts);
+
+    let compiled = map.compile();
+    let inputs = ports(map.inputs());
+    let outputs = ports(map.outputs());
+    let index = |point| {
+        compiled
+            .index_of(&[], point)
+            .expect("should be able to find compiled input or output!")
+    };
+    let input_indices = inputs
+        .iter()
+        .map(|id| index(ConnectionPoint::Input(*id)))
+        .collect::<Vec<_>>();
+    let output_indices = outputs
+        .iter()
+        .map(|id| index(ConnectionPoint::Output(*id)))
+        .collect::<Vec<_>>();
+
+    let graph = Graph::new(&compiled);
+    let mut depths = vec![];
+    let mut critical: Option<(usize, Vec<usize>)> = None;
+    for input in &input_indices {
+        let (distances, previous) = graph.longest_paths(*input);
+        depths.push(
+            output_indices
+                .iter()
+                .map(|output| distances[*output])
+                .collect::<Vec<_>>(),
+        );
+        for output in &output_indices {
+            let Some(depth) = distances[*output] else {
+                continue;
+            };
+            if critical.as_ref().is_some_and(|(best, _)| *best >= depth) {
+                continue;
+            }
+            let mut path = vec![*output];
+            let mut signal = *output;
+            while let Some(before) = previous[signal] {
+                path.push(before);
+                signal = before;
+            }
+            path.reverse();
+            critical = Some((depth, path));
+        }
+    }
+    let path = critical.map(|(_, path)| path).unwrap_or_default();
+
+    let steps = path
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect::<HashSet<_>>();
+    let critical_connections = map
+        .connections()
+        .filter(|(_, connection)| {
+            let start = compiled.index_of(&[], connection.start);
+            let end = compiled.index_of(&[], connection.end);
+            start
+                .zip(end)
+                .is_some_and(|(start, end)| steps.contains(&(start, end)))
+        })
+        .map(|(id, _)| id)
+        .collect();
+    // a signal inside a custom gate belongs to the custom gate at the top level
+    let critical_gates = path
+        .iter()
+        .filter_map(|index| {
+            let origin = compiled.origin(*index);
+            match origin.point {
+                _ if !origin.path.is_empty() => Some(origin.path[0]),
+                ConnectionPoint::GateOutput { gate, .. } => Some(gate),
+                _ => None,
+            }
+        })
+        .collect();
+    let critical_path = path
+        .iter()
+        .enumerate()
+        .filter(|(i, index)| *i == 0 || *i == path.len() - 1 || graph.weight(**index) > 0)
+        .map(|(_, index)| map.origin_name(compiled.origin(*index)))
+        .collect();
+
+    let names = |ids: &[Id], point: fn(Id) -> ConnectionPoint| {
+        ids.iter()
+            .map(|id| {
+                map.point_name(&point(*id))
+                    .unwrap_or_else(|| id.to_string())
+            })
+            .collect()
+    };
+    Statistics {
+        nand_count: flatten(map).map(|flattened| flattened.map.gates().count()),
+        primitive_counts: primitive_counts.into_iter().collect(),
+        custom_counts: custom_counts.into_iter().collect(),
+        inputs: names(&inputs, ConnectionPoint::Input),
+        outputs: names(&outputs, ConnectionPoint::Output),
+        depths,
+        critical_path,
+        critical_connections,
+        critical_gates,
+    }
+}
+
+fn count_gates(
+    map: &LogicGateMap,
+    primitive_counts: &mut BTreeMap<String, usize>,
+    custom_counts: &mut BTreeMap<String, usize>,
+) {
+    for gate in map.gates() {
+        let kind = match map.gate_by_id(gate) {
+            LogicGate::Nand { .. } => "nand",
+            LogicGate::Primitive { kind, .. } => match kind {
+                PrimitiveKind::And => "and",
+                PrimitiveKind::Or => "or",
+                PrimitiveKind::Not => "not",
+                PrimitiveKind::Xor => "xor",
+                PrimitiveKind::Xnor => "xnor",
+                PrimitiveKind::Nor => "nor",
+                PrimitiveKind::Buffer => "buffer",
+            },
+            LogicGate::TriState { .. } => "tri_state",
+            LogicGate::Clock { .. } => "clock",
+            LogicGate::Rom { .. } => "rom",
+            LogicGate::Ram { .. } => "ram",
+            LogicGate::Custom { .. } => {
+                let inner = map
+                    .custom_gate(gate)
+                    .expect("should have an instance of every custom gate!");
+                count_gates(inner, primitive_counts, custom_counts);
+                let name = inner.name().unwrap_or("unnamed");
+                *custom_counts.entry(name.to_string()).or_default() += 1;
+                continue;
+            }
+        };
+        *primitive_counts.entry(kind.to_string()).or_default() += 1;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    New,
+    Open,
+    Done,
+}
+
+/// the compiled map as a graph from each signal to the signals it drives
+struct Graph {
+    /// how many levels each signal's driver adds
+    weights: Vec<usize>,
+    fanout: Vec<Vec<usize>>,
+}
+impl Graph {
+    fn new(compiled: &CompiledMap) -> Self {
+        let count = compiled.signal_count();
+        let mut weights = vec![0; count];
+        let mut fanout = vec![vec![]; count];
+        for (index, weight) in weights.iter_mut().enumerate() {
+            let Some((operation, sources)) = compiled.driver(index) else {
+                continue;
+            };
+            *weight = match operation {
+                Operation::Resolve => 0,
+                _ => 1,
+            };
+            for source in sources {
+                fanout[*source].push(index);
+            }
+        }
+        Self { weights, fanout }
+    }
+
+    fn weight(&self, index: usize) -> usize {
+        self.weights[index]
+    }
+
+    /// the depth of every signal reachable from `start`,
+    /// and the signal before it on the deepest path there
+    fn longest_paths(&self, start: usize) -> (Vec<Option<usize>>, Vec<Option<usize>>) {
+        // a depth-first search without recursing, dropping every edge back to a
+        // signal which is still being searched, which leaves a graph without loops
+        let mut visits = vec![Visit::New; self.weights.len()];
+        let mut edges = vec![vec![]; self.weights.len()];
+        let mut order = vec![];
+        visits[start] = Visit::Open;
+        let mut stack = vec![(start, 0)];
+        while let Some((signal, next)) = stack.last_mut() {
+            let signal = *signal;
+            let Some(target) = self.fanout[signal].get(*next).copied() else {
+                visits[signal] = Visit::Done;
+                order.push(signal);
+                stack.pop();
+                continue;
+            };
+            *next += 1;
+            match visits[target] {
+                Visit::New => {
+                    visits[target] = Visit::Open;
+                    edges[signal].push(target);
+                    stack.push((target, 0));
+                }
+                Visit::Open => {}
+                Visit::Done => edges[signal].push(target),
+            }
+        }
+
+        let mut distances = vec![None; self.weights.len()];
+        let mut previous = vec![None; self.weights.len()];
+        distances[start] = Some(0);
+        // each signal comes before everything it drives
+        for signal in order.iter().rev() {
+            let Some(distance) = distances[*signal] else {
+                continue;
+            };
+            for target in &edges[*signal] {
+                let new = distance + self.weights[*target];
+                if distances[*target].is_none_or(|old| old < new) {
+                    distances[*target] = Some(new);
+                    previous[*target] = Some(*signal);
+                }
+            }
+        }
+        (distances, previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse::tests::parse_gate;
+
+    #[test]
+    fn counts_primitive_and_custom_gates_separately() {
+        let text = include_str!("../gates.dat").to_string()
+            + "
+define_gate both
+inputs a b
+outputs x y
+ands primitive
+custom_gates custom = and
+connections a => primitive in 0, b => primitive in 1, primitive out 0 => x
+connections a => custom in 0, b => custom in 1, custom out 0 => y
+";
+        let map = parse_gate(&text, "both");
+        let statistics = map.statistics();
+        let counts = |counts: &[(&str, usize)]| {
+            counts
+                .iter()
+                .map(|(kind, count)| (kind.to_string(), *count))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            statistics.primitive_counts,
+            counts(&[("and", 1), ("nand", 2)])
+        );
+        assert_eq!(statistics.custom_counts, counts(&[("and", 1), ("not", 1)]));
+        assert_eq!(statistics.nand_count, Ok(4));
+    }
+
+    #[test]
+    fn finds_the_critical_path() {
+        let map = parse_gate(include_str!("../gates.dat"), "nor");
+        let statistics = map.statistics();
+        assert_eq!(statistics.inputs, ["a", "b"]);
+        assert_eq!(statistics.outputs, ["out"]);
+        assert_eq!(statistics.depths, [[Some(3)], [Some(3)]]);
+        assert_eq!(statistics.critical_depth(), 3);
+        assert_eq!(
+            statistics.critical_path.first().map(String::as_str),
+            Some("a")
+        );
+        assert_eq!(
+            statistics.critical_path.last().map(String::as_str),
+            Some("out")
+        );
+        let not_a = map
+            .gate_by_name("not_a")
+            .expect("should have a gate called not_a!");
+        assert!(statistics.critical_gates.contains(&not_a));
+    }
+}
@@ -0,0 +1,356 @@
+use std::fmt::Display;
+
+use crate::{
+    compiled::{CompiledMap, SignalOrigin},
+    logic::Logic,
+    logic_gate::ConnectionPoint,
+    logic_gate_map::LogicGateMap,
+    path::PathError,
+    truth_table::MAX_SETTLE_STEPS,
+};
+
+/// some steps to run against one of the gates from `parse_text`, read by `parse_tests`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Test {
+    /// the name of the gate and anything after it on the `test` line
+    pub name: String,
+    pub gate: String,
+    /// each with the index of the line it was on
+    pub steps: Vec<(usize, TestStep)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestStep {
+    /// gives top-level inputs these values, without stepping
+    Set(Vec<(String, Logic)>),
+    /// steps until nothing changes
+    Settle,
+    /// steps this many times, for anything with a clock
+    Tick(u64),
+    /// fails unless these signals have these values
+    Expect(Vec<(String, Logic)>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestError {
+    /// a line of a test file which couldn't be read, and why
+    Parse(usize, String),
+}
+impl Display for TestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestError::Parse(line_number, reason) => {
+                write!(f, "line {}: {reason}", line_number + 1)
+            }
+        }
+    }
+}
+
+/// why a step of a test failed
+#[derive(Debug, Clone)]
+pub enum TestFailure {
+    /// each signal with the value it was expected to have and the one it had
+    Mismatch(Vec<(String, Logic, Logic)>),
+    /// described with the names from the map being tested
+    Oscillation(String),
+    Path(PathError),
+    /// only top-level inputs can be set
+    NotAnInput(String),
+}
+impl Display for TestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TestFailure::Mismatch(mismatches) => {
+                let mismatches = mismatches
+                    .iter()
+                    .map(|(path, expected, actual)| {
+                        format!("{path} should be {expected} but is {actual}")
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "{}", mismatches.join(", "))
+            }
+            TestFailure::Oscillation(description) => write!(f, "{description}"),
+            TestFailure::Path(path_error) => write!(f, "{path_error}"),
+            TestFailure::NotAnInput(path) => write!(f, "{path} isn't an input"),
+        }
+    }
+}
+
+/// reads a test file, which has any number of tests like this one:
+///
+/// ```text
+/// version 0
+/// test sr_latch holds what was set
+/// set reset 0 set 1
+/// settle
+/// expect out 1 not_out 0
+/// set set 0
+/// tick 5
+/// expect out 1 not_out 0
+/// ```
+///
+/// the first word after `test` is the gate it's for. signals are paths like
+/// `LogicGateMap::resolve_path` takes, so `expect` can look inside custom gates,
+/// and each is followed by its value, which is `0`, `1`, `X` or `Z`
+pub fn parse_tests(text: &str) -> Result<Vec<Test>, TestError> {
+    let mut lines = text.lines().enumerate();
+    if lines.next().map(|(_, line)| line.trim()) != Some("version 0") {
+        return Err(TestError::Parse(0, "expected version 0".to_string()));
+    }
+    let mut tests: Vec<Test> = vec![];
+    for (line_number, line) in lines {
+        let error = |reason: String| TestError::Parse(line_number, reason);
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("test ") {
+            let name = name.trim();
+            let gate = name
+                .split_whitespace()
+                .next()
+                .expect("should have trimmed the name!");
+            tests.push(Test {
+                name: name.to_string(),
+                gate: gate.to_string(),
+                steps: vec![],
+            });
+            continue;
+        }
+        let Some(test) = tests.last_mut() else {
+            return Err(error(format!("{line} isn't in a test")));
+        };
+        let mut words = line.split_whitespace();
+        let step = match words.next() {
+            Some("set") => TestStep::Set(values(words).map_err(error)?),
+            Some("settle") => TestStep::Settle,
+            Some("tick") => TestStep::Tick(
+                words
+                    .next()
+                    .and_then(|ticks| ticks.parse().ok())
+                    .ok_or(error(format!("{line} should have a number of ticks")))?,
+            ),
+            Some("expect") => TestStep::Expect(values(words).map_err(error)?),
+            _ => return Err(error(format!("unrecognised line {line}"))),
+        };
+        test.steps.push((line_number, step));
+    }
+    Ok(tests)
+}
+
+/// pairs of a path and a value
+fn values<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<Vec<(String, Logic)>, String> {
+    let mut values = vec![];
+    while let Some(path) = words.next() {
+        let Some(value) = words.next() else {
+            return Err(format!("{path} should have a value"));
+        };
+        values.push((path.to_string(), value.parse()?));
+    }
+    if values.is_empty() {
+        return Err("there should be at least one path and value".to_string());
+    }
+    Ok(values)
+}
+
+/// runs every step of `test` on a compiled copy of `map`, starting from the
+/// state it's in. clocks keep running, so a clocked map should be ticked
+/// rather than settled. gives back the index of the line of the step which
+/// failed if one did
+pub fn run_test(map: &LogicGateMap, test: &Test) -> Result<(), (usize, TestFailure)> {
+    let mut compiled = map.compile();
+    for (line_number, step) in &test.steps {
+        let fail = |failure| (*line_number, failure);
+        match step {
+            TestStep::Set(values) => {
+                for (path, value) in values {
+                    let (origin, index) = find(map, &compiled, path).map_err(fail)?;
+                    if !origin.path.is_empty() || !matches!(origin.point, ConnectionPoint::Input(_))
+                    {
+                        return Err(fail(TestFailure::NotAnInput(path.clone())));
+                    }
+                    compiled.set_value(index, *value);
+                }
+            }
+            TestStep::Settle => {
+                compiled.settle(MAX_SETTLE_STEPS).map_err(|oscillation| {
+                    fail(TestFailure::Oscillation(oscillation.describe(map)))
+                })?;
+            }
+            TestStep::Tick(ticks) => {
+                for _ in 0..*ticks {
+                    compiled.step();
+                }
+            }
+            TestStep::Expect(values) => {
+                let mut mismatches = vec![];
+                for (path, expected) in values {
+                    let (_, index) = find(map, &compiled, path).map_err(fail)?;
+                    let actual = compiled.value(index);
+                    if actual != *expected {
+                        mismatches.push((path.clone(), *expected, actual));
+                    }
+                }
+                if !mismatches.is_empty() {
+                    return Err(fail(TestFailure::Mismatch(mismatches)));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn find(
+    map: &LogicGateMap,
+    compiled: &CompiledMap,
+    path: &str,
+) -> Result<(SignalOrigin, usize), TestFailure> {
+    let origin = map.resolve_path(path).map_err(TestFailure::Path)?;
+    let index = compiled
+        .index_of(&origin.path, origin.point)
+        .expect("should be able to find compiled signal!");
+    Ok((origin, index))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    fn run(text: &str) -> Result<(), (usize, TestFailure)> {
+        let tests = parse_tests(text).expect("should be able to parse the tests!");
+        let map = parse_gate(include_str!("../gates.dat"), &tests[0].gate);
+        run_test(&map, &tests[0])
+    }
+
+    #[test]
+    fn parses_every_kind_of_step() {
+        let tests = parse_tests(
+            "version 0
+
+test sr_latch holds what was set
+set reset 0 set 1
+settle
+  expect out 1 not_out X
+tick 5
+test not
+expect out Z
+",
+        );
+        let values = |values: &[(&str, Logic)]| {
+            values
+                .iter()
+                .map(|(path, value)| (path.to_string(), *value))
+                .collect()
+        };
+        assert_eq!(
+            tests,
+            Ok(vec![
+                Test {
+                    name: "sr_latch holds what was set".to_string(),
+                    gate: "sr_latch".to_string(),
+                    steps: vec![
+                        (
+                            3,
+                            TestStep::Set(values(&[("reset", Logic::Zero), ("set", Logic::One)]))
+                        ),
+                        (4, TestStep::Settle),
+                        (
+                            5,
+                            TestStep::Expect(values(&[
+                                ("out", Logic::One),
+                                ("not_out", Logic::Unknown)
+                            ]))
+                        ),
+                        (6, TestStep::Tick(5)),
+                    ],
+                },
+                Test {
+                    name: "not".to_string(),
+                    gate: "not".to_string(),
+                    steps: vec![(
+                        8,
+                        TestStep::Expect(values(&[("out", Logic::HighImpedance)]))
+                    )],
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_lines_it_cant_read() {
+        let error = |text: &str| parse_tests(text).err();
+        let parse =
+            |line_number, reason: &str| Some(TestError::Parse(line_number, reason.to_string()));
+        assert_eq!(error("test not\n"), parse(0, "expected version 0"));
+        assert_eq!(
+            error("version 0\nsettle\n"),
+            parse(1, "settle isn't in a test")
+        );
+        assert_eq!(
+            error("version 0\ntest not\ntick\n"),
+            parse(2, "tick should have a number of ticks")
+        );
+        assert_eq!(
+            error("version 0\ntest not\nset in\n"),
+            parse(2, "in should have a value")
+        );
+        assert_eq!(
+            error("version 0\ntest not\nexpect\n"),
+            parse(2, "there should be at least one path and value")
+        );
+        assert_eq!(
+            error("version 0\ntest not\nwait 5\n"),
+            parse(2, "unrecognised line wait 5")
+        );
+        assert!(error("version 0\ntest not\nset in 2\n").is_some());
+    }
+
+    #[test]
+    fn the_example_tests_pass() {
+        let tests = parse_tests(include_str!("../gates.tests"))
+            .expect("should be able to parse the tests!");
+        assert_eq!(tests.len(), 6);
+        for test in &tests {
+            let map = parse_gate(include_str!("../gates.dat"), &test.gate);
+            assert!(run_test(&map, test).is_ok(), "{} should pass!", test.name);
+        }
+    }
+
+    #[test]
+    fn reports_the_step_which_failed() {
+        let failure = run("version 0
+test and
+set a 1 b 1
+settle
+expect out 1
+expect out 0 nand.out0 0 not.out0 0
+");
+        let Err((5, TestFailure::Mismatch(mismatches))) = failure else {
+            panic!("should fail on the second expect!");
+        };
+        assert_eq!(
+            mismatches,
+            [
+                ("out".to_string(), Logic::Zero, Logic::One),
+                ("not.out0".to_string(), Logic::Zero, Logic::One),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_inputs_can_be_set() {
+        assert!(matches!(
+            run("version 0\ntest and\nset out 1\n"),
+            Err((2, TestFailure::NotAnInput(path))) if path == "out"
+        ));
+        assert!(matches!(
+            run("version 0\ntest and\nset nand.in0 1\n"),
+            Err((2, TestFailure::NotAnInput(_)))
+        ));
+        assert!(matches!(
+            run("version 0\ntest and\nexpect missing 1\n"),
+            Err((2, TestFailure::Path(_)))
+        ));
+    }
+}
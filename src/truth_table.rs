@@ -0,0 +1,285 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::{
+    compiled::{CompiledMap, Oscillation},
+    id::Id,
+    logic::Logic,
+    logic_gate::ConnectionPoint,
+    logic_gate_map::LogicGateMap,
+};
+
+/// the most inputs a truth table can be made for, as it has a row
+/// for every combination of them
+pub const MAX_INPUTS: usize = 16;
+/// how long each row gets to settle. this is a lot more than the GUI uses,
+/// as deep custom gates take many steps with the synchronous scheduler
+pub const MAX_SETTLE_STEPS: usize = 10_000;
+
+#[derive(Debug, Clone)]
+pub struct TruthTable {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub rows: Vec<TruthTableRow>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TruthTableRow {
+    pub inputs: Vec<Logic>,
+    /// `Err` if the map never settled with these inputs
+    pub outputs: Result<Vec<Logic>, Oscillation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TruthTableFormat {
+    Text,
+    Csv,
+    Markdown,
+}
+impl FromStr for TruthTableFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(TruthTableFormat::Text),
+            "csv" => Ok(TruthTableFormat::Csv),
+            "markdown" => Ok(TruthTableFormat::Markdown),
+            _ => Err(format!("unknown truth table format {s}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TooManyInputs(pub usize);
+impl Display for TooManyInputs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} inputs is more than the {MAX_INPUTS} a truth table can be made for",
+            self.0
+        )
+    }
+}
+
+/// a compiled map with its top-level inputs and outputs in id order,
+/// which is settled from the same starting state for every set of inputs
+struct Evaluator {
+    compiled: CompiledMap,
+    inputs: Vec<usize>,
+    outputs: Vec<usize>,
+}
+impl Evaluator {
+    fn new(map: &LogicGateMap, inputs: &[Id], outputs: &[Id]) -> Self {
+        let mut compiled = map.compile();
+        compiled.set_clocks_paused(true);
+        let index = |point| {
+            compiled
+                .index_of(&[], point)
+                .expect("should be able to find compiled input or output!")
+        };
+        let inputs = inputs
+            .iter()
+            .map(|id| index(ConnectionPoint::Input(*id)))
+            .collect();
+        let outputs = outputs
+            .iter()
+            .map(|id| index(ConnectionPoint::Output(*id)))
+            .collect();
+        Self {
+            compiled,
+            inputs,
+            outputs,
+        }
+    }
+
+    fn evaluate(&self, values: &[Logic]) -> Result<Vec<Logic>, Oscillation> {
+        let mut compiled = self.compiled.clone();
+        for (index, value) in self.inputs.iter().zip(values) {
+            compiled.set_value(*index, *value);
+        }
+        compiled.settle(MAX_SETTLE_STEPS)?;
+        Ok(self
+            .outputs
+            .iter()
+            .map(|index| compiled.value(*index))
+            .collect())
+    }
+}
+
+/// the map's top-level inputs or outputs in id order, which is the order they were declared in
+pub fn ports(ids: impl Iterator<Item = (Id, Logic)>) -> Vec<Id> {
+    let mut ids = ids.map(|(id, _)| id).collect::<Vec<_>>();
+    ids.sort();
+    ids
+}
+
+/// settles the map with one set of values for its inputs, like a single row
+/// of its truth table, giving the values of its outputs
+pub fn evaluate(map: &LogicGateMap, values: &[Logic]) -> Result<Vec<Logic>, Oscillation> {
+    Evaluator::new(map, &ports(map.inputs()), &ports(map.outputs())).evaluate(values)
+}
+
+/// every row starts from the map's current state, so a gate with memory
+/// gives the outputs it would have if only that row's inputs were applied.
+/// clocks are paused, and the first input is the most significant bit of the row number
+pub fn truth_table(map: &LogicGateMap) -> Result<TruthTable, TooManyInputs> {
+    let inputs = ports(map.inputs());
+    let outputs = ports(map.outputs());
+    if inputs.len() > MAX_INPUTS {
+        return Err(TooManyInputs(inputs.len()));
+    }
+
+    let evaluator = Evaluator::new(map, &inputs, &outputs);
+    let rows = (0..1usize << inputs.len())
+        .map(|row| {
+            let values = (0..inputs.len())
+                .map(|i| match (row >> (inputs.len() - 1 - i)) & 1 {
+                    0 => Logic::Zero,
+                    _ => Logic::One,
+                })
+                .collect::<Vec<_>>();
+            TruthTableRow {
+                outputs: evaluator.evaluate(&values),
+                inputs: values,
+            }
+        })
+        .collect();
+
+    let names = |ids: &[Id], point: fn(Id) -> ConnectionPoint| {
+        ids.iter()
+            .map(|id| {
+                map.point_name(&point(*id))
+                    .unwrap_or_else(|| id.to_string())
+            })
+            .collect()
+    };
+    Ok(TruthTable {
+        inputs: names(&inputs, ConnectionPoint::Input),
+        outputs: names(&outputs, ConnectionPoint::Output),
+        rows,
+    })
+}
+
+impl TruthTable {
+    /// the text of every cell in a row, inputs first.
+    /// the outputs of a row which never settled are all `oscillating`
+    pub fn cells(&self, row: &TruthTableRow) -> Vec<String> {
+        let outputs = match &row.outputs {
+            Ok(outputs) => outputs.iter().map(|value| value.to_string()).collect(),
+            Err(_) => vec!["oscillating".to_string(); self.outputs.len()],
+        };
+        row.inputs
+            .iter()
+            .map(|value| value.to_string())
+            .chain(outputs)
+            .collect()
+    }
+
+    pub fn format(&self, format: TruthTableFormat) -> String {
+        let header = self
+            .inputs
+            .iter()
+            .chain(&self.outputs)
+            .cloned()
+            .collect::<Vec<_>>();
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| self.cells(row))
+            .collect::<Vec<_>>();
+        let mut result = String::new();
+        match format {
+            TruthTableFormat::Text => {
+                let widths = (0..header.len())
+                    .map(|column| {
+                        rows.iter()
+                            .map(|row| row[column].len())
+                            .chain([header[column].len()])
+                            .max()
+                            .unwrap_or(0)
+                    })
+                    .collect::<Vec<_>>();
+                for row in [&header].into_iter().chain(&rows) {
+                    let cells = row
+                        .iter()
+                        .zip(&widths)
+                        .map(|(cell, width)| format!("{cell:width$}"))
+                        .collect::<Vec<_>>();
+                    let (inputs, outputs) = cells.split_at(self.inputs.len());
+                    let line = format!("{} | {}", inputs.join(" "), outputs.join(" "));
+                    result.push_str(line.trim_end());
+                    result.push('\n');
+                }
+            }
+            TruthTableFormat::Csv => {
+                for row in [&header].into_iter().chain(&rows) {
+                    let cells = row.iter().map(|cell| csv_field(cell)).collect::<Vec<_>>();
+                    result.push_str(&cells.join(","));
+                    result.push('\n');
+                }
+            }
+            TruthTableFormat::Markdown => {
+                let separator = header.iter().map(|_| "---".to_string()).collect();
+                for row in [&header, &separator].into_iter().chain(&rows) {
+                    let cells = row
+                        .iter()
+                        .map(|cell| cell.replace('|', "\\|"))
+                        .collect::<Vec<_>>();
+                    result.push_str(&format!("| {} |\n", cells.join(" | ")));
+                }
+            }
+        }
+        result
+    }
+}
+
+fn csv_field(cell: &str) -> String {
+    if cell.contains([',', '"', '\n']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    #[test]
+    fn csv_has_a_header_and_a_row_for_every_input() {
+        let map = parse_gate(include_str!("../gates.dat"), "not");
+        let table = truth_table(&map).expect("should be able to make a truth table!");
+        assert_eq!(table.format(TruthTableFormat::Csv), "in,out\n0,1\n1,0\n");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn markdown_counts_up_from_the_first_input() {
+        let map = parse_gate(include_str!("../gates.dat"), "and");
+        let table = truth_table(&map).expect("should be able to make a truth table!");
+        assert_eq!(
+            table.format(TruthTableFormat::Markdown),
+            "| a | b | out |
+| --- | --- | --- |
+| 0 | 0 | 0 |
+| 0 | 1 | 0 |
+| 1 | 0 | 0 |
+| 1 | 1 | 1 |
+"
+        );
+    }
+
+    #[test]
+    fn too_many_inputs_are_refused() {
+        let wide = |width: usize| {
+            let text = format!("version 0\ndefine_gate wide\ninputs a:{width}\noutputs out\n");
+            parse_gate(&text, "wide")
+        };
+        assert_eq!(
+            truth_table(&wide(MAX_INPUTS + 1)).err(),
+            Some(TooManyInputs(MAX_INPUTS + 1))
+        );
+        assert!(truth_table(&wide(2)).is_ok());
+    }
+}
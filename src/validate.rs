@@ -0,0 +1,251 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    compiled::SignalOrigin,
+    feedback::{FeedbackLoop, feedback_loops},
+    id::Id,
+    logic_gate::{Connection, ConnectionPoint, LogicGate},
+    logic_gate_map::LogicGateMap,
+};
+
+/// a problem with how a map is wired up, found by `LogicGateMap::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// more than one connection drives the same point, which is only
+    /// expected when every one of them comes from a tri-state buffer
+    MultipleDrivers {
+        point: ConnectionPoint,
+        connections: Vec<Id>,
+    },
+    /// a gate input or map output which nothing drives, so it never changes
+    Undriven(ConnectionPoint),
+    /// a connection ending at one of the map's own inputs,
+    /// which should only be set from outside the map
+    DrivesInput { connection: Id, input: Id },
+    /// a gate output which isn't connected to anything
+    UnusedOutput { gate: Id, output: Id },
+    /// a connection to something which isn't in the map
+    Dangling {
+        connection: Id,
+        point: ConnectionPoint,
+    },
+    /// a loop of gates which wasn't expected, see `LogicGateMap::expects_feedback`
+    Feedback(FeedbackLoop),
+}
+impl Diagnostic {
+    /// a map with any errors can't be simulated, and everything else is a warning
+    pub fn is_error(&self) -> bool {
+        matches!(self, Diagnostic::Dangling { .. })
+    }
+
+    /// names everything by its path in `map`, which is the map that was validated.
+    /// anything which isn't in the map, like the missing end of a dangling
+    /// connection, is given by its id
+    pub fn describe(&self, map: &LogicGateMap) -> String {
+        let name = |point: &ConnectionPoint| {
+            let origin = SignalOrigin {
+                path: vec![],
+                point: *point,
+            };
+            if map.has_connection_point(point) {
+                map.origin_name(&origin)
+            } else {
+                origin.to_string()
+            }
+        };
+        let connection = |id: &Id| {
+            let found = map.connections().find(|(connection, _)| connection == id);
+            match found {
+                Some((_, connection)) => {
+                    format!("{} => {}", name(&connection.start), name(&connection.end))
+                }
+                None => format!("connection {id}"),
+            }
+        };
+        match self {
+            Diagnostic::MultipleDrivers { point, connections } => {
+                let connections = connections.iter().map(connection).collect::<Vec<_>>();
+                format!("{} is driven by {}", name(point), connections.join(", "))
+            }
+            Diagnostic::Undriven(point) => format!("{} isn't driven by anything", name(point)),
+            Diagnostic::DrivesInput { connection: id, .. } => {
+                format!("{} drives one of the map's own inputs", connection(id))
+            }
+            Diagnostic::UnusedOutput { gate, output } => {
+                let point = ConnectionPoint::GateOutput {
+                    gate: *gate,
+                    output: *output,
+                };
+                format!("{} isn't connected to anything", name(&point))
+            }
+            Diagnostic::Dangling {
+                connection: id,
+                point,
+            } => format!("{} goes to missing {}", connection(id), name(point)),
+            Diagnostic::Feedback(feedback) => feedback.describe(map),
+        }
+    }
+}
+
+/// only looks at the map's own level. the maps inside its custom gates
+/// are checked when their definitions are validated. a feedback loop which
+/// stores a value is fine in a map that expects feedback, but one which may
+/// oscillate is always reported
+pub fn validate(map: &LogicGateMap) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut connections = map.connections().collect::<Vec<_>>();
+    connections.sort_by_key(|(id, _)| *id);
+
+    let mut drivers: HashMap<ConnectionPoint, Vec<Id>> = HashMap::new();
+    let mut read = HashSet::new();
+    for (id, Connection { start, end }) in &connections {
+        let missing = [start, end]
+            .into_iter()
+            .filter(|point| !map.has_connection_point(point))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            diagnostics.extend(missing.into_iter().map(|point| Diagnostic::Dangling {
+                connection: *id,
+                point: *point,
+            }));
+            continue;
+        }
+        if let ConnectionPoint::Input(input) = end {
+            diagnostics.push(Diagnostic::DrivesInput {
+                connection: *id,
+                input: *input,
+            });
+        }
+        drivers.entry(*end).or_default().push(*id);
+        read.insert(*start);
+    }
+
+    let starts = connections
+        .iter()
+        .map(|(id, connection)| (*id, connection.start))
+        .collect::<HashMap<_, _>>();
+    let mut reported = HashSet::new();
+    for (_, Connection { end, .. }) in &connections {
+        let Some(ids) = drivers.get(end).filter(|ids| ids.len() > 1) else {
+            continue;
+        };
+        let tri_state = |id: &Id| {
+            matches!(
+                starts[id],
+                ConnectionPoint::GateOutput { gate, .. }
+                    if matches!(map.gate_by_id(gate), LogicGate::TriState { .. })
+            )
+        };
+        if reported.insert(*end) && !ids.iter().all(tri_state) {
+            diagnostics.push(Diagnostic::MultipleDrivers {
+                point: *end,
+                connections: ids.clone(),
+            });
+        }
+    }
+
+    let mut gates = map.gates().collect::<Vec<_>>();
+    gates.sort();
+    for gate in &gates {
+        for input in map.gate_by_id(*gate).inputs() {
+            let point = ConnectionPoint::GateInput { gate: *gate, input };
+            if !drivers.contains_key(&point) {
+                diagnostics.push(Diagnostic::Undriven(point));
+            }
+        }
+    }
+    let mut outputs = map.outputs().map(|(id, _)| id).collect::<Vec<_>>();
+    outputs.sort();
+    for output in outputs {
+        let point = ConnectionPoint::Output(output);
+        if !drivers.contains_key(&point) {
+            diagnostics.push(Diagnostic::Undriven(point));
+        }
+    }
+    for gate in &gates {
+        for output in map.gate_by_id(*gate).outputs() {
+            let point = ConnectionPoint::GateOutput {
+                gate: *gate,
+                output,
+            };
+            if !read.contains(&point) {
+                diagnostics.push(Diagnostic::UnusedOutput {
+                    gate: *gate,
+                    output,
+                });
+            }
+        }
+    }
+    diagnostics.extend(
+        feedback_loops(map)
+            .into_iter()
+            .filter(|feedback| !(feedback.storage && map.expects_feedback()))
+            .map(Diagnostic::Feedback),
+    );
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    fn describe(map: &LogicGateMap) -> Vec<(bool, String)> {
+        map.validate()
+            .iter()
+            .map(|diagnostic| (diagnostic.is_error(), diagnostic.describe(map)))
+            .collect()
+    }
+
+    #[test]
+    fn diagnostics_name_what_they_are_about() {
+        let map = parse_gate(
+            "version 0
+define_gate broken
+inputs a
+outputs out spare
+nots n1 n2
+connections a => n1 in 0, n1 out 0 => out, a => out, out => a
+",
+            "broken",
+        );
+        let warning = |description: &str| (false, description.to_string());
+        assert_eq!(
+            describe(&map),
+            [
+                warning("out => a drives one of the map's own inputs"),
+                warning("out is driven by n1.out0 => out, a => out"),
+                warning("n2.in0 isn't driven by anything"),
+                warning("spare isn't driven by anything"),
+                warning("n2.out0 isn't connected to anything"),
+                warning("feedback loop through gates n1 which may oscillate"),
+            ]
+        );
+    }
+
+    #[test]
+    fn dangling_connections_are_errors() {
+        let mut map = parse_gate(include_str!("../gates.dat"), "not");
+        let n = map.gate_by_name("n").expect("should have a gate called n!");
+        let input = map.resolve_path("in").expect("should have an input!").point;
+        let missing_input = map.gate_by_id(n).inputs()[0];
+        let missing = ConnectionPoint::GateInput {
+            gate: n,
+            input: missing_input,
+        };
+        map.remove_gate(n)
+            .expect("should be able to remove the gate!");
+        let connection = map.create_connection((input, missing));
+        let diagnostics = map.validate();
+        let dangling = Diagnostic::Dangling {
+            connection,
+            point: missing,
+        };
+        assert!(diagnostics.contains(&dangling));
+        assert!(dangling.is_error());
+        assert_eq!(
+            dangling.describe(&map),
+            format!("in => {n} in {missing_input} goes to missing {n} in {missing_input}")
+        );
+    }
+}
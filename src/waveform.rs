@@ -0,0 +1,309 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    ops::Range,
+};
+
+use crate::{compiled::CompiledMap, logic::Logic, logic_gate_map::LogicGateMap};
+
+/// a signal being recorded, named by a path like `LogicGateMap::resolve_path` takes
+#[derive(Debug, Clone)]
+struct RecordedSignal {
+    path: String,
+    /// where the signal is in the compiled map the recorder is attached to
+    index: usize,
+}
+
+/// the values of some signals after every step of a `CompiledMap` it's attached to,
+/// keeping only the most recent `capacity` steps. ticks are counted by the
+/// recorder, so they carry on across the map being compiled again.
+/// signals are added with `CompiledMap::record_signal`
+#[derive(Debug, Clone)]
+pub struct WaveformRecorder {
+    signals: Vec<RecordedSignal>,
+    capacity: usize,
+    /// the values of every signal at each tick, oldest first
+    samples: VecDeque<Vec<Logic>>,
+    /// the tick of the sample after the newest one
+    next_tick: u64,
+}
+impl WaveformRecorder {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            signals: vec![],
+            capacity,
+            samples: VecDeque::new(),
+            next_tick: 0,
+        }
+    }
+
+    /// the paths of the recorded signals, in the order they were added
+    pub fn signals(&self) -> impl Iterator<Item = &str> {
+        self.signals.iter().map(|signal| signal.path.as_str())
+    }
+
+    /// starts recording the signal at `index` in the compiled map this is
+    /// attached to, which clears what's been recorded so every sample has
+    /// a value for every signal
+    pub fn add_signal(&mut self, path: &str, index: usize) {
+        if self.signals.iter().any(|signal| signal.index == index) {
+            return;
+        }
+        self.signals.push(RecordedSignal {
+            path: path.to_string(),
+            index,
+        });
+        self.samples.clear();
+    }
+
+    /// also clears what's been recorded
+    pub fn remove_signal(&mut self, path: &str) {
+        self.signals.retain(|signal| signal.path != path);
+        self.samples.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// finds every signal again in `compiled`, which is made from `map`. anything
+    /// which isn't there any more, like a nested signal after flattening, stops
+    /// being recorded, and their paths are given back
+    pub fn attach(&mut self, map: &LogicGateMap, compiled: &CompiledMap) -> Vec<String> {
+        let mut removed = vec![];
+        self.signals.retain_mut(|signal| {
+            let index = map
+                .resolve_path(&signal.path)
+                .ok()
+                .and_then(|origin| compiled.index_of(&origin.path, origin.point));
+            match index {
+                Some(index) => signal.index = index,
+                None => removed.push(signal.path.clone()),
+            }
+            index.is_some()
+        });
+        if !removed.is_empty() {
+            self.samples.clear();
+        }
+        removed
+    }
+
+    /// called by `CompiledMap::step` with every signal after the step
+    pub fn record(&mut self, values: &[Logic]) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(
+            self.signals
+                .iter()
+                .map(|signal| values[signal.index])
+                .collect(),
+        );
+        self.next_tick += 1;
+    }
+
+    /// every sample still in the buffer with its tick, oldest first,
+    /// with a value for each signal in the order of `signals`
+    pub fn samples(&self) -> impl Iterator<Item = (u64, &[Logic])> {
+        let first_tick = self.ticks().start;
+        self.samples
+            .iter()
+            .enumerate()
+            .map(move |(i, values)| (first_tick + i as u64, values.as_slice()))
+    }
+
+    /// the ticks which are still in the buffer
+    pub fn ticks(&self) -> Range<u64> {
+        self.next_tick - self.samples.len() as u64..self.next_tick
+    }
+
+    /// the value of every signal at `tick`, if it's still in the buffer
+    pub fn sample(&self, tick: u64) -> Option<&[Logic]> {
+        let first_tick = self.ticks().start;
+        let index = usize::try_from(tick.checked_sub(first_tick)?).ok()?;
+        self.samples.get(index).map(|values| values.as_slice())
+    }
+
+    /// a Value Change Dump of everything in the buffer, with one time unit
+    /// for each tick. each custom gate is a scope, so the signals are nested
+    /// the same way as in the map, with `top` as the name of the outermost one
+    pub fn to_vcd(&self, top: &str) -> String {
+        let mut scopes = Scope::default();
+        for (i, signal) in self.signals.iter().enumerate() {
+            let mut segments = signal.path.split('/').collect::<Vec<_>>();
+            let name = segments
+                .pop()
+                .expect("split should give at least one segment");
+            let scope = segments.into_iter().fold(&mut scopes, |scope, segment| {
+                scope.children.entry(segment.to_string()).or_default()
+            });
+            scope.signals.push((name.to_string(), vcd_identifier(i)));
+        }
+
+        let mut result = String::new();
+        result.push_str("$version logic gate simulator $end\n");
+        result.push_str("$comment one time unit is one tick $end\n");
+        result.push_str("$timescale 1ns $end\n");
+        scopes.write(top, &mut result);
+        result.push_str("$enddefinitions $end\n");
+
+        let mut previous: Option<&[Logic]> = None;
+        for (tick, values) in self.samples() {
+            let changes = values
+                .iter()
+                .enumerate()
+                .filter(|(i, value)| previous.is_none_or(|previous| previous[*i] != **value))
+                .map(|(i, value)| format!("{}{}\n", vcd_value(*value), vcd_identifier(i)))
+                .collect::<String>();
+            if previous.is_none() {
+                result.push_str(&format!("#{tick}\n$dumpvars\n{changes}$end\n"));
+            } else if !changes.is_empty() {
+                result.push_str(&format!("#{tick}\n{changes}"));
+            }
+            previous = Some(values);
+        }
+        result
+    }
+}
+
+/// the signals directly in a scope and the scopes inside it, by name
+#[derive(Debug, Default)]
+struct Scope {
+    signals: Vec<(String, String)>,
+    children: BTreeMap<String, Scope>,
+}
+impl Scope {
+    fn write(&self, name: &str, result: &mut String) {
+        result.push_str(&format!("$scope module {name} $end\n"));
+        for (signal, identifier) in &self.signals {
+            result.push_str(&format!("$var wire 1 {identifier} {signal} $end\n"));
+        }
+        for (child_name, child) in &self.children {
+            child.write(child_name, result);
+        }
+        result.push_str("$upscope $end\n");
+    }
+}
+
+/// a short code made of the printable characters from `!` to `~`
+fn vcd_identifier(mut index: usize) -> String {
+    let mut identifier = String::new();
+    loop {
+        identifier.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return identifier;
+        }
+        index -= 1;
+    }
+}
+
+fn vcd_value(value: Logic) -> char {
+    match value {
+        Logic::Zero => '0',
+        Logic::One => '1',
+        Logic::Unknown => 'x',
+        Logic::HighImpedance => 'z',
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::tests::parse_gate;
+
+    /// records `a` and `b` from the first two signals of each sample
+    fn recorder(capacity: usize, samples: &[[Logic; 2]]) -> WaveformRecorder {
+        let mut recorder = WaveformRecorder::new(capacity);
+        recorder.add_signal("a", 0);
+        recorder.add_signal("b", 1);
+        for values in samples {
+            recorder.record(values);
+        }
+        recorder
+    }
+
+    #[test]
+    fn only_the_newest_ticks_are_kept() {
+        use Logic::{One, Zero};
+        let recorder = recorder(3, &[[Zero, Zero], [One, Zero], [Zero, One], [One, One]]);
+        assert_eq!(recorder.ticks(), 1..4);
+        assert_eq!(recorder.samples().count(), 3);
+        assert_eq!(recorder.sample(0), None);
+        assert_eq!(recorder.sample(1), Some([One, Zero].as_slice()));
+        assert_eq!(recorder.sample(3), Some([One, One].as_slice()));
+        assert_eq!(recorder.sample(4), None);
+        assert_eq!(
+            recorder.samples().map(|(tick, _)| tick).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn vcd_only_has_changes_on_edges() {
+        use Logic::{One, Unknown, Zero};
+        let recorder = recorder(
+            10,
+            &[[Zero, Unknown], [One, Unknown], [One, Unknown], [One, One]],
+        );
+        assert_eq!(
+            recorder.to_vcd("top"),
+            "$version logic gate simulator $end
+$comment one time unit is one tick $end
+$timescale 1ns $end
+$scope module top $end
+$var wire 1 ! a $end
+$var wire 1 \" b $end
+$upscope $end
+$enddefinitions $end
+#0
+$dumpvars
+0!
+x\"
+$end
+#1
+1!
+#3
+1\"
+"
+        );
+    }
+
+    #[test]
+    fn vcd_scopes_nest_like_custom_gates() {
+        let map = parse_gate(include_str!("../gates.dat"), "sr_latch");
+        let mut compiled = map.compile();
+        compiled.attach_recorder(&map, WaveformRecorder::new(10));
+        for path in ["out", "nor_a/out", "nor_a/not_a/n.out0", "nor_b/out"] {
+            compiled.record_signal(&map, path).unwrap();
+        }
+        let vcd = compiled
+            .recorder()
+            .expect("should have attached a recorder!")
+            .to_vcd("sr_latch");
+        let definitions = vcd
+            .lines()
+            .skip_while(|line| !line.starts_with("$scope"))
+            .take_while(|line| !line.starts_with("$enddefinitions"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            definitions,
+            [
+                "$scope module sr_latch $end",
+                "$var wire 1 ! out $end",
+                "$scope module nor_a $end",
+                "$var wire 1 \" out $end",
+                "$scope module not_a $end",
+                "$var wire 1 # n.out0 $end",
+                "$upscope $end",
+                "$upscope $end",
+                "$scope module nor_b $end",
+                "$var wire 1 $ out $end",
+                "$upscope $end",
+                "$upscope $end",
+            ]
+        );
+    }
+}
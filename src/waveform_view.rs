@@ -0,0 +1,287 @@
+use eframe::egui::{
+    Align2, Color32, FontId, Painter, Pos2, Rect, ScrollArea, Sense, Stroke, Ui, Vec2,
+};
+
+use crate::{logic::Logic, render::logic_colour, waveform::WaveformRecorder};
+
+/// a timing diagram of what a `WaveformRecorder` has recorded, with a row for
+/// each signal. dragging or scrolling sideways moves through the ticks, holding
+/// ctrl while scrolling zooms, and clicking a row puts cursor A on the nearest
+/// edge of its signal, or cursor B with the other button, to measure between them
+#[derive(Debug, Clone)]
+pub struct WaveformView {
+    /// how wide one tick is drawn
+    pixels_per_tick: f32,
+    /// the tick at the left edge of the traces, which can be part way through one
+    start_tick: f64,
+    /// whether the newest tick is kept at the right edge as more are recorded
+    follow: bool,
+    cursors: [Option<u64>; 2],
+}
+impl WaveformView {
+    pub fn new() -> Self {
+        Self {
+            pixels_per_tick: 8.0,
+            start_tick: 0.0,
+            follow: true,
+            cursors: [None, None],
+        }
+    }
+
+    pub fn show(&mut self, recorder: &WaveformRecorder, ui: &mut Ui) {
+        let ticks = recorder.ticks();
+        let width = ui.available_width() - NAME_WIDTH;
+        ui.horizontal(|ui| {
+            if ui.button("zoom in").clicked() {
+                self.pixels_per_tick = (self.pixels_per_tick * 2.0).min(MAX_PIXELS_PER_TICK);
+            }
+            if ui.button("zoom out").clicked() {
+                self.pixels_per_tick = (self.pixels_per_tick / 2.0).max(MIN_PIXELS_PER_TICK);
+            }
+            if ui.button("fit").clicked() && !ticks.is_empty() {
+                self.pixels_per_tick = (width / (ticks.end - ticks.start) as f32)
+                    .clamp(MIN_PIXELS_PER_TICK, MAX_PIXELS_PER_TICK);
+                self.start_tick = ticks.start as f64;
+                self.follow = false;
+            }
+            ui.checkbox(&mut self.follow, "follow newest");
+            if ui.button("clear cursors").clicked() {
+                self.cursors = [None, None];
+            }
+            ui.label(self.measurement());
+        });
+
+        let signals = recorder.signals().collect::<Vec<_>>();
+        ScrollArea::vertical().show(ui, |ui| {
+            let height = RULER_HEIGHT + ROW_HEIGHT * signals.len() as f32;
+            let (response, painter) = ui.allocate_painter(
+                Vec2::new(ui.available_width(), height),
+                Sense::click_and_drag(),
+            );
+            let rect = response.rect;
+            let traces = Rect::from_min_max(
+                Pos2::new(rect.left() + NAME_WIDTH, rect.top() + RULER_HEIGHT),
+                rect.max,
+            );
+            if response.hovered() {
+                let (scroll, zoom) = ui.input(|i| (i.smooth_scroll_delta.x, i.zoom_delta()));
+                if zoom != 1.0 {
+                    // keep the tick under the pointer where it is
+                    let pointer_x = response
+                        .hover_pos()
+                        .map_or(traces.center().x, |position| position.x);
+                    let tick = self.tick_at(traces, pointer_x);
+                    self.pixels_per_tick = (self.pixels_per_tick * zoom)
+                        .clamp(MIN_PIXELS_PER_TICK, MAX_PIXELS_PER_TICK);
+                    self.start_tick =
+                        tick - f64::from((pointer_x - traces.left()) / self.pixels_per_tick);
+                    self.follow = false;
+                }
+                if scroll != 0.0 {
+                    self.start_tick -= f64::from(scroll / self.pixels_per_tick);
+                    self.follow = false;
+                }
+            }
+            if response.dragged() {
+                self.start_tick -= f64::from(response.drag_delta().x / self.pixels_per_tick);
+                self.follow = false;
+            }
+            let visible = f64::from(traces.width() / self.pixels_per_tick);
+            if self.follow {
+                self.start_tick = ticks.end as f64 - visible;
+            }
+            let latest_start = (ticks.end as f64 - visible).max(ticks.start as f64);
+            self.start_tick = self.start_tick.clamp(ticks.start as f64, latest_start);
+
+            for (cursor, clicked) in [response.clicked(), response.secondary_clicked()]
+                .into_iter()
+                .enumerate()
+            {
+                let Some(position) = response.interact_pointer_pos().filter(|_| clicked) else {
+                    continue;
+                };
+                if position.x < traces.left() || ticks.is_empty() {
+                    continue;
+                }
+                let tick = self.tick_at(traces, position.x).round().max(0.0) as u64;
+                let row = ((position.y - traces.top()) / ROW_HEIGHT).floor();
+                let edge = (row >= 0.0 && (row as usize) < signals.len())
+                    .then(|| {
+                        let within = (SNAP_PIXELS / self.pixels_per_tick).ceil() as u64;
+                        nearest_edge(recorder, row as usize, tick, within)
+                    })
+                    .flatten();
+                self.cursors[cursor] = Some(edge.unwrap_or(tick.clamp(ticks.start, ticks.end - 1)));
+            }
+
+            painter.rect_filled(rect, 0.0, Color32::from_gray(20));
+            self.draw_ruler(&painter, traces, rect.top());
+            let trace_painter = painter.with_clip_rect(traces);
+            for (row, name) in signals.iter().enumerate() {
+                let top = traces.top() + ROW_HEIGHT * row as f32;
+                painter.text(
+                    Pos2::new(rect.left() + 4.0, top + ROW_HEIGHT / 2.0),
+                    Align2::LEFT_CENTER,
+                    name,
+                    FontId::monospace(12.0),
+                    Color32::LIGHT_GRAY,
+                );
+                self.draw_trace(&trace_painter, recorder, row, traces, top);
+            }
+            for (cursor, (tick, colour)) in self.cursors.iter().zip(CURSOR_COLOURS).enumerate() {
+                let Some(tick) = tick else {
+                    continue;
+                };
+                let x = self.x_of(traces, *tick);
+                if !(traces.left()..=traces.right()).contains(&x) {
+                    continue;
+                }
+                painter.line_segment(
+                    [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+                    Stroke::new(1.5, colour),
+                );
+                painter.text(
+                    Pos2::new(x + 3.0, rect.top()),
+                    Align2::LEFT_TOP,
+                    ["A", "B"][cursor],
+                    FontId::monospace(12.0),
+                    colour,
+                );
+            }
+        });
+    }
+
+    fn measurement(&self) -> String {
+        match self.cursors {
+            [None, None] => "click a trace to place cursor A, or right click for B".to_string(),
+            [Some(a), None] => format!("A = {a}"),
+            [None, Some(b)] => format!("B = {b}"),
+            [Some(a), Some(b)] => {
+                let distance = b as i128 - a as i128;
+                format!("A = {a}, B = {b}, B - A = {distance} ticks")
+            }
+        }
+    }
+
+    /// the tick at `x`, which is before the start of a tick's sample
+    fn tick_at(&self, traces: Rect, x: f32) -> f64 {
+        self.start_tick + f64::from((x - traces.left()) / self.pixels_per_tick)
+    }
+
+    fn x_of(&self, traces: Rect, tick: u64) -> f32 {
+        traces.left() + ((tick as f64 - self.start_tick) * f64::from(self.pixels_per_tick)) as f32
+    }
+
+    /// tick numbers along the top, far enough apart not to overlap
+    fn draw_ruler(&self, painter: &Painter, traces: Rect, top: f32) {
+        let step = (0..)
+            .flat_map(|power| [1, 2, 5].map(|mantissa| mantissa * 10u64.pow(power)))
+            .find(|step| *step as f32 * self.pixels_per_tick >= LABEL_SPACING)
+            .expect("should find a step between labels!");
+        let first = (self.start_tick.max(0.0) as u64).div_ceil(step) * step;
+        let mut tick = first;
+        while self.x_of(traces, tick) <= traces.right() {
+            let x = self.x_of(traces, tick);
+            painter.line_segment(
+                [
+                    Pos2::new(x, top + RULER_HEIGHT - 4.0),
+                    Pos2::new(x, traces.bottom()),
+                ],
+                Stroke::new(1.0, Color32::from_gray(50)),
+            );
+            painter.text(
+                Pos2::new(x, top + RULER_HEIGHT - 4.0),
+                Align2::CENTER_BOTTOM,
+                tick.to_string(),
+                FontId::monospace(10.0),
+                Color32::GRAY,
+            );
+            tick += step;
+        }
+    }
+
+    /// a high line for 1 and a low one for 0, a line through the middle for
+    /// high impedance and a filled band for unknown, each in its usual colour
+    fn draw_trace(
+        &self,
+        painter: &Painter,
+        recorder: &WaveformRecorder,
+        row: usize,
+        traces: Rect,
+        top: f32,
+    ) {
+        let ticks = recorder.ticks();
+        let first = (self.start_tick.floor().max(0.0) as u64).max(ticks.start);
+        let last = ((self.tick_at(traces, traces.right()).ceil() as u64) + 1).min(ticks.end);
+        let high = top + 4.0;
+        let low = top + ROW_HEIGHT - 4.0;
+        let middle = (high + low) / 2.0;
+
+        let mut previous: Option<Logic> = None;
+        let mut tick = first;
+        while tick < last {
+            let value = recorder.sample(tick).expect("should be in the buffer!")[row];
+            // draw everything until the value changes at once
+            let mut end = tick + 1;
+            while end < last
+                && recorder.sample(end).expect("should be in the buffer!")[row] == value
+            {
+                end += 1;
+            }
+            let (start_x, end_x) = (self.x_of(traces, tick), self.x_of(traces, end));
+            let colour = logic_colour(value);
+            match value {
+                Logic::Zero | Logic::One | Logic::HighImpedance => {
+                    let y = match value {
+                        Logic::One => high,
+                        Logic::Zero => low,
+                        _ => middle,
+                    };
+                    painter.line_segment(
+                        [Pos2::new(start_x, y), Pos2::new(end_x, y)],
+                        Stroke::new(2.0, colour),
+                    );
+                }
+                Logic::Unknown => {
+                    painter.rect_filled(
+                        Rect::from_min_max(Pos2::new(start_x, high), Pos2::new(end_x, low)),
+                        0.0,
+                        colour.gamma_multiply(0.5),
+                    );
+                }
+            }
+            if previous.is_some() {
+                painter.line_segment(
+                    [Pos2::new(start_x, high), Pos2::new(start_x, low)],
+                    Stroke::new(1.0, Color32::LIGHT_GRAY),
+                );
+            }
+            previous = Some(value);
+            tick = end;
+        }
+    }
+}
+
+/// the tick closest to `tick`, and no more than `within` away, where the
+/// signal in `row` is different to the tick before
+fn nearest_edge(recorder: &WaveformRecorder, row: usize, tick: u64, within: u64) -> Option<u64> {
+    let value = |tick: u64| recorder.sample(tick).map(|values| values[row]);
+    (0..=within)
+        .flat_map(|distance| [tick.checked_sub(distance), tick.checked_add(distance)])
+        .flatten()
+        .find(|tick| {
+            value(*tick)
+                .zip(tick.checked_sub(1).and_then(value))
+                .is_some_and(|(now, before)| now != before)
+        })
+}
+
+const NAME_WIDTH: f32 = 160.0;
+const RULER_HEIGHT: f32 = 18.0;
+const ROW_HEIGHT: f32 = 24.0;
+const MIN_PIXELS_PER_TICK: f32 = 0.01;
+const MAX_PIXELS_PER_TICK: f32 = 100.0;
+/// how close a click has to be to an edge for a cursor to go on it
+const SNAP_PIXELS: f32 = 8.0;
+const LABEL_SPACING: f32 = 60.0;
+const CURSOR_COLOURS: [Color32; 2] = [Color32::YELLOW, Color32::LIGHT_BLUE];